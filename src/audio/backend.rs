@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use rodio::{OutputStream, OutputStreamHandle, Sink};
+
+use crate::audio::mixer::SoundClip;
+use crate::state::constants::audio::MAX_VOICES;
+
+/// Destination for sounds played by the `Mixer`.
+pub trait AudioBackend {
+    fn play(&mut self, sound: usize, clip: &SoundClip, volume: f32, duration: Duration);
+}
+
+/// Plays sounds on the default output device, using one `Sink` per voice so that
+/// several sounds can overlap (e.g. footsteps during a kick).
+pub struct RodioBackend {
+    _stream: OutputStream, // Must be kept alive for as long as sounds should be heard
+    voices: Vec<Sink>,
+    next_voice: usize, // Voice to steal once every voice is busy
+}
+
+impl RodioBackend {
+    pub fn try_default() -> Result<Self, String> {
+        let (stream, handle): (OutputStream, OutputStreamHandle) = OutputStream::try_default().map_err(|e| e.to_string())?;
+
+        let voices = (0..MAX_VOICES)
+            .map(|_| Sink::try_new(&handle).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<Sink>, String>>()?;
+
+        Ok(Self { _stream: stream, voices, next_voice: 0 })
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn play(&mut self, _sound: usize, clip: &SoundClip, volume: f32, duration: Duration) {
+        // Prefer an idle voice, otherwise cut off the voice which has played the longest
+        let index = match self.voices.iter().position(|voice| voice.empty()) {
+            Some(index) => index,
+            None => {
+                let index = self.next_voice;
+                self.next_voice = (self.next_voice + 1) % self.voices.len();
+                self.voices[index].skip_one();
+                index
+            }
+        };
+
+        let voice = &self.voices[index];
+        voice.set_volume(volume);
+        voice.append(clip.source(duration));
+        voice.play();
+    }
+}
+
+/// Silent backend which only records which sounds were requested.
+///
+/// Used when no output device is available and for checking game logic without audio hardware.
+#[derive(Default)]
+pub struct NullBackend {
+    played: Rc<RefCell<Vec<usize>>>,
+}
+
+impl NullBackend {
+    /// Returns a handle to the list of played sound indices which stays valid after the
+    /// backend has been moved into a `Mixer`.
+    pub fn played(&self) -> Rc<RefCell<Vec<usize>>> {
+        Rc::clone(&self.played)
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, sound: usize, _clip: &SoundClip, _volume: f32, _duration: Duration) {
+        self.played.borrow_mut().push(sound);
+    }
+}
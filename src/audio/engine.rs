@@ -1,19 +1,11 @@
-use rodio::Sink;
+use std::fs::File;
+use std::io::Read;
+
+use crate::audio::mixer::Mixer;
 use crate::state::constants::audio::{WALK_SOUND_1, WALK_SOUND_2, WALK_SOUND_3, WALK_SOUND_4};
 use crate::state::structs::GameState;
 
-pub fn append_source_source(game_state: &&mut GameState, sink: &mut Sink, sample: usize, duration: u64) {
-    // let file = &game_state.sounds[sample];
-    // let cursor = Cursor::new(file.clone());
-    //
-    // let source = rodio::Decoder::new(BufReader::new(cursor))
-    //     .unwrap()
-    //     .take_duration(std::time::Duration::from_millis(duration));
-    //
-    // sink.append(source);
-}
-
-pub fn play_footstep_sound(game_state: &mut GameState, sink: &mut Sink) {
+pub fn play_footstep_sound(game_state: &mut GameState, mixer: &mut Mixer) {
     if game_state.footstep_index == 4 { game_state.footstep_index = 0; } else { game_state.footstep_index += 1; }
 
     let sound_index = match game_state.footstep_index {
@@ -23,5 +15,29 @@ pub fn play_footstep_sound(game_state: &mut GameState, sink: &mut Sink) {
         _ => WALK_SOUND_4,
     };
 
-    append_source_source(&game_state, sink, sound_index, 200);
-}
\ No newline at end of file
+    mixer.play(sound_index, 200);
+}
+
+fn load_sound(path: &str) -> Vec<u8> {
+    let mut file = File::open(path).expect("Failed to open sounds file");
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer).expect("Failed to read file");
+    buffer
+}
+
+/// Reads every sound file, in the order of the sound indices in `constants::audio`.
+pub fn load_sounds() -> Vec<Vec<u8>> {
+    vec![
+        load_sound("assets/sounds/walk_1.wav"),
+        load_sound("assets/sounds/walk_2.wav"),
+        load_sound("assets/sounds/walk_3.wav"),
+        load_sound("assets/sounds/walk_4.wav"),
+        load_sound("assets/sounds/jump.wav"),
+        load_sound("assets/sounds/fall_mild.wav"),
+        load_sound("assets/sounds/fall_heavy.wav"),
+        load_sound("assets/sounds/down.wav"),
+        load_sound("assets/sounds/explosion.wav"),
+        load_sound("assets/sounds/kick.wav"),
+        load_sound("assets/sounds/kick_box.wav"),
+    ]
+}
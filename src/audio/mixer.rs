@@ -0,0 +1,122 @@
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::{Decoder, Source};
+
+use crate::audio::backend::AudioBackend;
+use crate::state::constants::audio::MASTER_VOLUME;
+
+/// A sound decoded into raw samples, shared between every voice playing it.
+#[derive(Clone)]
+pub struct SoundClip {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Arc<Vec<i16>>,
+}
+
+impl SoundClip {
+    /// Decodes an encoded sound file (WAV) into samples.
+    ///
+    /// # Parameters
+    /// - `bytes`: The raw contents of the sound file.
+    ///
+    /// # Returns
+    /// The decoded clip, or an error message if the data could not be decoded.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let decoder = Decoder::new(Cursor::new(bytes.to_vec())).map_err(|e| e.to_string())?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<i16> = decoder.collect();
+
+        Ok(Self { channels, sample_rate, samples: Arc::new(samples) })
+    }
+
+    /// Creates a playable source over the clip which stops after `duration`.
+    pub fn source(&self, duration: Duration) -> impl Source<Item = i16> + Send + 'static {
+        ClipSource { clip: self.clone(), position: 0 }.take_duration(duration)
+    }
+}
+
+/// Streams the samples of a `SoundClip` without copying them.
+struct ClipSource {
+    clip: SoundClip,
+    position: usize,
+}
+
+impl Iterator for ClipSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.clip.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for ClipSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.clip.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        self.clip.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.clip.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let frames = self.clip.samples.len() as u64 / self.clip.channels.max(1) as u64;
+        Some(Duration::from_millis(frames * 1000 / self.clip.sample_rate.max(1) as u64))
+    }
+}
+
+/// Owns every decoded sound and forwards play requests to an audio backend.
+///
+/// The final volume of a voice is the per-sound volume multiplied by the master volume.
+pub struct Mixer {
+    clips: Vec<Option<SoundClip>>,
+    volumes: Vec<f32>,
+    master_volume: f32,
+    backend: Box<dyn AudioBackend>,
+}
+
+impl Mixer {
+    /// Decodes all sounds once and creates a mixer playing through `backend`.
+    ///
+    /// Sounds that fail to decode are reported and stay silent, keeping the indices
+    /// in `constants::audio` valid.
+    pub fn new(backend: Box<dyn AudioBackend>, sounds: &[Vec<u8>]) -> Self {
+        let clips = sounds.iter().enumerate().map(|(index, bytes)| {
+            SoundClip::decode(bytes).map_err(|e| println!("Failed to decode sound {}: {}", index, e)).ok()
+        }).collect();
+
+        Self {
+            clips,
+            volumes: vec![1.0; sounds.len()],
+            master_volume: MASTER_VOLUME,
+            backend,
+        }
+    }
+
+    /// Plays the sound with index `sound` for at most `duration` milliseconds.
+    pub fn play(&mut self, sound: usize, duration: u64) {
+        if let Some(Some(clip)) = self.clips.get(sound) {
+            let volume = self.volumes[sound] * self.master_volume;
+            self.backend.play(sound, clip, volume, Duration::from_millis(duration));
+        }
+    }
+
+    pub fn set_volume(&mut self, sound: usize, volume: f32) {
+        if let Some(current) = self.volumes.get_mut(sound) {
+            *current = volume.clamp(0.0, 1.0);
+        }
+    }
+
+    /// Sets the volume every sound is scaled by, starting out as `MASTER_VOLUME`.
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.clamp(0.0, 1.0);
+    }
+}
@@ -1 +1,3 @@
-pub mod engine;
\ No newline at end of file
+pub mod backend;
+pub mod engine;
+pub mod mixer;
@@ -0,0 +1,138 @@
+use crate::graphics::aseprite::Rect;
+use crate::graphics::atlas::SpriteAtlas;
+use crate::graphics::sprites::{DrawOptions, SpriteFrame};
+use crate::state::structs::Direction;
+
+/// What a clip does after its last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    Loop, // Starts over from the first frame
+    Once, // Holds the last frame and reports itself finished
+}
+
+/// A named sequence of frames, each shown for a number of ticks.
+///
+/// Frames are indices into the frames of a `SpriteAtlas`, with a separate list for facing left or
+/// mirrored while drawing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub frames: Vec<usize>, // Frames shown while facing right
+    pub left_frames: Vec<usize>, // Frames shown while facing left, same length as `frames`
+    pub flip_left: bool, // Whether the left frames are drawn mirrored
+    pub durations: Vec<u32>, // Ticks each frame is shown for
+    pub playback: Playback,
+}
+
+impl Clip {
+    /// Creates a looping clip facing either way which shows each of `frames` for `duration` ticks.
+    pub fn looping(frames: Vec<usize>, duration: u32) -> Self {
+        Clip {
+            durations: vec![duration.max(1); frames.len()],
+            left_frames: frames.clone(),
+            flip_left: false,
+            frames,
+            playback: Playback::Loop,
+        }
+    }
+
+    /// Total number of ticks the clip takes to play through once.
+    pub fn length(&self) -> u64 {
+        self.durations.iter().map(|&duration| duration as u64).sum()
+    }
+
+    /// Returns the position within the clip (0 for its first frame) after playing it for `elapsed` ticks.
+    pub fn index_at(&self, elapsed: u64) -> usize {
+        let mut remaining = match self.playback {
+            Playback::Loop => elapsed % self.length(),
+            Playback::Once => elapsed,
+        };
+
+        for (index, &duration) in self.durations.iter().enumerate() {
+            if remaining < duration as u64 {
+                return index;
+            }
+            remaining -= duration as u64;
+        }
+
+        self.durations.len() - 1
+    }
+
+    /// Whether the frames are drawn mirrored for the given facing direction.
+    pub fn is_flipped(&self, direction: Direction) -> bool {
+        self.flip_left && direction == Direction::Left
+    }
+
+    /// Returns the atlas frame at position `index` within the clip for the given facing direction.
+    pub fn frame(&self, index: usize, direction: Direction) -> usize {
+        match direction {
+            Direction::Right => self.frames[index],
+            Direction::Left => self.left_frames[index],
+        }
+    }
+}
+
+/// Plays clips of a `SpriteAtlas` for one entity, advanced once per tick.
+///
+/// Only the clip name and the elapsed ticks are stored, so entities stay `Copy` and animate
+/// the same regardless of the frame rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Animator {
+    clip: &'static str,
+    elapsed: u64, // Ticks the current clip has been playing for
+}
+
+impl Animator {
+    pub fn new(clip: &'static str) -> Self {
+        Animator { clip, elapsed: 0 }
+    }
+
+    /// Switches to `clip`, starting from its first frame. Does nothing if it is already playing.
+    pub fn play(&mut self, clip: &'static str) {
+        if self.clip != clip {
+            self.restart(clip);
+        }
+    }
+
+    /// Plays `clip` from its first frame, even if it is already playing.
+    pub fn restart(&mut self, clip: &'static str) {
+        self.clip = clip;
+        self.elapsed = 0;
+    }
+
+    /// Advances the clip by one tick.
+    pub fn update(&mut self) {
+        self.elapsed += 1;
+    }
+
+    /// Returns true once a clip played `Once` has shown its last frame for its full duration.
+    pub fn is_finished(&self, atlas: &SpriteAtlas) -> bool {
+        let clip = atlas.clip(self.clip);
+        clip.playback == Playback::Once && self.elapsed >= clip.length()
+    }
+
+    /// Returns the sprite to draw for the current tick.
+    pub fn frame<'a>(&self, atlas: &'a SpriteAtlas, direction: Direction) -> &'a SpriteFrame {
+        let index = atlas.clip(self.clip).index_at(self.elapsed);
+        atlas.clip_frame(self.clip, index, direction)
+    }
+
+    /// Returns how to draw the current frame for the given facing direction, which mirrors clips
+    /// without frames of their own for facing left.
+    pub fn draw_options(&self, atlas: &SpriteAtlas, direction: Direction) -> DrawOptions {
+        DrawOptions { flip_x: atlas.clip(self.clip).is_flipped(direction), ..DrawOptions::default() }
+    }
+
+    /// Returns the slice called `name` on the current frame as drawn, e.g. a hitbox drawn in Aseprite.
+    pub fn slice(&self, atlas: &SpriteAtlas, direction: Direction, name: &str) -> Option<Rect> {
+        let clip = atlas.clip(self.clip);
+        let frame = clip.frame(clip.index_at(self.elapsed), direction);
+        let rect = atlas.slice(name, frame)?;
+
+        if clip.is_flipped(direction) {
+            let width = self.frame(atlas, direction).width as i32;
+            Some(Rect { x: width - rect.x - rect.width as i32, ..rect })
+        } else {
+            Some(rect)
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const HEADER_LENGTH: usize = 128;
+const FRAME_HEADER_LENGTH: usize = 16;
+const TAGS_CHUNK: u16 = 0x2018;
+const SLICE_CHUNK: u16 = 0x2022;
+
+#[derive(Debug)]
+pub enum AsepriteError {
+    Io(PathBuf, io::Error),
+    Format(PathBuf, &'static str),
+}
+
+impl fmt::Display for AsepriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsepriteError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            AsepriteError::Format(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+/// Order in which the frames of a tag are played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    PingPong, // Forward, then back without repeating the last and first frames
+    PingPongReverse,
+}
+
+/// A named range of frames, e.g. `walk` or `kick`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub from: usize, // First frame of the tag
+    pub to: usize, // Last frame of the tag, inclusive
+    pub direction: TagDirection,
+    pub repeat: u16, // Times the tag plays before stopping, 0 for forever
+}
+
+impl Tag {
+    /// Returns the frames of the tag in the order they are played.
+    pub fn frames(&self) -> Vec<usize> {
+        let forward: Vec<usize> = (self.from..=self.to).collect();
+        let backward: Vec<usize> = forward.iter().rev().copied().collect();
+        let inner = |frames: &[usize]| frames[1..frames.len().saturating_sub(1).max(1)].to_vec();
+
+        match self.direction {
+            TagDirection::Forward => forward,
+            TagDirection::Reverse => backward,
+            TagDirection::PingPong => [forward, inner(&backward)].concat(),
+            TagDirection::PingPongReverse => [backward, inner(&forward)].concat(),
+        }
+    }
+}
+
+/// A rectangle in pixels, such as a slice in sprite coordinates or a clip area of the window buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A named rectangle such as a hitbox, which may move or change size from a given frame on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slice {
+    pub name: String,
+    pub keys: Vec<(usize, Rect)>, // Frame from which on each rectangle applies, in frame order
+}
+
+impl Slice {
+    /// Returns the rectangle of the slice on `frame`, or None before its first key.
+    pub fn rect_at(&self, frame: usize) -> Option<Rect> {
+        self.keys.iter().take_while(|(start, _)| *start <= frame).last().map(|&(_, rect)| rect)
+    }
+}
+
+/// The animation data of an Aseprite file: its canvas size, frame durations, tags and slices.
+///
+/// The pixels themselves are taken from the sprite sheet exported next to it, whose frames are
+/// laid out side by side at the canvas size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aseprite {
+    pub width: u32, // Size of the canvas, and thus of every frame, in pixels
+    pub height: u32,
+    pub durations: Vec<u32>, // Duration of each frame in milliseconds
+    pub tags: Vec<Tag>,
+    pub slices: Vec<Slice>,
+}
+
+impl Aseprite {
+    pub fn load(path: &Path) -> Result<Self, AsepriteError> {
+        let bytes = fs::read(path).map_err(|e| AsepriteError::Io(path.to_path_buf(), e))?;
+        Self::parse(&bytes).map_err(|message| AsepriteError::Format(path.to_path_buf(), message))
+    }
+
+    /// Reads the header and the chunks of every frame, skipping the ones which hold pixels or colours.
+    pub fn parse(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut header = Reader::new(bytes);
+        let _file_size = header.u32()?;
+        if header.u16()? != HEADER_MAGIC {
+            return Err("not an Aseprite file");
+        }
+        let frame_count = header.u16()? as usize;
+        let width = header.u16()? as u32;
+        let height = header.u16()? as u32;
+
+        let mut aseprite = Aseprite { width, height, durations: Vec::new(), tags: Vec::new(), slices: Vec::new() };
+        let mut offset = HEADER_LENGTH;
+
+        for _ in 0..frame_count {
+            let mut frame = Reader::new(bytes.get(offset..).ok_or("truncated frame")?);
+            let frame_length = frame.u32()? as usize;
+            if frame.u16()? != FRAME_MAGIC {
+                return Err("invalid frame header");
+            }
+            let old_chunk_count = frame.u16()? as usize;
+            aseprite.durations.push(frame.u16()? as u32);
+            frame.skip(2)?;
+            let chunk_count = match frame.u32()? as usize {
+                0 => old_chunk_count,
+                count => count,
+            };
+
+            let mut chunk_offset = offset + FRAME_HEADER_LENGTH;
+            for _ in 0..chunk_count {
+                let mut chunk = Reader::new(bytes.get(chunk_offset..).ok_or("truncated chunk")?);
+                let chunk_length = chunk.u32()? as usize;
+                let chunk_type = chunk.u16()?;
+                if chunk_length < 6 {
+                    return Err("invalid chunk length");
+                }
+
+                match chunk_type {
+                    TAGS_CHUNK => aseprite.tags = read_tags(&mut chunk)?,
+                    SLICE_CHUNK => aseprite.slices.push(read_slice(&mut chunk)?),
+                    _ => {}
+                }
+                chunk_offset += chunk_length;
+            }
+
+            offset += frame_length;
+        }
+
+        Ok(aseprite)
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.name == name)
+    }
+}
+
+fn read_tags(chunk: &mut Reader) -> Result<Vec<Tag>, &'static str> {
+    let count = chunk.u16()?;
+    chunk.skip(8)?;
+
+    (0..count).map(|_| {
+        let from = chunk.u16()? as usize;
+        let to = chunk.u16()? as usize;
+        let direction = match chunk.u8()? {
+            0 => TagDirection::Forward,
+            1 => TagDirection::Reverse,
+            2 => TagDirection::PingPong,
+            3 => TagDirection::PingPongReverse,
+            _ => return Err("unknown tag direction"),
+        };
+        let repeat = chunk.u16()?;
+        chunk.skip(6 + 3 + 1)?; // Reserved, deprecated colour and padding
+        let name = chunk.string()?;
+
+        if from > to {
+            return Err("tag ends before it starts");
+        }
+        Ok(Tag { name, from, to, direction, repeat })
+    }).collect()
+}
+
+fn read_slice(chunk: &mut Reader) -> Result<Slice, &'static str> {
+    let key_count = chunk.u32()?;
+    let flags = chunk.u32()?;
+    chunk.skip(4)?;
+    let name = chunk.string()?;
+
+    let keys = (0..key_count).map(|_| {
+        let frame = chunk.u32()? as usize;
+        let rect = Rect { x: chunk.i32()?, y: chunk.i32()?, width: chunk.u32()?, height: chunk.u32()? };
+        if flags & 1 != 0 {
+            chunk.skip(16)?; // Centre of a 9-patch slice
+        }
+        if flags & 2 != 0 {
+            chunk.skip(8)?; // Pivot
+        }
+        Ok((frame, rect))
+    }).collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Slice { name, keys })
+}
+
+/// Reads little-endian values from the start of a byte slice onwards.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], &'static str> {
+        let bytes = self.bytes.get(self.position..self.position + N).ok_or("unexpected end of file")?;
+        self.position += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), &'static str> {
+        self.bytes.get(self.position..self.position + length).ok_or("unexpected end of file")?;
+        self.position += length;
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, &'static str> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, &'static str> {
+        self.take().map(i32::from_le_bytes)
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        let length = self.u16()? as usize;
+        let bytes = self.bytes.get(self.position..self.position + length).ok_or("unexpected end of file")?;
+        self.position += length;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "invalid UTF-8 in name")
+    }
+}
+
+/// Builds Aseprite files in memory, holding only the parts `Aseprite::parse` reads.
+#[cfg(test)]
+pub mod testing {
+    use super::{Rect, FRAME_HEADER_LENGTH, FRAME_MAGIC, HEADER_LENGTH, HEADER_MAGIC, SLICE_CHUNK, TAGS_CHUNK};
+
+    /// A file with a canvas of `width` x `height` pixels and the given frames.
+    pub fn file(width: u16, height: u16, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut header = vec![0; HEADER_LENGTH];
+        let length = HEADER_LENGTH + frames.iter().map(Vec::len).sum::<usize>();
+        header[0..4].copy_from_slice(&(length as u32).to_le_bytes());
+        header[4..6].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
+        header[6..8].copy_from_slice(&(frames.len() as u16).to_le_bytes());
+        header[8..10].copy_from_slice(&width.to_le_bytes());
+        header[10..12].copy_from_slice(&height.to_le_bytes());
+
+        [header, frames.concat()].concat()
+    }
+
+    /// A frame shown for `duration` milliseconds, holding `chunks`.
+    pub fn frame(duration: u16, chunks: &[Vec<u8>]) -> Vec<u8> {
+        let length = FRAME_HEADER_LENGTH + chunks.iter().map(Vec::len).sum::<usize>();
+        let mut frame = Vec::new();
+        frame.extend((length as u32).to_le_bytes());
+        frame.extend(FRAME_MAGIC.to_le_bytes());
+        frame.extend((chunks.len() as u16).to_le_bytes());
+        frame.extend(duration.to_le_bytes());
+        frame.extend([0; 2]);
+        frame.extend(0u32.to_le_bytes()); // Leaves the chunk count to the old field
+        [frame, chunks.concat()].concat()
+    }
+
+    /// A tags chunk with a tag for each (`name`, `from`, `to`, `direction`, `repeat`).
+    pub fn tags_chunk(tags: &[(&str, u16, u16, u8, u16)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend((tags.len() as u16).to_le_bytes());
+        data.extend([0; 8]);
+        for &(name, from, to, direction, repeat) in tags {
+            data.extend(from.to_le_bytes());
+            data.extend(to.to_le_bytes());
+            data.push(direction);
+            data.extend(repeat.to_le_bytes());
+            data.extend([0; 10]);
+            data.extend(string(name));
+        }
+        chunk(TAGS_CHUNK, data)
+    }
+
+    /// A slice chunk with a key for each (`frame`, `rect`). The 9-patch centre and pivot which
+    /// `flags` announce are filled with bytes the parser must skip.
+    pub fn slice_chunk(name: &str, flags: u32, keys: &[(u32, Rect)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend((keys.len() as u32).to_le_bytes());
+        data.extend(flags.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend(string(name));
+        for &(frame, rect) in keys {
+            data.extend(frame.to_le_bytes());
+            data.extend(rect.x.to_le_bytes());
+            data.extend(rect.y.to_le_bytes());
+            data.extend(rect.width.to_le_bytes());
+            data.extend(rect.height.to_le_bytes());
+            if flags & 1 != 0 {
+                data.extend([0xEE; 16]);
+            }
+            if flags & 2 != 0 {
+                data.extend([0xDD; 8]);
+            }
+        }
+        chunk(SLICE_CHUNK, data)
+    }
+
+    /// A chunk of a type the parser skips, such as the pixels of a cel.
+    pub fn other_chunk(length: usize) -> Vec<u8> {
+        chunk(0x2005, vec![0xCC; length])
+    }
+
+    fn chunk(chunk_type: u16, data: Vec<u8>) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        chunk.extend(((data.len() + 6) as u32).to_le_bytes());
+        chunk.extend(chunk_type.to_le_bytes());
+        [chunk, data].concat()
+    }
+
+    fn string(text: &str) -> Vec<u8> {
+        [(text.len() as u16).to_le_bytes().to_vec(), text.as_bytes().to_vec()].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::testing::{file, frame, other_chunk, slice_chunk, tags_chunk};
+    use super::{Aseprite, AsepriteError, Rect, TagDirection};
+
+    #[test]
+    fn reads_the_canvas_size_and_frame_durations() {
+        let bytes = file(24, 32, &[frame(100, &[other_chunk(20)]), frame(150, &[]), frame(50, &[other_chunk(3)])]);
+        let aseprite = Aseprite::parse(&bytes).unwrap();
+
+        assert_eq!((aseprite.width, aseprite.height), (24, 32));
+        assert_eq!(aseprite.durations, vec![100, 150, 50]);
+        assert!(aseprite.tags.is_empty() && aseprite.slices.is_empty());
+    }
+
+    #[test]
+    fn plays_tags_in_their_direction() {
+        let tags = tags_chunk(&[("walk", 0, 3, 0, 0), ("back", 0, 3, 1, 0), ("bounce", 1, 4, 2, 0), ("unbounce", 1, 4, 3, 1), ("still", 2, 2, 2, 0)]);
+        let frames: Vec<Vec<u8>> = (0..5).map(|index| frame(100, if index == 0 { std::slice::from_ref(&tags) } else { &[] })).collect();
+        let aseprite = Aseprite::parse(&file(16, 16, &frames)).unwrap();
+
+        assert_eq!(aseprite.tag("walk").unwrap().frames(), vec![0, 1, 2, 3]);
+        assert_eq!(aseprite.tag("back").unwrap().frames(), vec![3, 2, 1, 0]);
+        assert_eq!(aseprite.tag("bounce").unwrap().direction, TagDirection::PingPong);
+        assert_eq!(aseprite.tag("bounce").unwrap().frames(), vec![1, 2, 3, 4, 3, 2]);
+        assert_eq!(aseprite.tag("unbounce").unwrap().frames(), vec![4, 3, 2, 1, 2, 3]);
+        assert_eq!(aseprite.tag("unbounce").unwrap().repeat, 1);
+        assert_eq!(aseprite.tag("still").unwrap().frames(), vec![2]);
+        assert!(aseprite.tag("run").is_none());
+    }
+
+    #[test]
+    fn reads_slice_keys_past_nine_patch_and_pivot_data() {
+        let hitbox = Rect { x: 4, y: 2, width: 10, height: 20 };
+        let crouched = Rect { x: 4, y: 12, width: 10, height: 10 };
+        let chunks = [
+            slice_chunk("hitbox", 1 | 2, &[(0, hitbox), (2, crouched)]),
+            slice_chunk("feet", 2, &[(1, Rect { x: -1, y: 30, width: 12, height: 2 })]),
+        ];
+        let aseprite = Aseprite::parse(&file(16, 32, &[frame(100, &chunks), frame(100, &[]), frame(100, &[])])).unwrap();
+
+        let slice = &aseprite.slices[0];
+        assert_eq!(slice.name, "hitbox");
+        assert_eq!(slice.keys, vec![(0, hitbox), (2, crouched)]);
+        assert_eq!((slice.rect_at(1), slice.rect_at(2)), (Some(hitbox), Some(crouched)));
+        assert_eq!(aseprite.slices[1].name, "feet");
+        assert_eq!(aseprite.slices[1].rect_at(0), None);
+        assert_eq!(aseprite.slices[1].rect_at(1).unwrap().x, -1);
+    }
+
+    #[test]
+    fn rejects_truncated_and_invalid_files() {
+        let tags = tags_chunk(&[("walk", 0, 1, 0, 0)]);
+        let bytes = file(16, 16, &[frame(100, &[tags, slice_chunk("hitbox", 3, &[(0, Rect { x: 0, y: 0, width: 1, height: 1 })])]), frame(100, &[])]);
+        assert!(Aseprite::parse(&bytes).is_ok());
+
+        // Cutting the file short anywhere is reported instead of read past the end
+        for length in 0..bytes.len() {
+            assert!(Aseprite::parse(&bytes[..length]).is_err(), "file cut at {} bytes was accepted", length);
+        }
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[4] ^= 0xFF;
+        assert_eq!(Aseprite::parse(&wrong_magic), Err("not an Aseprite file"));
+
+        let backwards = file(16, 16, &[frame(100, &[tags_chunk(&[("walk", 3, 1, 0, 0)])])]);
+        assert_eq!(Aseprite::parse(&backwards), Err("tag ends before it starts"));
+        let unknown_direction = file(16, 16, &[frame(100, &[tags_chunk(&[("walk", 0, 1, 9, 0)])])]);
+        assert_eq!(Aseprite::parse(&unknown_direction), Err("unknown tag direction"));
+
+        let path = std::env::temp_dir().join(format!("age_of_panda_{}_truncated.aseprite", std::process::id()));
+        fs::write(&path, &bytes[..140]).unwrap();
+        let error = Aseprite::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(error, Err(AsepriteError::Format(ref file, "unexpected end of file")) if *file == path));
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::graphics::animation::{Clip, Playback};
+use crate::graphics::aseprite::{Aseprite, Rect};
+use crate::graphics::sprites::{load_sprites_from_map, tint_sprites, SpriteFrame};
+use crate::state::constants::graphics::TICK_RATE;
+use crate::state::structs::Direction;
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(PathBuf, io::Error),
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            AtlasError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+struct Sheet {
+    frames: Range<usize>, // Frames of the sheet within the atlas
+    aseprite: Option<Aseprite>, // Tags and frame durations of a sheet imported from Aseprite
+}
+
+/// Every sprite of the game, loaded from the sheets listed in a manifest, and the animation clips made from them.
+pub struct SpriteAtlas {
+    frames: Vec<SpriteFrame>,
+    sheets: HashMap<String, Sheet>,
+    clips: HashMap<String, Clip>,
+    slices: HashMap<(String, usize), Rect>, // Slices drawn in Aseprite by name and atlas frame, e.g. hitboxes
+}
+
+impl SpriteAtlas {
+    /// Loads the sheets and clips described by a manifest file with one entry per line:
+    ///
+    /// - `sheet <name> <image> <width>x<height> [tint=RRGGBB]` splits an image into frames of the given size.
+    /// - `aseprite <name> <file> [tint=RRGGBB]` splits the image exported next to an Aseprite file into frames
+    ///   of its canvas size, and makes its tags, frame durations and slices available.
+    /// - `clip <name> <sheet> <frames> [left=<frames> | flip] [duration=<ticks>,...] [once]` animates frames
+    ///   of a sheet, given as indices and ranges such as `0-3` or `1,4`, or as the name of an Aseprite tag.
+    ///   Facing left shows the `left` frames, or the same frames mirrored with `flip`. The duration applies to every
+    ///   frame or lists one per frame, and defaults to the durations set in Aseprite. Clips loop unless
+    ///   marked `once` or made from a tag which plays once.
+    pub fn load(path: &Path) -> Result<Self, AtlasError> {
+        let source = fs::read_to_string(path).map_err(|e| AtlasError::Io(path.to_path_buf(), e))?;
+        let mut atlas = SpriteAtlas { frames: Vec::new(), sheets: HashMap::new(), clips: HashMap::new(), slices: HashMap::new() };
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let result = match tokens[0] {
+                "sheet" => atlas.add_sheet(&tokens[1..]),
+                "aseprite" => atlas.add_aseprite(&tokens[1..]),
+                "clip" => atlas.add_clip(&tokens[1..]),
+                entry => Err(format!("unknown entry '{}', expected 'sheet', 'aseprite' or 'clip'", entry)),
+            };
+            result.map_err(|message| AtlasError::Parse { path: path.to_path_buf(), line: index + 1, message })?;
+        }
+
+        Ok(atlas)
+    }
+
+    /// Returns every frame of a sheet in reading order.
+    pub fn sheet(&self, name: &str) -> &[SpriteFrame] {
+        let sheet = self.sheets.get(name).unwrap_or_else(|| panic!("Unknown sprite sheet '{}'", name));
+        &self.frames[sheet.frames.clone()]
+    }
+
+    pub fn clip(&self, name: &str) -> &Clip {
+        self.clips.get(name).unwrap_or_else(|| panic!("Unknown animation clip '{}'", name))
+    }
+
+    /// Returns the frame at position `index` within a clip for the given facing direction.
+    pub fn clip_frame(&self, name: &str, index: usize, direction: Direction) -> &SpriteFrame {
+        &self.frames[self.clip(name).frame(index, direction)]
+    }
+
+    /// Returns the rectangle of the slice called `name` on an atlas frame, or None if it has no such slice.
+    pub fn slice(&self, name: &str, frame: usize) -> Option<Rect> {
+        self.slices.get(&(name.to_string(), frame)).copied()
+    }
+
+    fn add_sheet(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let [name, image, size, options @ ..] = tokens else {
+            return Err("expected 'sheet <name> <image> <width>x<height>'".to_string());
+        };
+
+        let (width, height) = size.split_once('x')
+            .and_then(|(width, height)| Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?)))
+            .filter(|&(width, height)| width > 0 && height > 0)
+            .ok_or_else(|| format!("expected a frame size such as '16x16' but found '{}'", size))?;
+
+        let frames = load_sprites_from_map(image, width, height).map_err(|e| format!("{}: {}", image, e))?;
+        self.insert_sheet(name, apply_sheet_options(frames, options)?, None);
+
+        Ok(())
+    }
+
+    fn add_aseprite(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let [name, file, options @ ..] = tokens else {
+            return Err("expected 'aseprite <name> <file>'".to_string());
+        };
+
+        let aseprite = Aseprite::load(Path::new(file)).map_err(|e| e.to_string())?;
+        let image = Path::new(file).with_extension("png");
+        let image = image.to_string_lossy();
+        let mut frames = load_sprites_from_map(&image, aseprite.width, aseprite.height).map_err(|e| format!("{}: {}", image, e))?;
+        if frames.len() < aseprite.durations.len() {
+            return Err(format!("{} has {} frames but {} has {}", image, frames.len(), file, aseprite.durations.len()));
+        }
+        // Cells of the exported grid past the last frame are left empty by Aseprite
+        frames.truncate(aseprite.durations.len());
+
+        self.insert_sheet(name, apply_sheet_options(frames, options)?, Some(aseprite));
+
+        Ok(())
+    }
+
+    fn insert_sheet(&mut self, name: &str, frames: Vec<SpriteFrame>, aseprite: Option<Aseprite>) {
+        let start = self.frames.len();
+        self.frames.extend(frames);
+
+        for slice in aseprite.iter().flat_map(|aseprite| &aseprite.slices) {
+            for frame in start..self.frames.len() {
+                if let Some(rect) = slice.rect_at(frame - start) {
+                    self.slices.insert((slice.name.clone(), frame), rect);
+                }
+            }
+        }
+
+        self.sheets.insert(name.to_string(), Sheet { frames: start..self.frames.len(), aseprite });
+    }
+
+    fn add_clip(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let [name, sheet_name, frames, options @ ..] = tokens else {
+            return Err("expected 'clip <name> <sheet> <frames>'".to_string());
+        };
+
+        let sheet = self.sheets.get(*sheet_name).ok_or_else(|| format!("unknown sheet '{}'", sheet_name))?;
+        let start = sheet.frames.start;
+        // Every frame of an Aseprite sheet has a duration, so a clip cannot reach past them
+        let length = sheet.aseprite.as_ref().map_or(sheet.frames.len(), |aseprite| aseprite.durations.len().min(sheet.frames.len()));
+        let tag = sheet.aseprite.as_ref().and_then(|aseprite| aseprite.tag(frames));
+
+        // Frames are given relative to the sheet, either by tag or by index
+        let resolve = |list: &str| -> Result<Vec<usize>, String> {
+            let frames = match (&sheet.aseprite, sheet.aseprite.as_ref().and_then(|aseprite| aseprite.tag(list))) {
+                (_, Some(tag)) => tag.frames(),
+                (Some(_), None) => parse_frame_list(list).map_err(|_| format!("'{}' is neither a tag nor a list of frames", list))?,
+                (None, None) => parse_frame_list(list)?,
+            };
+            match frames.iter().find(|&&frame| frame >= length) {
+                Some(frame) => Err(format!("sheet '{}' has no frame {}", sheet_name, frame)),
+                None => Ok(frames),
+            }
+        };
+
+        let sheet_frames = resolve(frames)?;
+        let mut left_frames = None;
+        let mut flip = false;
+        let mut durations = match &sheet.aseprite {
+            Some(aseprite) => sheet_frames.iter().map(|&frame| milliseconds_to_ticks(aseprite.durations[frame])).collect(),
+            None => vec![1],
+        };
+        let mut playback = if tag.is_some_and(|tag| tag.repeat == 1) { Playback::Once } else { Playback::Loop };
+
+        for option in options {
+            match option.split_once('=') {
+                Some(("left", list)) => left_frames = Some(resolve(list)?),
+                Some(("duration", list)) => {
+                    durations = list.split(',')
+                        .map(|duration| duration.parse::<u32>().ok().filter(|&duration| duration > 0)
+                            .ok_or_else(|| format!("'duration' expects a positive number of ticks but found '{}'", duration)))
+                        .collect::<Result<Vec<u32>, String>>()?;
+                }
+                None if *option == "flip" => flip = true,
+                None if *option == "once" => playback = Playback::Once,
+                _ => return Err(format!("unknown clip option '{}'", option)),
+            }
+        }
+
+        let frames: Vec<usize> = sheet_frames.iter().map(|&frame| start + frame).collect();
+        let left_frames = match left_frames {
+            Some(left_frames) => left_frames.iter().map(|&frame| start + frame).collect(),
+            None => frames.clone(),
+        };
+
+        if left_frames.len() != frames.len() {
+            return Err(format!("clip has {} frames facing right but {} facing left", frames.len(), left_frames.len()));
+        }
+        if durations.len() == 1 {
+            durations = vec![durations[0]; frames.len()];
+        } else if durations.len() != frames.len() {
+            return Err(format!("clip has {} frames but {} durations", frames.len(), durations.len()));
+        }
+
+        self.clips.insert(name.to_string(), Clip { frames, left_frames, flip_left: flip, durations, playback });
+
+        Ok(())
+    }
+}
+
+/// Applies the `tint` option of a sheet entry to its frames.
+fn apply_sheet_options(mut frames: Vec<SpriteFrame>, options: &[&str]) -> Result<Vec<SpriteFrame>, String> {
+    for option in options {
+        match option.split_once('=') {
+            Some(("tint", value)) => {
+                let tint = u32::from_str_radix(value, 16).ok().filter(|_| value.len() == 6)
+                    .ok_or_else(|| format!("'tint' expects a colour as RRGGBB but found '{}'", value))?;
+                frames = tint_sprites(&frames, tint);
+            }
+            _ => return Err(format!("unknown sheet option '{}'", option)),
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Converts an Aseprite frame duration to simulation ticks, showing every frame for at least one tick.
+fn milliseconds_to_ticks(milliseconds: u32) -> u32 {
+    ((milliseconds * TICK_RATE + 500) / 1000).max(1)
+}
+
+/// Parses a list of frame indices and inclusive ranges, e.g. `0-3` or `1,4,6-7`.
+fn parse_frame_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut frames = Vec::new();
+
+    for part in list.split(',') {
+        let invalid = || format!("expected frames such as '0-3' or '1,4' but found '{}'", list);
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (first.parse::<usize>().map_err(|_| invalid())?, last.parse::<usize>().map_err(|_| invalid())?);
+                if first > last {
+                    return Err(invalid());
+                }
+                frames.extend(first..=last);
+            }
+            None => frames.push(part.parse().map_err(|_| invalid())?),
+        }
+    }
+
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::graphics::aseprite::testing::{file, frame, tags_chunk};
+
+    use super::SpriteAtlas;
+
+    /// Writes a two frame Aseprite file of 2x2 pixels, tagged `walk` over frames 0 to 2, next to
+    /// an exported sheet of `cells` frames, and returns the path of the Aseprite file.
+    fn aseprite_with_sheet(directory: &Path, cells: u32) -> PathBuf {
+        fs::create_dir_all(directory).unwrap();
+        let path = directory.join("hero.aseprite");
+        fs::write(&path, file(2, 2, &[frame(100, &[tags_chunk(&[("walk", 0, 2, 0, 0)])]), frame(200, &[])])).unwrap();
+        image::RgbaImage::new(2 * cells, 2).save(path.with_extension("png")).unwrap();
+        path
+    }
+
+    /// Loads a manifest importing the Aseprite file at `path` as sheet `hero`, followed by `clip`.
+    fn load_atlas(path: &Path, clip: &str) -> Result<SpriteAtlas, String> {
+        let manifest = path.with_extension("atlas");
+        fs::write(&manifest, format!("aseprite hero {}\n{}\n", path.display(), clip)).unwrap();
+        SpriteAtlas::load(&manifest).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn clips_stop_at_the_last_aseprite_frame() {
+        let directory = std::env::temp_dir().join(format!("age_of_panda_{}_atlas", std::process::id()));
+        let path = aseprite_with_sheet(&directory, 3);
+
+        // The exported sheet has a spare cell, which is not a frame of the sheet
+        let atlas = load_atlas(&path, "clip idle hero 0-1").unwrap();
+        assert_eq!(atlas.sheet("hero").len(), 2);
+        assert_eq!(atlas.clip("idle").durations, vec![6, 12]);
+
+        let past_the_end = load_atlas(&path, "clip idle hero 1-2").err().unwrap();
+        let tag_past_the_end = load_atlas(&path, "clip walk hero walk").err().unwrap();
+        let short_sheet = load_atlas(&aseprite_with_sheet(&directory, 1), "clip idle hero 0").err().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(past_the_end.ends_with("2: sheet 'hero' has no frame 2"), "{}", past_the_end);
+        assert!(tag_past_the_end.ends_with("2: sheet 'hero' has no frame 2"), "{}", tag_past_the_end);
+        assert!(short_sheet.contains("has 1 frames but"), "{}", short_sheet);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+
+/// Window the game is presented in and the source of keyboard input.
+pub trait DisplayBackend {
+    /// Returns false once the game should shut down, e.g. because the window was closed.
+    fn is_open(&self) -> bool;
+
+    fn is_key_down(&self, key: Key) -> bool;
+
+    /// Returns true if `key` was pressed since the last presented frame, repeating while held.
+    fn is_key_pressed(&self, key: Key) -> bool;
+
+    /// Keys which went down since the last presented frame, without repeats.
+    fn keys_pressed(&self) -> Vec<Key>;
+
+    /// Shows a finished frame of `width` x `height` pixels.
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize) -> Result<(), String>;
+
+    /// Current size of the area frames are shown in, or None if frames are shown at the size they are presented.
+    fn size(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Switches to a borderless window covering a `width` x `height` screen, or back to a normal window of that size.
+    fn set_fullscreen(&mut self, _fullscreen: bool, _width: usize, _height: usize) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Whether frames are paced by the wall clock. Otherwise every frame runs exactly one
+    /// simulation tick without sleeping, which is faster than real time and deterministic.
+    fn is_realtime(&self) -> bool {
+        true
+    }
+}
+
+/// Presents frames in a desktop window created by minifb.
+pub struct MinifbBackend {
+    window: Window,
+    title: String,
+}
+
+impl MinifbBackend {
+    /// Opens a resizable window of `width` x `height` pixels, or a borderless one covering the screen if `fullscreen`.
+    pub fn open(title: &str, width: usize, height: usize, fullscreen: bool) -> Result<Self, String> {
+        Ok(MinifbBackend { window: open_window(title, width, height, fullscreen)?, title: title.to_string() })
+    }
+}
+
+fn open_window(title: &str, width: usize, height: usize, fullscreen: bool) -> Result<Window, String> {
+    let options = WindowOptions {
+        borderless: fullscreen,
+        title: !fullscreen,
+        resize: !fullscreen,
+        topmost: fullscreen,
+        ..WindowOptions::default()
+    };
+    let mut window = Window::new(title, width, height, options).map_err(|e| e.to_string())?;
+
+    if fullscreen {
+        window.set_position(0, 0);
+    }
+    Ok(window)
+}
+
+impl DisplayBackend for MinifbBackend {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.window.is_key_down(key)
+    }
+
+    fn is_key_pressed(&self, key: Key) -> bool {
+        self.window.is_key_pressed(key, KeyRepeat::Yes)
+    }
+
+    fn keys_pressed(&self) -> Vec<Key> {
+        self.window.get_keys_pressed(KeyRepeat::No)
+    }
+
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize) -> Result<(), String> {
+        self.window.update_with_buffer(buffer, width, height).map_err(|e| e.to_string())
+    }
+
+    fn size(&self) -> Option<(usize, usize)> {
+        Some(self.window.get_size())
+    }
+
+    /// minifb cannot change the style of an open window, so a new one replaces it.
+    fn set_fullscreen(&mut self, fullscreen: bool, width: usize, height: usize) -> Result<(), String> {
+        self.window = open_window(&self.title, width, height, fullscreen)?;
+        Ok(())
+    }
+}
+
+/// A key being pressed or released at the start of a given frame.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+/// Runs without a window: keys come from a script and frames are captured to memory.
+///
+/// Closes itself after a fixed number of frames, so `start_event_loop` returns on its own.
+pub struct HeadlessBackend {
+    script: Vec<KeyEvent>, // Sorted by frame
+    next_event: usize, // Index of the first event which has not been applied yet
+    held_keys: HashSet<Key>,
+    new_keys: Vec<Key>, // Keys pressed by the script at the start of the current frame
+    frame: u64, // Number of frames presented so far
+    frame_limit: u64,
+    capture_interval: Option<u64>, // Every how many frames a copy of the presented frame is kept
+    frames: Vec<Vec<u32>>,
+}
+
+impl HeadlessBackend {
+    /// # Parameters
+    /// - `script`: Key presses and releases, in any order.
+    /// - `frame_limit`: Number of frames after which `is_open` returns false.
+    pub fn new(mut script: Vec<KeyEvent>, frame_limit: u64) -> Self {
+        script.sort_by_key(|event| event.frame);
+
+        let mut backend = HeadlessBackend {
+            script,
+            next_event: 0,
+            held_keys: HashSet::new(),
+            new_keys: Vec::new(),
+            frame: 0,
+            frame_limit,
+            capture_interval: None,
+            frames: Vec::new(),
+        };
+        backend.apply_script();
+        backend
+    }
+
+    /// Keeps a copy of every `interval`th presented frame, starting with the first, retrievable through `frames`.
+    pub fn capture_frames(mut self, interval: u64) -> Self {
+        self.capture_interval = Some(interval.max(1));
+        self
+    }
+
+    /// All captured frames in presentation order.
+    pub fn frames(&self) -> &[Vec<u32>] {
+        &self.frames
+    }
+
+    fn apply_script(&mut self) {
+        self.new_keys.clear();
+        while let Some(event) = self.script.get(self.next_event).filter(|event| event.frame <= self.frame) {
+            if event.pressed {
+                if self.held_keys.insert(event.key) {
+                    self.new_keys.push(event.key);
+                }
+            } else {
+                self.held_keys.remove(&event.key);
+            }
+            self.next_event += 1;
+        }
+    }
+}
+
+impl DisplayBackend for HeadlessBackend {
+    fn is_open(&self) -> bool {
+        self.frame < self.frame_limit
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    fn is_key_pressed(&self, key: Key) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    fn keys_pressed(&self) -> Vec<Key> {
+        self.new_keys.clone()
+    }
+
+    fn present(&mut self, buffer: &[u32], _width: usize, _height: usize) -> Result<(), String> {
+        if self.capture_interval.is_some_and(|interval| self.frame.is_multiple_of(interval)) {
+            self.frames.push(buffer.to_vec());
+        }
+
+        self.frame += 1;
+        self.apply_script();
+
+        Ok(())
+    }
+
+    fn is_realtime(&self) -> bool {
+        false
+    }
+}
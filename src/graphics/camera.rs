@@ -0,0 +1,151 @@
+use crate::audio::mixer::Mixer;
+use crate::state::collision::Aabb;
+use crate::state::constants::camera::{DEAD_ZONE_LEFT, DEAD_ZONE_RIGHT, LOOK_AHEAD, LOOK_AHEAD_SMOOTHING};
+use crate::state::core_logic::CoreLogic;
+use crate::state::structs::Direction::Right;
+use crate::state::structs::{Direction, GameState};
+use crate::state::timestep::interpolate;
+
+/// The part of the world shown on screen.
+///
+/// The camera follows its target loosely: the target may move freely within a dead zone in the
+/// middle of the screen, the view leads slightly in the facing direction and never shows anything
+/// beyond the edges of the map.
+pub struct Camera {
+    pub x: f32, // World position of the left edge of the view
+    pub y: f32, // World position of the top edge of the view
+    pub previous_x: f32, // Position at the start of the current tick, used to interpolate drawing
+    pub previous_y: f32,
+    width: f32, // Size of the view in pixels
+    height: f32,
+    look_ahead: f32, // Current distance the view leads the target by, eased towards `LOOK_AHEAD`
+    shake_magnitude: f32, // Largest shake offset in pixels
+    shake_duration: u32, // Ticks the current shake lasts in total
+    shake_remaining: u32, // Ticks left of the current shake
+}
+
+/// A snapshot of the camera for drawing a single frame, converting world to screen coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    pub x: f32,
+    pub y: f32,
+    width: usize,
+    height: usize,
+}
+
+impl Camera {
+    pub fn new(width: usize, height: usize) -> Self {
+        Camera {
+            x: 0.0,
+            y: 0.0,
+            previous_x: 0.0,
+            previous_y: 0.0,
+            width: width as f32,
+            height: height as f32,
+            look_ahead: 0.0,
+            shake_magnitude: 0.0,
+            shake_duration: 0,
+            shake_remaining: 0,
+        }
+    }
+
+    /// Moves the view just enough to keep `target_x` (ahead by the look-ahead) inside the dead zone
+    /// and `target_y` centred, without leaving the `map_width` x `map_height` map.
+    pub fn follow(&mut self, target_x: f32, target_y: f32, direction: Direction, map_width: f32, map_height: f32) {
+        let look_ahead = if direction == Right { LOOK_AHEAD } else { -LOOK_AHEAD };
+        self.look_ahead += (look_ahead - self.look_ahead) * LOOK_AHEAD_SMOOTHING;
+
+        let focus_x = target_x + self.look_ahead;
+        if focus_x < self.x + DEAD_ZONE_LEFT {
+            self.x = focus_x - DEAD_ZONE_LEFT;
+        } else if focus_x > self.x + DEAD_ZONE_RIGHT {
+            self.x = focus_x - DEAD_ZONE_RIGHT;
+        }
+        self.y = target_y - self.height / 2.0;
+
+        self.x = self.x.clamp(0.0, (map_width - self.width).max(0.0));
+        self.y = self.y.clamp(0.0, (map_height - self.height).max(0.0));
+    }
+
+    /// Jumps straight to the target, e.g. when entering a new map.
+    pub fn snap(&mut self, target_x: f32, target_y: f32, direction: Direction, map_width: f32, map_height: f32) {
+        self.look_ahead = if direction == Right { LOOK_AHEAD } else { -LOOK_AHEAD };
+        self.x = target_x + self.look_ahead - (DEAD_ZONE_LEFT + DEAD_ZONE_RIGHT) / 2.0;
+        self.follow(target_x, target_y, direction, map_width, map_height);
+        self.previous_x = self.x;
+        self.previous_y = self.y;
+    }
+
+    /// Shakes the view by up to `magnitude` pixels, fading out over `ticks` ticks.
+    /// A weaker shake does not cut a stronger one short.
+    pub fn shake(&mut self, magnitude: f32, ticks: u32) {
+        if magnitude * ticks as f32 >= self.shake_magnitude * self.shake_remaining as f32 {
+            self.shake_magnitude = magnitude;
+            self.shake_duration = ticks;
+            self.shake_remaining = ticks;
+        }
+    }
+
+    /// Counts down the running shake, called once per tick.
+    pub fn update_shake(&mut self) {
+        self.shake_remaining = self.shake_remaining.saturating_sub(1);
+    }
+
+    /// Returns the view to draw with, between the previous and the current tick by `alpha`.
+    pub fn view(&self, alpha: f32) -> View {
+        let (shake_x, shake_y) = self.shake_offset();
+
+        View {
+            x: interpolate(self.previous_x, self.x, alpha) + shake_x,
+            y: interpolate(self.previous_y, self.y, alpha) + shake_y,
+            width: self.width as usize,
+            height: self.height as usize,
+        }
+    }
+
+    fn shake_offset(&self) -> (f32, f32) {
+        if self.shake_remaining == 0 {
+            return (0.0, 0.0);
+        }
+
+        // A fixed wobble rather than random noise keeps the shake identical across replays
+        let strength = self.shake_magnitude * self.shake_remaining as f32 / self.shake_duration as f32;
+        let phase = (self.shake_duration - self.shake_remaining) as f32;
+        ((phase * 2.3).sin() * strength, (phase * 3.7).cos() * strength)
+    }
+}
+
+impl View {
+    /// The part of the world shown.
+    pub fn area(&self) -> Aabb {
+        Aabb::new(self.x, self.y, self.width as f32, self.height as f32)
+    }
+
+    /// Converts a world position to a position in the window buffer.
+    pub fn world_to_screen(&self, x: f32, y: f32) -> (isize, isize) {
+        ((x - self.x).round() as isize, (y - self.y).round() as isize)
+    }
+
+    /// Returns the window buffer position of a `width` x `height` sprite at the world position
+    /// (`x`, `y`), or None if it would be entirely off screen. Sprites crossing an edge are clipped when drawn.
+    pub fn place(&self, x: f32, y: f32, width: u32, height: u32) -> Option<(isize, isize)> {
+        let (screen_x, screen_y) = self.world_to_screen(x, y);
+        let visible_x = screen_x + (width as isize) > 0 && screen_x < self.width as isize;
+        let visible_y = screen_y + (height as isize) > 0 && screen_y < self.height as isize;
+
+        (visible_x && visible_y).then_some((screen_x, screen_y))
+    }
+}
+
+pub struct FollowCamera;
+
+impl CoreLogic for FollowCamera {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        let map = &game_state.all_maps[game_state.current_map_index];
+        let player = &game_state.player;
+
+        // Follow the centre of the player sprite
+        game_state.camera.follow(player.x + 11.0, player.y, player.direction, map.width as f32, map.height as f32);
+        game_state.camera.update_shake();
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use minifb::Key;
+
+use crate::graphics::render_graphics::{ScaleMode, Scaler};
+use crate::state::constants::graphics::{DISPLAY_SETTINGS_FILE, SCALED_WINDOW_HEIGHT, SCALED_WINDOW_WIDTH, WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH};
+use crate::state::structs::GameState;
+
+#[derive(Debug)]
+pub enum DisplaySettingsError {
+    Io(PathBuf, io::Error),
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for DisplaySettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisplaySettingsError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            DisplaySettingsError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+/// How the game is shown on screen, kept between runs in the display settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplaySettings {
+    pub fullscreen: bool, // Borderless window covering the screen instead of a normal window
+    pub window_size: (usize, usize), // Size of the normal window, updated as it is resized
+    pub fullscreen_size: Option<(usize, usize)>, // Size of the screen if set in the file, as minifb cannot look it up itself. Fullscreen needs it
+    pub scale_mode: ScaleMode,
+    pub crt: bool, // Scanlines over the picture
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            fullscreen: false,
+            window_size: (SCALED_WINDOW_WIDTH, SCALED_WINDOW_HEIGHT),
+            fullscreen_size: None,
+            scale_mode: ScaleMode::Stretch,
+            crt: false,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Loads the settings from a file with one `setting = value` line per setting.
+    /// Settings missing from the file keep their defaults.
+    pub fn load(path: &Path) -> Result<Self, DisplaySettingsError> {
+        let source = fs::read_to_string(path).map_err(|e| DisplaySettingsError::Io(path.to_path_buf(), e))?;
+        let parse_error = |line: usize, message: String| DisplaySettingsError::Parse { path: path.to_path_buf(), line, message };
+
+        let mut settings = DisplaySettings::default();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line.split_once('=').ok_or_else(|| parse_error(index + 1, "expected 'setting = value'".to_string()))?;
+            let (name, value) = (name.trim(), value.trim());
+            let invalid = |expected: &str| parse_error(index + 1, format!("'{}' expects {} but found '{}'", name, expected, value));
+
+            match name {
+                "fullscreen" => settings.fullscreen = value.parse().map_err(|_| invalid("true or false"))?,
+                "window_size" => settings.window_size = parse_size(value).ok_or_else(|| invalid("a size such as '960x540'"))?,
+                "fullscreen_size" => settings.fullscreen_size = Some(parse_size(value).ok_or_else(|| invalid("a size such as '1920x1080'"))?),
+                "scale" => settings.scale_mode = ScaleMode::parse(value).ok_or_else(|| invalid("'stretch' or 'integer'"))?,
+                "crt" => settings.crt = value.parse().map_err(|_| invalid("true or false"))?,
+                _ => return Err(parse_error(index + 1, format!("unknown setting '{}'", name))),
+            }
+        }
+
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DisplaySettingsError> {
+        let scale = match self.scale_mode {
+            ScaleMode::Stretch => "stretch",
+            ScaleMode::Integer => "integer",
+        };
+        let fullscreen_size = match self.fullscreen_size {
+            Some((width, height)) => format!("fullscreen_size = {}x{}\n", width, height),
+            None => String::new(),
+        };
+        let source = format!(
+            "# Display settings, F11 toggles fullscreen (see README.md)\nfullscreen = {}\nwindow_size = {}x{}\n{}scale = {}\ncrt = {}\n",
+            self.fullscreen, self.window_size.0, self.window_size.1, fullscreen_size, scale, self.crt,
+        );
+
+        fs::write(path, source).map_err(|e| DisplaySettingsError::Io(path.to_path_buf(), e))
+    }
+
+    /// Size of the window to open for the current mode.
+    pub fn size(&self) -> (usize, usize) {
+        match (self.fullscreen, self.fullscreen_size) {
+            (true, Some(size)) => size,
+            _ => self.window_size,
+        }
+    }
+}
+
+/// Parses a size such as `960x540`.
+fn parse_size(value: &str) -> Option<(usize, usize)> {
+    let (width, height) = value.split_once('x')?;
+    let size = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// Toggles the debug overlay when F3 is pressed, and between windowed and fullscreen when F11 is
+/// pressed, saving the choice. Called once per presented frame.
+pub fn handle_display_keys(game_state: &mut GameState) {
+    let keys = game_state.display.keys_pressed();
+    if keys.contains(&Key::F3) {
+        game_state.show_debug = !game_state.show_debug;
+    }
+    if !keys.contains(&Key::F11) {
+        return;
+    }
+
+    let settings = &mut game_state.display_settings;
+    // minifb cannot look up the size of the screen, so fullscreen waits until the display settings give it
+    if !settings.fullscreen && settings.fullscreen_size.is_none() {
+        println!("Fullscreen needs the size of the screen: set 'fullscreen_size', e.g. 'fullscreen_size = 1920x1080', in {}", DISPLAY_SETTINGS_FILE);
+        return;
+    }
+
+    settings.fullscreen = !settings.fullscreen;
+    let (width, height) = settings.size();
+
+    if let Err(e) = game_state.display.set_fullscreen(settings.fullscreen, width, height) {
+        println!("Failed to switch {} fullscreen: {}", if settings.fullscreen { "to" } else { "from" }, e);
+        settings.fullscreen = !settings.fullscreen;
+        return;
+    }
+    println!("{} at {}x{}", if settings.fullscreen { "Fullscreen" } else { "Windowed" }, width, height);
+    save_display_settings(settings);
+}
+
+/// Matches the scaled buffer to the size of the window after it was resized or toggled to fullscreen.
+pub fn fit_to_window(game_state: &mut GameState) {
+    let Some((width, height)) = game_state.display.size() else {
+        return;
+    };
+    // A minimised window has no area to draw to, so the last size is kept until it is restored
+    if width == 0 || height == 0 || (width, height) == (game_state.window_width, game_state.window_height) {
+        return;
+    }
+
+    let settings = &mut game_state.display_settings;
+    if !settings.fullscreen {
+        settings.window_size = (width, height);
+    }
+    game_state.scaler = Scaler::new(WINDOW_BUFFER_WIDTH, WINDOW_BUFFER_HEIGHT, width, height, settings.scale_mode, settings.crt);
+    game_state.scaled_buffer.resize(width * height, 0);
+    game_state.window_width = width;
+    game_state.window_height = height;
+}
+
+/// Loads the display settings file, using the defaults if it is missing or invalid. Starts in a
+/// window if the file asks for fullscreen without giving the size of the screen.
+pub fn load_display_settings() -> DisplaySettings {
+    let path = Path::new(DISPLAY_SETTINGS_FILE);
+    if !path.exists() {
+        return DisplaySettings::default();
+    }
+
+    let mut settings = DisplaySettings::load(path).unwrap_or_else(|e| {
+        println!("Using default display settings: {}", e);
+        DisplaySettings::default()
+    });
+    if settings.fullscreen && settings.fullscreen_size.is_none() {
+        println!("Starting in a window: fullscreen needs 'fullscreen_size' in {}", DISPLAY_SETTINGS_FILE);
+        settings.fullscreen = false;
+    }
+    settings
+}
+
+pub fn save_display_settings(settings: &DisplaySettings) {
+    match settings.save(Path::new(DISPLAY_SETTINGS_FILE)) {
+        Ok(()) => println!("Saved display settings to {}", DISPLAY_SETTINGS_FILE),
+        Err(e) => println!("Failed to save display settings: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minifb::Key;
+
+    use crate::graphics::backend::{HeadlessBackend, KeyEvent};
+    use crate::state::game::testing::game_state;
+
+    use super::{handle_display_keys, DisplaySettings};
+
+    #[test]
+    fn fullscreen_needs_the_size_of_the_screen() {
+        let mut game_state = game_state();
+        let script = vec![KeyEvent { frame: 0, key: Key::F11, pressed: true }];
+        game_state.display = Box::leak(Box::new(HeadlessBackend::new(script, u64::MAX)));
+
+        handle_display_keys(&mut game_state);
+
+        assert!(!game_state.display_settings.fullscreen);
+        let asked = DisplaySettings { fullscreen: true, ..DisplaySettings::default() };
+        assert_eq!(asked.size(), asked.window_size);
+        let sized = DisplaySettings { fullscreen_size: Some((1920, 1080)), ..asked };
+        assert_eq!(sized.size(), (1920, 1080));
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::graphics::sprites::{draw_sprite_with, load_sprites_from_map, DrawOptions, SpriteFrame};
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(PathBuf, io::Error),
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            FontError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+/// Where text is placed relative to the position it is drawn at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left, // Starts at the position
+    Centre, // Centred on the position
+    Right, // Ends at the position
+}
+
+/// How `BitmapFont::draw` places and colours text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyle {
+    pub align: Align, // Applied to every line separately
+    pub colour: u32, // Colour of the text in RGB format
+    pub shadow: Option<u32>, // Colour of a copy drawn one pixel down and to the right, behind the text
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle { align: Align::Left, colour: 0xFFFFFF, shadow: None }
+    }
+}
+
+/// A font of white glyphs cut from an image, drawn in any colour.
+pub struct BitmapFont {
+    glyphs: Vec<SpriteFrame>, // One glyph per character from `first` on
+    widths: Vec<u32>, // Visible width of each glyph
+    advances: Vec<u32>, // Distance from the start of each glyph to the start of the next
+    first: u32, // Character of the first glyph
+    line_height: u32,
+}
+
+impl BitmapFont {
+    /// Loads a font from a metrics file with one `setting = value` line per setting:
+    ///
+    /// - `image`: Image holding the glyphs in reading order, each against the left edge of its cell.
+    /// - `cell`: Size of the cell of every glyph, e.g. `6x8`.
+    /// - `first`: Character code of the first glyph (default 32, a space).
+    /// - `spacing`: Pixels between glyphs (default 1).
+    /// - `space_width`: Width of a space, whose glyph is empty (default half a cell).
+    /// - `line_height`: Pixels from one line of text to the next (default the cell height plus one).
+    pub fn load(path: &Path) -> Result<Self, FontError> {
+        let source = fs::read_to_string(path).map_err(|e| FontError::Io(path.to_path_buf(), e))?;
+        let parse_error = |line: usize, message: String| FontError::Parse { path: path.to_path_buf(), line, message };
+
+        let (mut image, mut cell, mut first, mut spacing, mut space_width, mut line_height) = (None, None, 32, 1, None, None);
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line.split_once('=').ok_or_else(|| parse_error(index + 1, "expected 'setting = value'".to_string()))?;
+            let (name, value) = (name.trim(), value.trim());
+            let number = || value.parse::<u32>().map_err(|_| parse_error(index + 1, format!("'{}' expects a whole number but found '{}'", name, value)));
+
+            match name {
+                "image" => image = Some((index + 1, value.to_string())),
+                "cell" => {
+                    cell = Some(value.split_once('x')
+                        .and_then(|(width, height)| Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?)))
+                        .filter(|&(width, height)| width > 0 && height > 0)
+                        .ok_or_else(|| parse_error(index + 1, format!("expected a cell size such as '6x8' but found '{}'", value)))?);
+                }
+                "first" => first = number()?,
+                "spacing" => spacing = number()?,
+                "space_width" => space_width = Some(number()?),
+                "line_height" => line_height = Some(number()?),
+                _ => return Err(parse_error(index + 1, format!("unknown setting '{}'", name))),
+            }
+        }
+
+        let missing = |name: &str| parse_error(source.lines().count(), format!("missing '{}'", name));
+        let (image_line, image) = image.ok_or_else(|| missing("image"))?;
+        let (width, height) = cell.ok_or_else(|| missing("cell"))?;
+        let glyphs = load_sprites_from_map(&image, width, height).map_err(|e| parse_error(image_line, format!("{}: {}", image, e)))?;
+
+        // Glyphs sit against the left edge of their cell, so their visible width sets how far the text moves on
+        let space_width = space_width.unwrap_or(width / 2);
+        let widths: Vec<u32> = glyphs.iter().map(|glyph| glyph.visible_width()).collect();
+        let advances = widths.iter().enumerate().map(|(index, &width)| {
+            if first + index as u32 == ' ' as u32 { space_width } else { width + spacing }
+        }).collect();
+
+        Ok(BitmapFont { glyphs, widths, advances, first, line_height: line_height.unwrap_or(height + 1) })
+    }
+
+    /// Returns the glyph for `character`, falling back to its upper case form and then to `?`.
+    fn glyph(&self, character: char) -> Option<usize> {
+        let index = |character: char| (character as u32).checked_sub(self.first).map(|index| index as usize).filter(|&index| index < self.glyphs.len());
+        index(character).or_else(|| index(character.to_ascii_uppercase())).or_else(|| index('?'))
+    }
+
+    /// Width of a single line of text in pixels.
+    fn line_width(&self, line: &str) -> u32 {
+        let glyphs: Vec<usize> = line.chars().filter_map(|character| self.glyph(character)).collect();
+        let width: u32 = glyphs.iter().map(|&glyph| self.advances[glyph]).sum();
+
+        // The spacing after the last glyph is not part of the text
+        width - glyphs.last().map_or(0, |&glyph| self.advances[glyph].saturating_sub(self.widths[glyph]))
+    }
+
+    /// Returns the width of the widest line and the height of all lines of `text` in pixels.
+    pub fn measure(&self, text: &str) -> (u32, u32) {
+        let width = text.lines().map(|line| self.line_width(line)).max().unwrap_or(0);
+        (width, text.lines().count() as u32 * self.line_height)
+    }
+
+    /// Draws text, which may span several lines, onto the window buffer.
+    ///
+    /// # Parameters
+    /// - `text`: The text to draw. Characters without a glyph are drawn as `?`.
+    /// - `x`, `y`: The window buffer position the first line is aligned to, at its top.
+    /// - `style`: The alignment and colours of the text.
+    /// - `window_buffer`: The pixels of the window buffer.
+    /// - `window_width`: The width of the window buffer in pixels.
+    pub fn draw(&self, text: &str, x: isize, y: isize, style: TextStyle, window_buffer: &mut [u32], window_width: usize) {
+        if let Some(shadow) = style.shadow {
+            self.draw_lines(text, (x + 1, y + 1), shadow, style.align, window_buffer, window_width);
+        }
+        self.draw_lines(text, (x, y), style.colour, style.align, window_buffer, window_width);
+    }
+
+    fn draw_lines(&self, text: &str, (x, y): (isize, isize), colour: u32, align: Align, window_buffer: &mut [u32], window_width: usize) {
+        let options = DrawOptions { flash: Some(colour), ..DrawOptions::default() };
+
+        for (row, line) in text.lines().enumerate() {
+            let width = self.line_width(line) as isize;
+            let mut glyph_x = match align {
+                Align::Left => x,
+                Align::Centre => x - width / 2,
+                Align::Right => x - width,
+            };
+            let glyph_y = y + (row as u32 * self.line_height) as isize;
+
+            for glyph in line.chars().filter_map(|character| self.glyph(character)) {
+                draw_sprite_with(glyph_x, glyph_y, &self.glyphs[glyph], window_buffer, window_width, options);
+                glyph_x += self.advances[glyph] as isize;
+            }
+        }
+    }
+}
@@ -1,3 +1,11 @@
 pub mod sprites; pub mod render_graphics;
 pub mod update_graphics;
+pub mod backend;
+pub mod camera;
 
+pub mod parallax;
+pub mod animation;
+pub mod atlas;
+pub mod aseprite;
+pub mod display;
+pub mod font;
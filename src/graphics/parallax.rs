@@ -0,0 +1,183 @@
+use std::fmt;
+
+use crate::graphics::animation::Clip;
+use crate::graphics::sprites::{draw_sprite, load_sprites_from_map, tint_sprites, SpriteFrame};
+use crate::state::constants::graphics::TICK_RATE;
+
+/// What a layer shows beyond its own edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat, // Tiled endlessly in the horizontal direction
+    None,   // Drawn once, leaving the rest of the screen to the layers below
+}
+
+/// One image of a map's scenery, scrolling at its own speed relative to the camera.
+pub struct ParallaxLayer {
+    pub frames: Vec<SpriteFrame>,
+    pub scroll_x: f32, // Share of the camera movement the layer follows: 0.0 is fixed to the screen, 1.0 moves with the world
+    pub scroll_y: f32,
+    pub x: f32, // Position of the layer when the camera is at the origin
+    pub y: f32,
+    pub wrap: Wrap,
+    pub animation: Clip, // Cycles through `frames`, whose indices it holds
+}
+
+/// Error raised while loading a parallax file, pointing at the offending line and column (both 1-based).
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParallaxError {
+    pub line: usize,
+    pub column: usize, // Start of the image path or option at fault
+    pub message: String,
+}
+
+impl fmt::Display for ParallaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl ParallaxLayer {
+    /// Returns the animation frame shown at the given simulation tick.
+    pub fn frame_at(&self, tick: u64) -> &SpriteFrame {
+        let index = self.animation.index_at(tick);
+        &self.frames[self.animation.frames[index]]
+    }
+}
+
+/// Parses a parallax file and loads the images of its layers, listed back to front.
+///
+/// Each non-empty line that does not start with `#` describes one layer: the image path
+/// followed by any of these options, e.g. `assets/sprites/layer_0.png frames=4 scroll_x=0.0625`:
+/// - `frames`: Number of animation frames side by side in the image (default 1)
+/// - `fps`: Animation frames per second (default 0, a still image)
+/// - `scroll_x`, `scroll_y`: Share of the camera movement the layer follows (default 1)
+/// - `x`, `y`: Position of the layer in pixels (default 0)
+/// - `wrap`: `repeat` to tile the layer horizontally, or `none` (default `repeat`)
+/// - `tint`: Colour multiplied onto the image as `RRGGBB` (default `FFFFFF`, unchanged)
+pub fn parse_parallax(source: &str) -> Result<Vec<ParallaxLayer>, ParallaxError> {
+    let mut layers = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let mut tokens = tokens_with_columns(line);
+        let Some((path_column, path)) = tokens.next().filter(|(_, path)| !path.starts_with('#')) else {
+            continue;
+        };
+
+        let error = |column: usize, message: String| ParallaxError { line: index + 1, column: column + 1, message };
+
+        let mut frames = 1;
+        let mut frames_column = path_column;
+        let mut frame_rate = 0.0;
+        let mut tint = 0xFFFFFF;
+        let mut layer = ParallaxLayer { frames: Vec::new(), scroll_x: 1.0, scroll_y: 1.0, x: 0.0, y: 0.0, wrap: Wrap::Repeat, animation: Clip::looping(vec![0], 1) };
+
+        for (column, option) in tokens {
+            let error = |message: String| error(column, message);
+            let (key, value) = option.split_once('=').ok_or_else(|| error(format!("expected 'option=value' but found '{}'", option)))?;
+            let number = || value.parse::<f32>().map_err(|_| error(format!("'{}' expects a number but found '{}'", key, value)));
+
+            match key {
+                "frames" => {
+                    frames = value.parse::<u32>().ok().filter(|&frames| frames > 0).ok_or_else(|| error(format!("'frames' expects a positive whole number but found '{}'", value)))?;
+                    frames_column = column;
+                }
+                "fps" => frame_rate = number()?,
+                "scroll_x" => layer.scroll_x = number()?,
+                "scroll_y" => layer.scroll_y = number()?,
+                "x" => layer.x = number()?,
+                "y" => layer.y = number()?,
+                "wrap" => layer.wrap = match value {
+                    "repeat" => Wrap::Repeat,
+                    "none" => Wrap::None,
+                    _ => return Err(error(format!("unknown wrap mode '{}'", value))),
+                },
+                "tint" => tint = u32::from_str_radix(value, 16).ok().filter(|_| value.len() == 6).ok_or_else(|| error(format!("'tint' expects a colour as RRGGBB but found '{}'", value)))?,
+                _ => return Err(error(format!("unknown option '{}'", key))),
+            }
+        }
+
+        let (width, height) = image::image_dimensions(path).map_err(|e| error(path_column, format!("{}: {}", path, e)))?;
+        if width / frames == 0 {
+            return Err(error(frames_column, format!("{} frames do not fit in {}, which is {} pixels wide", frames, path, width)));
+        }
+        let sheet = load_sprites_from_map(path, width / frames, height).map_err(|e| error(path_column, format!("{}: {}", path, e)))?;
+        layer.frames = if tint == 0xFFFFFF { sheet } else { tint_sprites(&sheet, tint) };
+
+        // A still layer keeps showing its first frame
+        if frame_rate > 0.0 {
+            layer.animation = Clip::looping((0..layer.frames.len()).collect(), (TICK_RATE as f32 / frame_rate).round() as u32);
+        }
+
+        layers.push(layer);
+    }
+
+    Ok(layers)
+}
+
+/// Splits a line on whitespace, keeping the character column at which each token starts (0-based).
+fn tokens_with_columns(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split_whitespace().map(move |token| {
+        let offset = token.as_ptr() as usize - line.as_ptr() as usize;
+        (line[..offset].chars().count(), token)
+    })
+}
+
+/// Draws a parallax layer across the whole window buffer for a camera at (`view_x`, `view_y`).
+///
+/// # Parameters
+/// - `layer`: The layer to draw.
+/// - `tick`: The current simulation tick, which selects the animation frame.
+/// - `view_x`, `view_y`: World position of the top left corner of the view.
+/// - `window_buffer`: The pixels of the window buffer.
+/// - `window_width`: The width of the window buffer in pixels.
+pub fn draw_parallax_layer(layer: &ParallaxLayer, tick: u64, view_x: f32, view_y: f32, window_buffer: &mut [u32], window_width: usize) {
+    let frame = layer.frame_at(tick);
+    let width = frame.width as isize;
+    let x = -(view_x * layer.scroll_x - layer.x).floor() as isize;
+    let y = -(view_y * layer.scroll_y - layer.y).floor() as isize;
+
+    match layer.wrap {
+        Wrap::Repeat => {
+            // Tile copies side by side from the one crossing the left edge until the screen is covered
+            let mut tile_x = x.rem_euclid(width.max(1)) - width;
+            while tile_x < window_width as isize {
+                draw_sprite(tile_x, y, frame, window_buffer, window_width);
+                tile_x += width.max(1);
+            }
+        }
+        Wrap::None => draw_sprite(x, y, frame, window_buffer, window_width),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_layer_options() {
+        let layers = parse_parallax("# Back to front\n\nassets/sprites/layer_0.png frames=4 scroll_x=0.5 wrap=none fps=5\n").unwrap();
+
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].frames.len(), 4);
+        assert_eq!(layers[0].scroll_x, 0.5);
+        assert_eq!(layers[0].wrap, Wrap::None);
+        assert_eq!(layers[0].animation.frames, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn reports_the_option_at_fault() {
+        let error = parse_parallax("assets/sprites/layer_0.png\n  assets/sprites/layer_1.png scroll_x=0 wrap=sideways\n").err().unwrap();
+
+        assert_eq!((error.line, error.column), (2, 41));
+        assert_eq!(error.to_string(), "2:41: unknown wrap mode 'sideways'");
+    }
+
+    #[test]
+    fn rejects_frames_narrower_than_a_pixel() {
+        let (width, _) = image::image_dimensions("assets/sprites/layer_0.png").unwrap();
+        let error = parse_parallax(&format!("assets/sprites/layer_0.png scroll_y=0 frames={}", width + 1)).err().unwrap();
+
+        assert_eq!((error.line, error.column), (1, 39));
+        assert!(error.message.contains("frames do not fit"), "{}", error.message);
+    }
+}
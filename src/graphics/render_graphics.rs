@@ -1,14 +1,137 @@
+use std::time::Instant;
+
+use crate::state::constants::graphics::{LETTERBOX_COLOUR, SCANLINE_BRIGHTNESS, WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH};
+use crate::graphics::display::fit_to_window;
 use crate::state::structs::GameState;
 
 pub fn render_pixel_buffer(game_state: &mut GameState) {
+    fit_to_window(game_state);
+
     // Scale the buffer to the screen resolution
-    scale_buffer(&game_state.window_buffer, &mut game_state.scaled_buffer, game_state.all_maps[game_state.current_map_index].width, game_state.all_maps[game_state.current_map_index].height, game_state.window_width, game_state.window_height);
+    game_state.scaler.scale(game_state.window_buffer, game_state.scaled_buffer);
 
     // Draw the scaled buffer onto the window
-    game_state.window.update_with_buffer(&game_state.scaled_buffer, game_state.window_width, game_state.window_height).unwrap();
+    game_state.display.present(game_state.scaled_buffer, game_state.window_width, game_state.window_height).unwrap_or_else(|e| {
+        panic!("Failed to present frame: {}", e);
+    });
+}
+
+/// How the window buffer is fitted into the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    Stretch, // Fills the whole window, stretching pixels to its aspect ratio
+    Integer, // Largest whole multiple of the buffer size which fits, centred with bars around it, so pixels stay square
+}
+
+impl ScaleMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "stretch" => Some(ScaleMode::Stretch),
+            "integer" => Some(ScaleMode::Integer),
+            _ => None,
+        }
+    }
+}
+
+/// Scales the window buffer up to the window size through lookup tables built once per window size.
+///
+/// Every window row and column maps to a source row and column, so scaling a frame is a copy
+/// per pixel, and window rows showing the same source row as the row above are copied whole.
+pub struct Scaler {
+    source_width: usize,
+    target_width: usize,
+    target_height: usize,
+    left: usize, // Position and size of the scaled image within the window, the rest is letterboxing
+    top: usize,
+    columns: Vec<usize>, // Source column of every column of the scaled image
+    rows: Vec<usize>, // Source row of every row of the scaled image
+    scanlines: Vec<bool>, // Rows of the scaled image darkened by the CRT filter
+}
+
+impl Scaler {
+    /// # Parameters
+    /// - `source_width`, `source_height`: Size of the window buffer in pixels.
+    /// - `target_width`, `target_height`: Size of the window in pixels.
+    /// - `mode`: How the buffer is fitted into the window.
+    /// - `crt`: Whether to darken the bottom line of every scaled pixel row, like the scanlines of a CRT.
+    pub fn new(source_width: usize, source_height: usize, target_width: usize, target_height: usize, mode: ScaleMode, crt: bool) -> Self {
+        let (width, height) = match mode {
+            ScaleMode::Stretch => (target_width, target_height),
+            ScaleMode::Integer => {
+                // Windows smaller than the buffer still show it, shrunk at a factor of 1
+                let factor = (target_width / source_width).min(target_height / source_height).max(1);
+                ((source_width * factor).min(target_width), (source_height * factor).min(target_height))
+            }
+        };
+
+        // The same float ratios as the original per-pixel scaler, so stretched frames come out identical
+        let x_ratio = source_width as f32 / width as f32;
+        let y_ratio = source_height as f32 / height as f32;
+        let columns: Vec<usize> = (0..width).map(|x| ((x as f32 * x_ratio).floor() as usize).min(source_width - 1)).collect();
+        let rows: Vec<usize> = (0..height).map(|y| ((y as f32 * y_ratio).floor() as usize).min(source_height - 1)).collect();
+
+        // Source rows shown on a single window row have no room for a scanline
+        let scanlines = (0..height).map(|y| {
+            let last_of_row = rows.get(y + 1) != Some(&rows[y]);
+            let tall = y > 0 && rows[y - 1] == rows[y];
+            crt && last_of_row && tall
+        }).collect();
+
+        Scaler {
+            source_width,
+            target_width,
+            target_height,
+            left: (target_width - width) / 2,
+            top: (target_height - height) / 2,
+            columns,
+            rows,
+            scanlines,
+        }
+    }
+
+    /// Scales `source` into `target`, which must hold a whole window of pixels.
+    pub fn scale(&self, source: &[u32], target: &mut [u32]) {
+        let (width, height) = (self.columns.len(), self.rows.len());
+        let scanline_brightness = (SCANLINE_BRIGHTNESS * 256.0) as u32;
+
+        // Letterboxing above and below the image
+        target[..self.top * self.target_width].fill(LETTERBOX_COLOUR);
+        target[(self.top + height) * self.target_width..self.target_height * self.target_width].fill(LETTERBOX_COLOUR);
+
+        for y in 0..height {
+            let start = (self.top + y) * self.target_width;
+            let row = &mut target[start..start + self.target_width];
+            row[..self.left].fill(LETTERBOX_COLOUR);
+            row[self.left + width..].fill(LETTERBOX_COLOUR);
+
+            if y > 0 && self.rows[y - 1] == self.rows[y] {
+                // Rows above a scanline are never darkened, so the row above can be copied as it is
+                target.copy_within(start - self.target_width + self.left..start - self.target_width + self.left + width, start + self.left);
+            } else {
+                let source_row = &source[self.rows[y] * self.source_width..(self.rows[y] + 1) * self.source_width];
+                for (pixel, &column) in row[self.left..self.left + width].iter_mut().zip(&self.columns) {
+                    *pixel = source_row[column];
+                }
+            }
+
+            if self.scanlines[y] {
+                for pixel in &mut target[start + self.left..start + self.left + width] {
+                    *pixel = darken(*pixel, scanline_brightness);
+                }
+            }
+        }
+    }
 }
 
-// Function to scale a buffer to a different resolution
+/// Multiplies the colour channels of a pixel by `brightness` / 256.
+fn darken(pixel: u32, brightness: u32) -> u32 {
+    let red_blue = (((pixel & 0x00FF00FF) * brightness) >> 8) & 0x00FF00FF;
+    let green = (((pixel & 0x0000FF00) * brightness) >> 8) & 0x0000FF00;
+    (pixel & 0xFF000000) | red_blue | green
+}
+
+/// The original scaler, which works out the source pixel with float multiplies for every window pixel.
+/// Kept as the baseline for `benchmark_scaling`.
 fn scale_buffer(src: &[u32], dst: &mut [u32], src_width: usize, src_height: usize, dst_width: usize, dst_height: usize) {
     let x_ratio = src_width as f32 / dst_width as f32;
     let y_ratio = src_height as f32 / dst_height as f32;
@@ -20,4 +143,39 @@ fn scale_buffer(src: &[u32], dst: &mut [u32], src_width: usize, src_height: usiz
             dst[y * dst_width + x] = src[src_y * src_width + src_x];
         }
     }
-}
\ No newline at end of file
+}
+
+/// Times scaling a frame to a `width` x `height` window with the original scaler and every `Scaler`
+/// setting, printing the average time per frame and whether stretching matches the original output.
+pub fn benchmark_scaling(width: usize, height: usize, frames: u32) {
+    // A noisy test pattern, so identical rows cannot hide mistakes
+    let source: Vec<u32> = (0..WINDOW_BUFFER_WIDTH * WINDOW_BUFFER_HEIGHT).map(|i| 0xFF000000 | (i as u32).wrapping_mul(2654435761) >> 8).collect();
+    let mut expected = vec![0; width * height];
+    let mut target = vec![0; width * height];
+    let frames = frames.max(1);
+
+    println!("Scaling {}x{} to {}x{}, average of {} frames:", WINDOW_BUFFER_WIDTH, WINDOW_BUFFER_HEIGHT, width, height, frames);
+
+    let start = Instant::now();
+    for _ in 0..frames {
+        scale_buffer(&source, &mut expected, WINDOW_BUFFER_WIDTH, WINDOW_BUFFER_HEIGHT, width, height);
+    }
+    println!("  original:       {:>8.3} ms", start.elapsed().as_secs_f64() * 1000.0 / frames as f64);
+
+    for (name, mode, crt) in [("stretch", ScaleMode::Stretch, false), ("stretch + crt", ScaleMode::Stretch, true), ("integer", ScaleMode::Integer, false), ("integer + crt", ScaleMode::Integer, true)] {
+        let scaler = Scaler::new(WINDOW_BUFFER_WIDTH, WINDOW_BUFFER_HEIGHT, width, height, mode, crt);
+
+        let start = Instant::now();
+        for _ in 0..frames {
+            scaler.scale(&source, &mut target);
+        }
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0 / frames as f64;
+
+        if mode == ScaleMode::Stretch && !crt {
+            let matches = if target == expected { "matches the original" } else { "DIFFERS from the original" };
+            println!("  {:<15} {:>8.3} ms, {}", format!("{}:", name), elapsed, matches);
+        } else {
+            println!("  {:<15} {:>8.3} ms", format!("{}:", name), elapsed);
+        }
+    }
+}
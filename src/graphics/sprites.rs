@@ -1,5 +1,7 @@
 use image::GenericImageView;
 
+use crate::graphics::aseprite::Rect;
+
 pub struct SpriteFrame {
     pub width: u32,  // Width of the sprite in pixels
     pub height: u32, // Height of the sprite in pixels
@@ -10,39 +12,12 @@ impl SpriteFrame {
     fn new(width: u32, height: u32, data: Vec<u32>) -> Self {
         Self { width, height, data }
     }
-}
 
-pub struct SpriteMaps {
-    pub player: Vec<SpriteFrame>,
-    pub kick: Vec<SpriteFrame>,
-    pub jump: Vec<SpriteFrame>,
-    pub shadow: Vec<SpriteFrame>,
-    pub game_over: Vec<SpriteFrame>,
-    pub metal_box: Vec<SpriteFrame>,
-    pub mountains: Vec<SpriteFrame>,
-    pub docks: Vec<SpriteFrame>,
-    pub sea: Vec<SpriteFrame>,
-    pub lighthouse: Vec<SpriteFrame>,
-    pub ground: Vec<SpriteFrame>,
-    pub heart: Vec<SpriteFrame>
-}
-
-impl SpriteMaps {
-    pub fn new() -> Self {
-        Self {
-            player: load_sprites_from_map("assets/sprites/player.png", 23, 33),
-            shadow: load_sprites_from_map("assets/sprites/shadow.png", 24, 10),
-            game_over: load_sprites_from_map("assets/sprites/game_over.png", 256, 224),
-            kick: load_sprites_from_map("assets/sprites/kick.png", 27, 33),
-            jump: load_sprites_from_map("assets/sprites/jump.png", 24, 34),
-            metal_box: load_sprites_from_map("assets/sprites/box.png", 16, 16),
-            mountains: load_sprites_from_map("assets/sprites/layer_0.png", 256, 224),
-            docks: load_sprites_from_map("assets/sprites/layer_1.png", 256, 224),
-            sea: load_sprites_from_map("assets/sprites/layer_2.png", 256, 224),
-            ground: load_sprites_from_map("assets/sprites/layer_3.png", 256, 224),
-            lighthouse: load_sprites_from_map("assets/sprites/layer_4.png", 256, 224),
-            heart: load_sprites_from_map("assets/sprites/heart.png", 14, 12),
-        }
+    /// Width up to and including the rightmost column with a visible pixel, 0 for an empty sprite.
+    pub fn visible_width(&self) -> u32 {
+        (0..self.width).rev()
+            .find(|&x| (0..self.height).any(|y| self.data[(y * self.width + x) as usize] >> 24 != 0))
+            .map_or(0, |x| x + 1)
     }
 }
 
@@ -57,10 +32,10 @@ impl SpriteMaps {
 /// - `sprite_height`: The height of each individual sprite in pixels.
 ///
 /// # Returns
-/// A vector containing tuples of sprite dimensions and pixel data.
-pub fn load_sprites_from_map(sprite_map_path: &str, sprite_width: u32, sprite_height: u32) -> Vec<SpriteFrame> {
+/// A vector containing tuples of sprite dimensions and pixel data, or an error if the image cannot be loaded.
+pub fn load_sprites_from_map(sprite_map_path: &str, sprite_width: u32, sprite_height: u32) -> Result<Vec<SpriteFrame>, String> {
     // Load the sprite map image
-    let sprite_map = image::open(sprite_map_path).expect(&format!("Failed to open sprite map at {}", sprite_map_path));
+    let sprite_map = image::open(sprite_map_path).map_err(|e| e.to_string())?;
     let (map_width, map_height) = sprite_map.dimensions();
 
     println!("Sprite map loaded from {}", sprite_map_path);
@@ -89,7 +64,28 @@ pub fn load_sprites_from_map(sprite_map_path: &str, sprite_width: u32, sprite_he
     println!("Total sprites extracted: {}\n", sprites.len());
 
     // Return the vector of sprites
-    sprites
+    Ok(sprites)
+}
+
+/// Creates recoloured copies of sprites by multiplying every pixel with a tint colour.
+///
+/// # Parameters
+/// - `sprites`: The sprites to recolour.
+/// - `tint`: The tint colour in ARGB format. The alpha channel of the tint is ignored.
+///
+/// # Returns
+/// A vector with one tinted sprite per input sprite.
+pub fn tint_sprites(sprites: &[SpriteFrame], tint: u32) -> Vec<SpriteFrame> {
+    sprites.iter().map(|sprite| {
+        let data = sprite.data.iter().map(|&pixel| tint_pixel(pixel, tint)).collect();
+        SpriteFrame::new(sprite.width, sprite.height, data)
+    }).collect()
+}
+
+/// Multiplies the colour channels of an ARGB pixel with those of `tint`, keeping the alpha channel of the pixel.
+fn tint_pixel(pixel: u32, tint: u32) -> u32 {
+    let multiply = |shift: u32| (((pixel >> shift) & 0xFF) * ((tint >> shift) & 0xFF) / 255) << shift;
+    (pixel & 0xFF000000) | multiply(16) | multiply(8) | multiply(0)
 }
 
 /// Converts an image to a buffer of u32 pixels in ARGB format.
@@ -114,13 +110,14 @@ pub fn img_to_buffer(img: &image::DynamicImage) -> Vec<u32> {
 /// Draws a sprite onto the window buffer at the specified coordinates, with alpha blending.
 ///
 /// # Parameters
-/// - `x`: The x-coordinate where the sprite will be drawn.
-/// - `y`: The y-coordinate where the sprite will be drawn.
+/// - `x`: The x-coordinate where the sprite will be drawn, which may be negative.
+/// - `y`: The y-coordinate where the sprite will be drawn, which may be negative.
 /// - `sprite`: A tuple containing the sprite's width, height, and pixel data. The pixel data is a vector of `u32` values representing RGBA colors.
 /// - `window_buffer`: A mutable slice of `u32` representing the pixels of the window buffer. Each `u32` value represents an RGBA color.
 /// - `window_width`: The width of the window in pixels.
 ///
 /// This function uses alpha blending to combine the sprite's pixels with the corresponding pixels in the window buffer. Only non-transparent pixels in the sprite are drawn.
+/// Pixels beyond any edge of the window buffer are left out, so a sprite can be drawn partly off screen.
 ///
 /// # Alpha Blending
 /// Alpha blending is a process used in computer graphics to combine a foreground image with a background image, resulting in a composite image.
@@ -129,7 +126,7 @@ pub fn img_to_buffer(img: &image::DynamicImage) -> Vec<u32> {
 /// - An alpha value of 255 (0xFF) means the pixel is completely opaque.
 ///
 /// The formula for alpha blending is:
-/// ```
+/// ```text
 /// blended_color = (foreground_color * alpha + background_color * (255 - alpha)) / 255
 /// ```
 ///
@@ -147,43 +144,102 @@ pub fn img_to_buffer(img: &image::DynamicImage) -> Vec<u32> {
 /// - Blue: 0xFF (255 in decimal, full intensity)
 ///
 /// # Example Usage
-/// ```rust
+/// ```ignore
 /// let sprite = (16, 16, vec![0x80FF00FF; 256]); // A semi-transparent magenta 16x16 sprite
 /// let mut window_buffer = vec![0xFFFFFFFF; 800 * 600]; // A white 800x600 window buffer
 /// draw_sprite(10, 10, &sprite, &mut window_buffer, 800);
 /// ```
-pub fn draw_sprite(x: usize, y: usize, sprite: &SpriteFrame, window_buffer: &mut [u32], window_width: usize) {
+pub fn draw_sprite(x: isize, y: isize, sprite: &SpriteFrame, window_buffer: &mut [u32], window_width: usize) {
+    draw_sprite_with(x, y, sprite, window_buffer, window_width, DrawOptions::default());
+}
+
+/// How `draw_sprite_with` transforms a sprite while drawing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawOptions {
+    pub flip_x: bool, // Mirror the sprite horizontally, e.g. to face left with frames drawn facing right
+    pub flip_y: bool, // Mirror the sprite vertically
+    pub opacity: f32, // Multiplied with the alpha channel of every pixel, from 0.0 (invisible) to 1.0
+    pub tint: u32, // Colour multiplied onto every pixel in RGB format, 0xFFFFFF leaves the sprite unchanged
+    pub flash: Option<u32>, // Colour in RGB format which replaces the colour of every visible pixel
+    pub clip: Option<Rect>, // Area of the window buffer drawn to, the whole buffer if None
+}
 
-    for row in 0..sprite.height as usize {
-        for col in 0..sprite.width as usize {
-            let sprite_pixel_index = row * (sprite.width as usize) + col;
-            let window_pixel_index = (y + row) * window_width + (x + col);
+impl Default for DrawOptions {
+    fn default() -> Self {
+        DrawOptions { flip_x: false, flip_y: false, opacity: 1.0, tint: 0xFFFFFF, flash: None, clip: None }
+    }
+}
 
-            if window_pixel_index < window_buffer.len() {
-                let sprite_pixel = sprite.data[sprite_pixel_index];
-                let sprite_alpha = (sprite_pixel >> 24) & 0xFF; // Extract alpha channel from sprite pixel
-                let sprite_rgb = sprite_pixel & 0x00FFFFFF; // Extract RGB channels from sprite pixel
+/// Draws a sprite like `draw_sprite`, flipped, faded, recoloured or clipped as described by `options`.
+///
+/// # Parameters
+/// - `x`, `y`: The window buffer position of the top left corner of the sprite, after flipping, which may be negative.
+/// - `sprite`: The sprite to draw.
+/// - `window_buffer`: The pixels of the window buffer.
+/// - `window_width`: The width of the window buffer in pixels.
+/// - `options`: The transformations applied to every pixel of the sprite.
+pub fn draw_sprite_with(x: isize, y: isize, sprite: &SpriteFrame, window_buffer: &mut [u32], window_width: usize, options: DrawOptions) {
+    let (width, height) = (sprite.width as isize, sprite.height as isize);
+    let opacity = (options.opacity.clamp(0.0, 1.0) * 255.0) as u32;
 
-                if sprite_alpha > 0 { // Only blend if the pixel is not fully transparent
-                    let window_pixel = window_buffer[window_pixel_index];
-                    let window_rgb = window_pixel & 0x00FFFFFF; // Extract RGB channels from window buffer pixel
+    // Only the part of the sprite inside both the window buffer and the clip rectangle is drawn
+    let window_height = (window_buffer.len() / window_width.max(1)) as isize;
+    let (mut left, mut top, mut right, mut bottom) = (0, 0, window_width as isize, window_height);
+    if let Some(clip) = options.clip {
+        left = left.max(clip.x as isize);
+        top = top.max(clip.y as isize);
+        right = right.min(clip.x as isize + clip.width as isize);
+        bottom = bottom.min(clip.y as isize + clip.height as isize);
+    }
+    let columns = (left - x).max(0)..(right - x).min(width);
+    let rows = (top - y).max(0)..(bottom - y).min(height);
 
-                    // Calculate blended color using alpha blending formula
-                    let blended_r = ((sprite_rgb >> 16) & 0xFF) * sprite_alpha / 255 + ((window_rgb >> 16) & 0xFF) * (255 - sprite_alpha) / 255;
-                    let blended_g = ((sprite_rgb >> 8) & 0xFF) * sprite_alpha / 255 + ((window_rgb >> 8) & 0xFF) * (255 - sprite_alpha) / 255;
-                    let blended_b = (sprite_rgb & 0xFF) * sprite_alpha / 255 + (window_rgb & 0xFF) * (255 - sprite_alpha) / 255;
+    for row in rows {
+        let source_row = if options.flip_y { height - 1 - row } else { row };
+        let window_row = ((y + row) as usize) * window_width;
 
-                    // Combine blended color with full alpha
-                    let blended_pixel = 0xFF000000 | (blended_r & 0xFF) << 16 | (blended_g & 0xFF) << 8 | (blended_b & 0xFF);
+        for col in columns.clone() {
+            let source_col = if options.flip_x { width - 1 - col } else { col };
+            let window_pixel_index = window_row + (x + col) as usize;
 
-                    // Assign the blended pixel to the window buffer
-                    window_buffer[window_pixel_index] = blended_pixel;
-                }
+            let mut pixel = sprite.data[(source_row * width + source_col) as usize];
+            if let Some(flash) = options.flash {
+                pixel = (pixel & 0xFF000000) | (flash & 0x00FFFFFF);
+            } else if options.tint & 0x00FFFFFF != 0xFFFFFF {
+                pixel = tint_pixel(pixel, options.tint);
+            }
+            if opacity < 255 {
+                pixel = ((pixel >> 24) * opacity / 255) << 24 | (pixel & 0x00FFFFFF);
             }
+
+            window_buffer[window_pixel_index] = alpha_blend(pixel, window_buffer[window_pixel_index]);
         }
     }
 }
 
+/// Blends a sprite pixel over a window buffer pixel, see `draw_sprite` for the formula.
+///
+/// # Returns
+/// The blended, fully opaque pixel, or `window_pixel` unchanged if the sprite pixel is fully transparent.
+pub fn alpha_blend(sprite_pixel: u32, window_pixel: u32) -> u32 {
+    let sprite_alpha = (sprite_pixel >> 24) & 0xFF; // Extract alpha channel from sprite pixel
+    let sprite_rgb = sprite_pixel & 0x00FFFFFF; // Extract RGB channels from sprite pixel
+
+    if sprite_alpha == 0 { // Only blend if the pixel is not fully transparent
+        return window_pixel;
+    }
+
+    let window_rgb = window_pixel & 0x00FFFFFF; // Extract RGB channels from window buffer pixel
+
+    // Calculate blended color using alpha blending formula
+    let blended_r = ((sprite_rgb >> 16) & 0xFF) * sprite_alpha / 255 + ((window_rgb >> 16) & 0xFF) * (255 - sprite_alpha) / 255;
+    let blended_g = ((sprite_rgb >> 8) & 0xFF) * sprite_alpha / 255 + ((window_rgb >> 8) & 0xFF) * (255 - sprite_alpha) / 255;
+    let blended_b = (sprite_rgb & 0xFF) * sprite_alpha / 255 + (window_rgb & 0xFF) * (255 - sprite_alpha) / 255;
+
+    // Combine blended color with full alpha
+    0xFF000000 | (blended_r & 0xFF) << 16 | (blended_g & 0xFF) << 8 | (blended_b & 0xFF)
+}
+
 /// Sets a pixel in the window buffer at the specified coordinates.
 ///
 /// # Parameters
@@ -1,182 +1,315 @@
-use crate::graphics::sprites::draw_sprite;
+use crate::graphics::font::{Align, TextStyle};
+use crate::graphics::parallax::draw_parallax_layer;
+use crate::graphics::sprites::{draw_sprite, draw_sprite_with, DrawOptions};
 
-use crate::state::constants::graphics::{FIXED_PLAYER_X, KICK_FRAME_DURATION, LEFT_JUMP_INITIATED, LEFT_JUMP_MID_AIR, RIGHT_JUMP_INITIATED, RIGHT_JUMP_MID_AIR, SHADOW_LARGE, SHADOW_MEDIUM, SHADOW_SMALL};
+use crate::state::constants::animation::{GAME_OVER, HEART, HEART_EMPTY, INVINCIBLE_FLASH_COLOUR, INVINCIBLE_FLASH_TICKS, JUMP_AIR, KICK, METAL_BOX, SHADOW, WALK};
+use crate::state::constants::graphics::{SHADOW_LARGE, SHADOW_MEDIUM, SHADOW_SMALL, STAGE_CLEAR_BRIGHTNESS, TICK_RATE, WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH};
+use crate::state::constants::hud::{DEBUG_COLOUR, MARGIN, SHADOW_COLOUR, TEXT_COLOUR};
+use crate::state::constants::input::REBINDING_BRIGHTNESS;
+use crate::state::constants::menu::{DIM_BRIGHTNESS, GAME_OVER_HEADING_Y, HEADING_GAP, HEADING_Y, HIGHLIGHT_COLOUR, HINT, TITLE};
+use crate::input::action::Action;
 use crate::state::constants::physics::{GROUND};
+use crate::state::scene::{menu, Scene};
 use crate::state::structs::Direction::{Left, Right};
 use crate::state::structs::GameState;
+use crate::state::timestep::interpolate;
+use crate::state::transition::transition_brightness;
 
 pub fn update_pixel_buffer(game_state: &mut GameState) {
     draw_game_world(game_state);
-    draw_player(game_state)
+
+    // Every scene draws its own screen over the world
+    match game_state.scene {
+        Scene::Title { .. } => draw_title_screen(game_state),
+        Scene::Playing => {
+            draw_player(game_state);
+            draw_hud(game_state);
+        }
+        Scene::Paused { .. } => {
+            draw_player(game_state);
+            draw_hud(game_state);
+            blend_screen(game_state, 0xFF000000, DIM_BRIGHTNESS);
+            draw_menu(game_state, "PAUSED", HEADING_Y);
+        }
+        Scene::GameOver { ticks, .. } => draw_game_over(game_state, ticks),
+        Scene::StageClear { .. } => draw_stage_clear(game_state),
+    }
+
+    draw_screen_effects(game_state);
 }
 
-fn draw_player(game_state: &mut GameState) {
+fn draw_screen_effects(game_state: &mut GameState) {
+    if let Some(frame) = game_state.transition_frame {
+        // Fade to black and back while switching maps
+        blend_screen(game_state, 0xFF000000, transition_brightness(frame));
+    } else if game_state.rebinding.is_some() {
+        draw_rebinding_screen(game_state);
+    }
+}
 
-    // Determine the current direction and action of the player
-    let direction = game_state.player.direction;
+/// Shows the first map dimmed behind the name of the game and the title menu.
+fn draw_title_screen(game_state: &mut GameState) {
+    blend_screen(game_state, 0xFF000000, DIM_BRIGHTNESS);
+    draw_menu(game_state, TITLE, HEADING_Y);
 
-    // Determine the sprite to draw
-    let sprite_to_draw =
+    let font = &game_state.font;
+    let (_, height) = font.measure(HINT);
+    let style = TextStyle { align: Align::Centre, colour: TEXT_COLOUR, shadow: Some(SHADOW_COLOUR) };
+    font.draw(HINT, WINDOW_BUFFER_WIDTH as isize / 2, WINDOW_BUFFER_HEIGHT as isize - MARGIN - height as isize, style, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+}
 
-    if game_state.player.is_kicking {
-        game_state.player.kick_frame_timer += 1;
-        if game_state.player.kick_frame_timer >= KICK_FRAME_DURATION as usize {
-            game_state.player.kick_frame += 1;
-            game_state.player.kick_frame_timer = 0;
+/// Plays the game over animation over the whole screen, followed by its menu.
+fn draw_game_over(game_state: &mut GameState, ticks: u64) {
+    let clip = game_state.sprites.clip(GAME_OVER);
+    let sprite = game_state.sprites.clip_frame(GAME_OVER, clip.index_at(ticks), Right);
+    draw_sprite(0, 0, sprite, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
 
-            if game_state.player.kick_frame >= 2 {
-                game_state.player.is_kicking = false;
-                game_state.player.kick_frame = 0;
-            }
-        }
+    if menu(game_state).is_some() {
+        draw_menu(game_state, "GAME OVER", GAME_OVER_HEADING_Y);
+    }
+}
+
+/// Washes out the final map once it has been completed and shows the final score and time.
+fn draw_stage_clear(game_state: &mut GameState) {
+    draw_player(game_state);
+    blend_screen(game_state, 0xFFFFFFFF, STAGE_CLEAR_BRIGHTNESS);
+
+    let heading = format!("STAGE CLEAR\n\nSCORE {}\nTIME {}", game_state.score, format_time(game_state.play_ticks));
+    draw_menu(game_state, &heading, HEADING_Y);
+}
+
+/// Draws `heading` centred with its top at `y`, followed by the menu of the scene if it shows one.
+fn draw_menu(game_state: &mut GameState, heading: &str, y: isize) {
+    let menu = menu(game_state);
+    let font = &game_state.font;
+    let centre = WINDOW_BUFFER_WIDTH as isize / 2;
+    let style = TextStyle { align: Align::Centre, colour: TEXT_COLOUR, shadow: Some(SHADOW_COLOUR) };
+    font.draw(heading, centre, y, style, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
 
-        // Select the correct kick frame based on direction
-        if direction == Right {
-            &game_state.sprites.kick[game_state.player.kick_frame]
+    let Some((items, selected)) = menu else {
+        return;
+    };
+    let (_, heading_height) = font.measure(heading);
+    let mut item_y = y + heading_height as isize + HEADING_GAP;
+
+    for (index, item) in items.iter().enumerate() {
+        let (text, colour) = if index == selected {
+            (format!("> {} <", item.label()), HIGHLIGHT_COLOUR)
         } else {
-            &game_state.sprites.kick[2 + game_state.player.kick_frame]
-        }
+            (item.label().to_string(), TEXT_COLOUR)
+        };
+        font.draw(&text, centre, item_y, TextStyle { colour, ..style }, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+        item_y += font.measure(&text).1 as isize;
     }
-    else if game_state.player.almost_ground && !game_state.player.on_obstacle && direction == Right {
-        &game_state.sprites.jump[RIGHT_JUMP_INITIATED]
-    } else if game_state.player.almost_ground && !game_state.player.on_obstacle && direction == Left {
-        &game_state.sprites.jump[LEFT_JUMP_INITIATED]
-    } else if !game_state.player.on_ground && !game_state.player.on_obstacle && direction == Right {
-        &game_state.sprites.jump[RIGHT_JUMP_MID_AIR]
-    } else if !game_state.player.on_ground && !game_state.player.on_obstacle && direction == Left {
-        &game_state.sprites.jump[LEFT_JUMP_MID_AIR]
-    } else if direction == Right {
-        &game_state.sprites.player[game_state.player.right_increment]
-    } else if direction == Left {
-        &game_state.sprites.player[game_state.player.left_increment]
-    } else { // Default is moving to the right
-        &game_state.sprites.player[game_state.player.right_increment]
+}
+
+/// Darkens the game and shows the pose of the action waiting for a key in the middle of the screen.
+fn draw_rebinding_screen(game_state: &mut GameState) {
+    blend_screen(game_state, 0xFF000000, REBINDING_BRIGHTNESS);
+
+    let sprites = &game_state.sprites;
+    let (clip, index, direction) = match game_state.rebinding.and_then(|index| Action::ALL.get(index)) {
+        Some(Action::MoveLeft) => (WALK, 0, Left),
+        Some(Action::MoveRight) => (WALK, 0, Right),
+        Some(Action::Jump) => (JUMP_AIR, 0, Right),
+        Some(Action::Kick) => (KICK, 1, Right),
+        Some(Action::Push) => (WALK, 1, Right),
+        Some(Action::Pause) | None => (HEART, 0, Right),
     };
+    let sprite = sprites.clip_frame(clip, index, direction);
 
-    // Draw the chosen player sprite
-    draw_sprite(
-        FIXED_PLAYER_X as usize,
-        game_state.player.y as usize - (sprite_to_draw.height - 10) as usize,
-        sprite_to_draw,
+    draw_sprite_with(
+        (WINDOW_BUFFER_WIDTH as isize - sprite.width as isize) / 2,
+        (WINDOW_BUFFER_HEIGHT as isize - sprite.height as isize) / 2,
+        sprite,
         game_state.window_buffer,
-        game_state.all_maps[game_state.current_map_index].width
+        WINDOW_BUFFER_WIDTH,
+        DrawOptions { flip_x: sprites.clip(clip).is_flipped(direction), ..DrawOptions::default() },
     );
+}
+
+/// Mixes every pixel of the window buffer with `target`, keeping `amount` (0.0 - 1.0) of the original colour.
+fn blend_screen(game_state: &mut GameState, target: u32, amount: f32) {
+    for pixel in game_state.window_buffer.iter_mut() {
+        *pixel = blend_pixel(*pixel, target, amount);
+    }
+}
+
+/// Mixes `pixel` with `target`, keeping `amount` (0.0 - 1.0) of the original pixel.
+fn blend_pixel(pixel: u32, target: u32, amount: f32) -> u32 {
+    let channel = |shift: u32| {
+        let source = ((pixel >> shift) & 0xFF) as f32;
+        let destination = ((target >> shift) & 0xFF) as f32;
+        ((source * amount + destination * (1.0 - amount)) as u32 & 0xFF) << shift
+    };
+
+    0xFF000000 | channel(16) | channel(8) | channel(0)
+}
+
+fn draw_player(game_state: &mut GameState) {
+    let sprite_to_draw = game_state.player.animator.frame(&game_state.sprites, game_state.player.direction);
+    let mut options = game_state.player.animator.draw_options(&game_state.sprites, game_state.player.direction);
+
+    // Flash white while invincible after taking damage
+    if game_state.player.invincible && (game_state.tick / INVINCIBLE_FLASH_TICKS).is_multiple_of(2) {
+        options.flash = Some(INVINCIBLE_FLASH_COLOUR);
+    }
+
+    // Draw the chosen player sprite
+    let view = game_state.camera.view(game_state.render_alpha);
+    let player_x = interpolate(game_state.player.previous_x, game_state.player.x, game_state.render_alpha);
+    let player_y = interpolate(game_state.player.previous_y, game_state.player.y, game_state.render_alpha);
+    if let Some((x, y)) = view.place(player_x, player_y - (sprite_to_draw.height - 10) as f32, sprite_to_draw.width, sprite_to_draw.height) {
+        draw_sprite_with(x, y, sprite_to_draw, game_state.window_buffer, WINDOW_BUFFER_WIDTH, options);
+    }
 
     // Draw different sizes of shadows based on player state
+    let shadows = game_state.sprites.sheet(SHADOW);
     let shadow_sprite = if game_state.player.on_ground {
-            &game_state.sprites.shadow[SHADOW_SMALL]
+            &shadows[SHADOW_SMALL]
     } else if game_state.player.almost_ground {
-            &game_state.sprites.shadow[SHADOW_MEDIUM]
+            &shadows[SHADOW_MEDIUM]
     } else { // Player is in the air
-            &game_state.sprites.shadow[SHADOW_LARGE]
+            &shadows[SHADOW_LARGE]
     };
 
     // Draw associated shadow if not on or above obstacle
     if !game_state.player.on_obstacle && !game_state.player.above_obstacle {
-        draw_sprite(
-            FIXED_PLAYER_X as usize,
-            GROUND as usize + 7,
-            shadow_sprite,
-            game_state.window_buffer,
-            game_state.all_maps[game_state.current_map_index].width
-        );
-
+        if let Some((x, y)) = view.place(player_x, GROUND + 7.0, shadow_sprite.width, shadow_sprite.height) {
+            draw_sprite(x, y, shadow_sprite, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+        }
     }
 }
 
 fn draw_game_world(game_state: &mut GameState) {
     draw_map(game_state);
+    draw_hazards(game_state);
     draw_obstacles(game_state);
-    draw_hearts(game_state);
+    draw_enemies(game_state);
 }
 
 fn draw_map(game_state: &mut GameState) {
-    let texture_width = game_state.all_maps[game_state.current_map_index].width;
+    let view = game_state.camera.view(game_state.render_alpha);
 
-    // Always draw the static background layer first in order to fill all pixels as the parallax effect can result in empty pixels
-    draw_sprite(0, 0, &game_state.sprites.mountains[game_state.mountains_sprite_frame_index], game_state.window_buffer, game_state.all_maps[game_state.current_map_index].width);
+    // The first layer is expected to cover the whole screen, as nothing clears the buffer beneath it
+    for layer in game_state.all_maps[game_state.current_map_index].parallax.iter() {
+        draw_parallax_layer(layer, game_state.tick, view.x, view.y, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+    }
+}
 
-    // Loop through the layers and draw them based on the player's position in relation to the divisor to achieve parallax scrolling
-    for (i, divisor) in [16, 6, 6, 4, 1].iter().enumerate() {
+fn draw_obstacles(game_state: &mut GameState) {
+    let view = game_state.camera.view(game_state.render_alpha);
 
-        // // Layer 0 will have offset divided by 16, layer 1 by 6, layer 2 by 4, and layer 3 by 1
-        let offset_x = game_state.player.x as usize / divisor % texture_width;
-        let offset_y = game_state.player.y as usize / 666;
+    let map = &game_state.all_maps[game_state.current_map_index];
 
-        let layer = match i {
-            0 => &game_state.sprites.mountains[game_state.mountains_sprite_frame_index],
-            1 => &game_state.sprites.docks[0],
-            2 => &game_state.sprites.lighthouse[game_state.lighthouse_sprite_frame_index],
-            3 => &game_state.sprites.sea[0],
-            4 => &game_state.sprites.ground[game_state.ground_sprite_frame_index],
-            _ => unreachable!(),
-        };
+    // Draw the obstacles, which have a metal box sprite of 3 different frames based on durability
+    for obstacle in map.obstacles_near(view.area()).into_iter().map(|id| &map.obstacles[id]).filter(|obstacle| obstacle.active) {
+        let metal_boxes = game_state.sprites.sheet(METAL_BOX);
+        let metal_box_sprite =
+            if obstacle.durability == 2 {
+                &metal_boxes[0] // undamaged
+            } else if obstacle.durability == 1 {
+                &metal_boxes[1] // slightly damaged
+            } else {
+                &metal_boxes[2] // damaged
+            };
 
-        draw_sprite(
-            (game_state.window_width).saturating_sub(offset_x),
-            offset_y,
-            layer,
-            game_state.window_buffer,
-            game_state.all_maps[game_state.current_map_index].width,
-        );
+        // Only draw obstacles that are within the view
+        if let Some((x, y)) = view.place(obstacle.x_left, obstacle.y_bottom, metal_box_sprite.width, metal_box_sprite.height) {
+            draw_sprite(x, y, metal_box_sprite, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+        }
     }
 }
 
-fn draw_obstacles(game_state: &mut GameState) {
-    // Draw the obstacles, which have a metal box sprite of 3 different frames based on durability
-    game_state.all_maps[game_state.current_map_index].obstacles.iter().enumerate().for_each(|(index, obstacle)| {
-        if obstacle.active {
-            let relative_x = calculate_relative_x(obstacle.x_left as isize, game_state.player.x as isize);
-
-            println!("player.x: {}, fixed player.x: {}, window_width/4 {} obstacle x.left: {}, final x : {}", game_state.player.x, FIXED_PLAYER_X, game_state.window_width / 4,
-                     obstacle.x_left, relative_x);
-
-            // Only draw obstacles that are within the window width
-            if relative_x < game_state.window_width / 4 && relative_x > 0 {
-                let metal_box_sprite =
-                    if obstacle.durability == 2 {
-                        &game_state.sprites.metal_box[0] // undamaged
-                    } else if obstacle.durability == 1 {
-                        &game_state.sprites.metal_box[1] // slightly damaged
-                    } else {
-                        &game_state.sprites.metal_box[2] // damaged
-                    };
-
-
-                draw_sprite(relative_x, obstacle.y_bottom as usize, metal_box_sprite, game_state.window_buffer, game_state.all_maps[game_state.current_map_index].width);
-            }
+fn draw_hazards(game_state: &mut GameState) {
+    let view = game_state.camera.view(game_state.render_alpha);
+
+    // Covered traps are hidden beneath the box sealing them
+    for hazard in game_state.all_maps[game_state.current_map_index].hazards.iter().filter(|hazard| hazard.active) {
+        let sprite = hazard.animator.frame(&game_state.sprites, Right);
+
+        if let Some((x, y)) = view.place(hazard.x_left, hazard.y_bottom, sprite.width, sprite.height) {
+            draw_sprite(x, y, sprite, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
         }
-    });
+    }
 }
 
+fn draw_enemies(game_state: &mut GameState) {
+    let view = game_state.camera.view(game_state.render_alpha);
+
+    for enemy in game_state.all_maps[game_state.current_map_index].enemies.iter() {
+        let sprite = enemy.animator.frame(&game_state.sprites, enemy.direction);
+        let options = enemy.animator.draw_options(&game_state.sprites, enemy.direction);
+
+        let enemy_x = interpolate(enemy.previous_x, enemy.x, game_state.render_alpha);
+        let enemy_y = interpolate(enemy.previous_y, enemy.y, game_state.render_alpha) - (sprite.height - 10) as f32;
+
+        if let Some((x, y)) = view.place(enemy_x, enemy_y, sprite.width, sprite.height) {
+            draw_sprite_with(x, y, sprite, game_state.window_buffer, WINDOW_BUFFER_WIDTH, options);
+        }
+    }
+}
 
 fn draw_hearts(game_state: &mut GameState) {
-    let heart_sprite_width = game_state.sprites.heart[game_state.heart_sprite_frame_index].width as usize;
-
-    // Assign a triple of indices based on the player's health as there are 3 independent hearts
-    let health_triple = match game_state.player.health {
-        3 => [0, 0, 0],
-        2 => [0, 0, 2],
-        1 => [0, 2, 2],
-        0 => [2, 2, 2],
-        _ => [2, 2, 2],
-    };
+    let full_heart = game_state.heart_animator.frame(&game_state.sprites, Right);
+    let empty_heart = game_state.sprites.clip_frame(HEART_EMPTY, 0, Right);
 
-    for (i, &triple) in health_triple.iter().enumerate() {
-        // Only alternate between the heart_sprite index if the triple value is less than 2
-        let heart_index = if triple < 2 { game_state.heart_sprite_frame_index } else { triple };
+    // Draw the 3 hearts in the top left corner of the screen, emptying them from the right as health is lost
+    for i in 0..3 {
+        let heart_sprite = if i < game_state.player.health as usize { full_heart } else { empty_heart };
 
-        // Draw the hearts in the top left corner of the screen
         draw_sprite(
-            i * (heart_sprite_width + 1),
+            (i * (heart_sprite.width as usize + 1)) as isize,
             0,
-            &game_state.sprites.heart[heart_index],
+            heart_sprite,
             game_state.window_buffer,
-            game_state.all_maps[game_state.current_map_index].width,
+            WINDOW_BUFFER_WIDTH,
         );
     }
 }
 
-fn calculate_relative_x(world_object_x: isize, world_player_x: isize) -> usize {
-    (world_object_x - world_player_x + FIXED_PLAYER_X).max(0) as usize
-}
\ No newline at end of file
+fn draw_hud(game_state: &mut GameState) {
+    draw_hearts(game_state);
+
+    let font = &game_state.font;
+    let style = TextStyle { colour: TEXT_COLOUR, shadow: Some(SHADOW_COLOUR), ..TextStyle::default() };
+    let right = WINDOW_BUFFER_WIDTH as isize - MARGIN;
+
+    // Level name at the top in the middle, score and time in the top right corner
+    let title = level_title(&game_state.all_maps[game_state.current_map_index].name);
+    font.draw(&title, WINDOW_BUFFER_WIDTH as isize / 2, MARGIN, TextStyle { align: Align::Centre, ..style }, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+
+    let score_and_time = format!("SCORE {}\nTIME {}", game_state.score, format_time(game_state.play_ticks));
+    font.draw(&score_and_time, right, MARGIN, TextStyle { align: Align::Right, ..style }, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+
+    if game_state.show_debug {
+        draw_debug_info(game_state);
+    }
+}
+
+/// Shows the state of the simulation in the bottom left corner while toggled on with F3.
+fn draw_debug_info(game_state: &mut GameState) {
+    let map = &game_state.all_maps[game_state.current_map_index];
+    let player = &game_state.player;
+    let text = format!(
+        "TICK {}\nPLAYER {:.1}, {:.1}\nVELOCITY {:.2}, {:.2}\nCAMERA {:.1}, {:.1}\nENEMIES {} BOXES {} TRAPS {}",
+        game_state.tick, player.x, player.y, player.vx, player.vy, game_state.camera.x, game_state.camera.y,
+        map.enemies.len(), map.obstacles.iter().filter(|obstacle| obstacle.active).count(), map.hazards.iter().filter(|hazard| hazard.active).count(),
+    );
+
+    let font = &game_state.font;
+    let (_, height) = font.measure(&text);
+    let style = TextStyle { colour: DEBUG_COLOUR, shadow: Some(SHADOW_COLOUR), ..TextStyle::default() };
+    font.draw(&text, MARGIN, WINDOW_BUFFER_HEIGHT as isize - MARGIN - height as isize, style, game_state.window_buffer, WINDOW_BUFFER_WIDTH);
+}
+
+/// Formats a number of ticks as minutes and seconds, e.g. `1:05`.
+fn format_time(ticks: u64) -> String {
+    let seconds = ticks / TICK_RATE as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Turns the file name of a level into the name shown on screen, e.g. `02_docks` into `DOCKS`.
+fn level_title(name: &str) -> String {
+    name.trim_start_matches(|character: char| character.is_ascii_digit() || character == '_').replace('_', " ").to_uppercase()
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use minifb::Key;
+
+use crate::graphics::backend::DisplayBackend;
+
+/// Something the player can do, independent of the keys it is bound to.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Kick,
+    Pause,
+    Push, // Held with a move key to push a box, or with kick to launch it
+}
+
+impl Action {
+    /// Every action, in the order of their bits in an action mask. New actions go last so recordings keep their meaning.
+    pub const ALL: [Action; 6] = [Action::MoveLeft, Action::MoveRight, Action::Jump, Action::Kick, Action::Pause, Action::Push];
+
+    /// Name used in the controls file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Jump => "jump",
+            Action::Kick => "kick",
+            Action::Pause => "pause",
+            Action::Push => "push",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// Bit standing for this action in an action mask.
+    pub fn bit(self) -> u8 {
+        1 << Action::ALL.iter().position(|&action| action == self).unwrap()
+    }
+
+    /// Whether holding a key repeats the action. Actions which toggle something only fire once per press.
+    fn repeats(self) -> bool {
+        self != Action::Pause
+    }
+
+    /// Whether the action happens once when its key goes down, like a kick, rather than for as long as it is held.
+    fn is_press(self) -> bool {
+        matches!(self, Action::Jump | Action::Kick)
+    }
+
+    /// Leaves only the actions of `mask` which last while their key is held, for the ticks which
+    /// catch up after the first tick of a frame: a single key press must not kick once per tick.
+    pub fn held_only(mask: u8) -> u8 {
+        Action::ALL.into_iter().filter(|action| action.is_press()).fold(mask, |mask, action| mask & !action.bit())
+    }
+}
+
+/// Keys which can be bound, looked up by the name minifb gives them (e.g. `Space`, `Left`, `Key1`).
+const BINDABLE_KEYS: [Key; 78] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    Key::NumPad0, Key::NumPad1, Key::NumPad2, Key::NumPad3, Key::NumPad4, Key::NumPad5, Key::NumPad6, Key::NumPad7, Key::NumPad8, Key::NumPad9,
+    Key::Up, Key::Down, Key::Left, Key::Right,
+    Key::Space, Key::Enter, Key::Tab, Key::Backspace, Key::Insert, Key::Delete, Key::Home, Key::End, Key::PageUp, Key::PageDown,
+    Key::LeftShift, Key::RightShift, Key::LeftCtrl, Key::RightCtrl, Key::LeftAlt, Key::RightAlt,
+    Key::Comma, Key::Period, Key::Slash, Key::Backslash, Key::Semicolon, Key::Apostrophe, Key::Minus, Key::Equal,
+    Key::LeftBracket, Key::RightBracket, Key::Backquote, Key::NumPadEnter,
+];
+
+pub fn key_name(key: Key) -> String {
+    format!("{:?}", key)
+}
+
+pub fn key_from_name(name: &str) -> Option<Key> {
+    BINDABLE_KEYS.into_iter().find(|&key| key_name(key).eq_ignore_ascii_case(name))
+}
+
+/// Returns true if `key` can be bound to an action. Escape always quits, F1 opens the rebinding screen and F11 toggles fullscreen.
+pub fn is_bindable(key: Key) -> bool {
+    BINDABLE_KEYS.contains(&key)
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(PathBuf, io::Error),
+    Parse { path: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingsError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            BindingsError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+/// The keys bound to each action. Any of an action's keys triggers it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    keys: HashMap<Action, Vec<Key>>,
+}
+
+impl Default for Bindings {
+    /// Both the letter keys and the arrow keys work out of the box.
+    fn default() -> Self {
+        let mut keys = HashMap::new();
+        keys.insert(Action::MoveLeft, vec![Key::A, Key::Left]);
+        keys.insert(Action::MoveRight, vec![Key::D, Key::Right]);
+        keys.insert(Action::Jump, vec![Key::Space, Key::Up]);
+        keys.insert(Action::Kick, vec![Key::X, Key::LeftCtrl]);
+        keys.insert(Action::Pause, vec![Key::P, Key::Enter]);
+        keys.insert(Action::Push, vec![Key::LeftShift, Key::RightShift]);
+
+        Bindings { keys }
+    }
+}
+
+impl Bindings {
+    /// Loads the bindings from a controls file with one `action = key, key` line per action.
+    /// Actions missing from the file keep their default keys.
+    pub fn load(path: &Path) -> Result<Self, BindingsError> {
+        let source = fs::read_to_string(path).map_err(|e| BindingsError::Io(path.to_path_buf(), e))?;
+        let parse_error = |line: usize, message: String| BindingsError::Parse { path: path.to_path_buf(), line, message };
+
+        let mut bindings = Bindings::default();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, keys) = line.split_once('=').ok_or_else(|| parse_error(index + 1, "expected 'action = key, key'".to_string()))?;
+            let action = Action::from_name(name.trim()).ok_or_else(|| parse_error(index + 1, format!("unknown action '{}'", name.trim())))?;
+            let keys = keys.split(',')
+                .map(|key| key_from_name(key.trim()).ok_or_else(|| parse_error(index + 1, format!("unknown key '{}'", key.trim()))))
+                .collect::<Result<Vec<Key>, BindingsError>>()?;
+
+            bindings.keys.insert(action, keys);
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BindingsError> {
+        let mut source = String::from("# Controls: action = key, key (see README.md for key names)\n");
+        for action in Action::ALL {
+            let keys: Vec<String> = self.keys(action).iter().map(|&key| key_name(key)).collect();
+            source.push_str(&format!("{} = {}\n", action.name(), keys.join(", ")));
+        }
+
+        fs::write(path, source).map_err(|e| BindingsError::Io(path.to_path_buf(), e))
+    }
+
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.keys.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    /// Binds `key` to `action` alone, taking it away from any other action.
+    pub fn rebind(&mut self, action: Action, key: Key) {
+        for keys in self.keys.values_mut() {
+            keys.retain(|&bound| bound != key);
+        }
+        self.keys.insert(action, vec![key]);
+    }
+
+    /// Returns the actions whose keys are currently held as a bit mask (see `Action::bit`).
+    pub fn action_mask(&self, display: &dyn DisplayBackend) -> u8 {
+        Action::ALL.into_iter().fold(0, |mask, action| {
+            let held = self.keys(action).iter().any(|&key| {
+                if action.repeats() { display.is_key_pressed(key) } else { display.is_key_down(key) }
+            });
+            if held { mask | action.bit() } else { mask }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use minifb::Key;
+
+    use crate::graphics::backend::{HeadlessBackend, KeyEvent};
+
+    use super::{Action, Bindings, BindingsError};
+
+    /// Path of a controls file for the test `name`, which is written by the test itself.
+    fn controls_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("age_of_panda_{}_{}.cfg", std::process::id(), name))
+    }
+
+    fn load(name: &str, source: &str) -> Result<Bindings, BindingsError> {
+        let path = controls_file(name);
+        fs::write(&path, source).unwrap();
+        let bindings = Bindings::load(&path);
+        fs::remove_file(&path).unwrap();
+        bindings
+    }
+
+    #[test]
+    fn saved_bindings_load_back_unchanged() {
+        let mut bindings = Bindings::default();
+        bindings.rebind(Action::Kick, Key::K);
+        bindings.rebind(Action::Push, Key::NumPad0);
+        let path = controls_file("round_trip");
+
+        bindings.save(&path).unwrap();
+        let loaded = Bindings::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), bindings);
+    }
+
+    #[test]
+    fn an_action_takes_every_key_listed_and_others_keep_their_defaults() {
+        let bindings = load("several_keys", "# Comment\n\nkick = X, k ,Enter\n").unwrap();
+
+        assert_eq!(bindings.keys(Action::Kick), [Key::X, Key::K, Key::Enter]);
+        assert_eq!(bindings.keys(Action::Jump), Bindings::default().keys(Action::Jump));
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_are_rejected_with_their_line() {
+        let unknown_action = load("unknown_action", "kick = X\nfly = Space\n");
+        assert!(matches!(unknown_action, Err(BindingsError::Parse { line: 2, message, .. }) if message.contains("'fly'")));
+
+        let unknown_key = load("unknown_key", "kick = X, Banana\n");
+        assert!(matches!(unknown_key, Err(BindingsError::Parse { line: 1, message, .. }) if message.contains("'Banana'")));
+
+        // Escape always quits, so it cannot be bound
+        let reserved_key = load("reserved_key", "jump = Escape\n");
+        assert!(matches!(reserved_key, Err(BindingsError::Parse { line: 1, .. })));
+
+        let missing_keys = load("missing_keys", "jump\n");
+        assert!(matches!(missing_keys, Err(BindingsError::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn rebinding_a_key_takes_it_from_its_previous_action() {
+        let mut bindings = Bindings::default();
+
+        bindings.rebind(Action::Kick, Key::Space);
+
+        assert_eq!(bindings.keys(Action::Kick), [Key::Space]);
+        assert_eq!(bindings.keys(Action::Jump), [Key::Up]);
+    }
+
+    #[test]
+    fn the_action_mask_holds_the_actions_of_any_of_their_keys() {
+        let script = [Key::Left, Key::X, Key::M].map(|key| KeyEvent { frame: 0, key, pressed: true }).to_vec();
+        let display = HeadlessBackend::new(script, u64::MAX);
+
+        assert_eq!(Bindings::default().action_mask(&display), Action::MoveLeft.bit() | Action::Kick.bit());
+    }
+}
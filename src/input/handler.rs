@@ -2,56 +2,122 @@ use crate::input::jump::Jump;
 use crate::input::kick::Kick;
 use crate::input::move_left::MoveLeft;
 use crate::input::move_right::MoveRight;
-use minifb::{Key, KeyRepeat};
-use rodio::Sink;
+use crate::input::push::Push;
+use minifb::Key;
+use crate::input::action::{is_bindable, key_name, Action};
+use crate::audio::mixer::Mixer;
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
-use crate::state::core_logic::decrease_velocity;
+use crate::state::constants::input::CONTROLS_FILE;
+use crate::state::core_logic::{decrease_velocity, CoreLogic};
 use crate::state::structs::GameState;
 
-pub fn handle_user_input(game_state: &mut GameState, commands: &InputLogicMap, sink: &mut Sink) {
+pub fn handle_user_input(game_state: &mut GameState, commands: &InputLogicMap, mixer: &mut Mixer) {
 
-    let legal_keys = [Key::Space, Key::D, Key::A, Key::X];
-    let mut any_key_pressed = false;
+    // The held actions are a bit mask, read once per frame by `simulate_frame`, so they can be recorded and replayed
+    let frame_actions = game_state.frame_actions;
+    let pressed = game_state.input_mode.next(game_state.tick, || frame_actions);
+    game_state.held_actions = pressed;
 
-    for key in legal_keys.iter() {
-        if game_state.window.is_key_pressed(*key, KeyRepeat::Yes) {
-            any_key_pressed = true;
-            if !game_state.player.invincible {
-                delegate_command(*key, &commands, game_state, sink);
+    // The player has no control while being knocked back or while leaving a map
+    let controls_locked = game_state.player.invincible || game_state.transition_frame.is_some();
+    let mut any_action_pressed = false;
+
+    for action in Action::ALL {
+        if pressed & action.bit() != 0 && action != Action::Pause {
+            // Push only changes what the other actions do, so holding it alone still slows the player down
+            any_action_pressed |= action != Action::Push;
+            if !controls_locked {
+                delegate_command(action, commands, game_state, mixer);
             }
         }
     }
 
-    // If no legal was pressed, decelerate the player to avoid sliding forever
-    if !any_key_pressed {
+    // If no action was pressed, decelerate the player to avoid sliding forever
+    if !any_action_pressed {
         decrease_velocity(game_state);
     }
 
 }
 
-fn delegate_command(key: Key, commands: &InputLogicMap, game_state: &mut GameState, sink: &mut Sink) {
-    if let Some(command) = commands.get(&key) {
-        command.execute(game_state, sink);
+fn delegate_command(action: Action, commands: &InputLogicMap, game_state: &mut GameState, mixer: &mut Mixer) {
+    if let Some(command) = commands.get(&action) {
+        command.execute(game_state, mixer);
     } else {
-        println!("No command associated with key: {:?}", key);
+        println!("No command associated with action: {:?}", action);
+    }
+}
+
+/// Runs the rebinding screen opened with F1: each action in turn is bound to the next key pressed,
+/// after which the bindings are saved to the controls file. Called once per presented frame.
+pub fn handle_rebinding(game_state: &mut GameState) {
+    let keys = game_state.display.keys_pressed();
+
+    let Some(index) = game_state.rebinding else {
+        if keys.contains(&Key::F1) {
+            game_state.rebinding = Some(0);
+            println!("Rebinding controls, press a key for {}", Action::ALL[0].name());
+        }
+        return;
+    };
+
+    // Once every action is bound, wait for the keys to be let go so the game does not act on the last one
+    if index == Action::ALL.len() {
+        let display = &game_state.display;
+        let bindings = &game_state.bindings;
+        let any_held = Action::ALL.iter().any(|&action| bindings.keys(action).iter().any(|&key| display.is_key_down(key)));
+        if !any_held {
+            game_state.rebinding = None;
+        }
+        return;
+    }
+
+    let Some(&key) = keys.iter().find(|&&key| is_bindable(key)) else {
+        return;
+    };
+
+    let action = Action::ALL[index];
+    game_state.bindings.rebind(action, key);
+    game_state.rebinding = Some(index + 1);
+    println!("Bound {} to {}", action.name(), key_name(key));
+
+    if let Some(next) = Action::ALL.get(index + 1) {
+        println!("Press a key for {}", next.name());
+    } else {
+        match game_state.bindings.save(Path::new(CONTROLS_FILE)) {
+            Ok(()) => println!("Saved controls to {}", CONTROLS_FILE),
+            Err(e) => println!("Failed to save controls: {}", e),
+        }
+    }
+}
+
+/// Core logic system which runs the commands of all pressed actions.
+pub struct ProcessInput {
+    pub commands: InputLogicMap,
+}
+
+impl CoreLogic for ProcessInput {
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer) {
+        handle_user_input(game_state, &self.commands, mixer);
     }
 }
 
 pub trait InputLogic {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink);
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer);
 }
 
 
-pub type InputLogicMap = HashMap<Key, Arc<dyn InputLogic>>;
+pub type InputLogicMap = HashMap<Action, Arc<dyn InputLogic>>;
 
 pub fn initialize_input_logic_map() -> InputLogicMap {
     let mut logic_map: InputLogicMap = HashMap::new();
 
-    logic_map.insert(Key::A, Arc::new(MoveLeft));
-    logic_map.insert(Key::D, Arc::new(MoveRight));
-    logic_map.insert(Key::Space, Arc::new(Jump));
-    logic_map.insert(Key::X, Arc::new(Kick));
+    logic_map.insert(Action::MoveLeft, Arc::new(MoveLeft));
+    logic_map.insert(Action::MoveRight, Arc::new(MoveRight));
+    logic_map.insert(Action::Jump, Arc::new(Jump));
+    logic_map.insert(Action::Kick, Arc::new(Kick));
+    logic_map.insert(Action::Push, Arc::new(Push));
 
     logic_map
 }
@@ -1,6 +1,5 @@
-use minifb::Key;
-use rodio::Sink;
-use crate::audio::engine::append_source_source;
+use crate::input::action::Action;
+use crate::audio::mixer::Mixer;
 use crate::input::handler::InputLogic;
 use crate::state::constants::audio::JUMP_SOUND;
 use crate::state::constants::physics::JUMP_VELOCITY;
@@ -9,16 +8,16 @@ use crate::state::structs::GameState;
 pub struct Jump;
 
 impl InputLogic for Jump {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer) {
 
         if !game_state.player.is_jumping && (game_state.player.on_ground || game_state.player.on_obstacle) {
             game_state.player.vy = JUMP_VELOCITY;
             game_state.player.on_ground = false;
             game_state.player.on_obstacle = false;
             game_state.player.is_jumping = true;
-            game_state.player.last_key = Some(Key::Space);
+            game_state.player.last_action = Some(Action::Jump);
 
-            append_source_source(&game_state, sink, JUMP_SOUND, 1500);
+            mixer.play(JUMP_SOUND, 1500);
         }
     }
 }
\ No newline at end of file
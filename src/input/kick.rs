@@ -1,82 +1,236 @@
-use rodio::Sink;
-use crate::audio::engine::append_source_source;
+use crate::audio::mixer::Mixer;
+use crate::input::action::Action;
 use crate::input::handler::{InputLogic};
-use crate::state::collision::check_collision;
+use crate::state::collision::{obstacle_in_front, player_hitbox, reach_box, Aabb};
+use crate::state::constants::animation::KICK;
 use crate::state::constants::audio::{KICK_BOX_SOUND, KICK_SOUND};
+use crate::state::constants::graphics::TILE_WIDTH;
+use crate::state::constants::physics::{BOX_KICK_REACH, LAUNCH_SPEED};
+use crate::state::constants::hud::BOX_SCORE;
+use crate::state::constants::enemy::{KICK_KNOCKBACK, KICK_RANGE};
+use crate::state::enemy::damage_enemy;
 use crate::state::structs::Direction::Left;
-use crate::state::structs::GameState;
+use crate::state::structs::{GameState, ObstacleId};
 
 pub struct Kick;
 
 impl InputLogic for Kick {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer) {
         game_state.player.is_kicking = true;
-        game_state.player.kick_frame = 0;
-        game_state.player.kick_frame_timer = 0;
+        game_state.player.animator.restart(KICK);
 
-        let (collision, id) = check_collision(game_state.all_maps[game_state.current_map_index].obstacles, &game_state.sprites, &game_state.player, game_state.player.direction == Left);
+        // Enemies within reach take the kick before any box does
+        if Self::hit_enemy(game_state, mixer) {
+            return;
+        }
 
         // Check if the player is adjacent to an obstacle
-        if collision {
-            append_source_source(&game_state, sink, KICK_BOX_SOUND, 1000);
-            Self::damage_obstacle(game_state, sink, id);
+        if let Some(id) = obstacle_in_front(game_state, BOX_KICK_REACH) {
+            mixer.play(KICK_BOX_SOUND, 1000);
 
+            // Kicking while holding push sends the box sliding instead of wearing it down
+            let strong = game_state.held_actions & Action::Push.bit() != 0;
+            if !(strong && Self::launch_obstacle(game_state, id)) {
+                Self::damage_obstacle(game_state, id);
+            }
         } else {
-            append_source_source(&game_state, sink, KICK_SOUND, 1000);
+            mixer.play(KICK_SOUND, 1000);
         }
     }
 }
 
 impl Kick {
-    fn damage_obstacle(game_state: &mut GameState, sink: &mut Sink, id: Option<usize>) {
-        if game_state.all_maps[game_state.current_map_index].obstacles[id.unwrap()].durability > 0 {
-            game_state.all_maps[game_state.current_map_index].obstacles[id.unwrap()].durability -= 1;
+    fn hit_enemy(game_state: &mut GameState, mixer: &mut Mixer) -> bool {
+        let facing_left = game_state.player.direction == Left;
+        // Enemies are reached like boxes, only further
+        let reach = reach_box(player_hitbox(&game_state.player, &game_state.sprites), game_state.player.direction, KICK_RANGE);
+        let enemies = &mut game_state.all_maps[game_state.current_map_index].enemies;
+
+        let Some(index) = enemies.iter().position(|enemy| reach.overlaps(&enemy.hitbox())) else {
+            return false;
+        };
+
+        // `MoveEnemies` pushes the enemy back, stopping it at the boxes in the way
+        enemies[index].knockback = if facing_left { -KICK_KNOCKBACK } else { KICK_KNOCKBACK };
+        if !damage_enemy(game_state, mixer, index) {
+            mixer.play(KICK_BOX_SOUND, 1000);
+        }
+
+        true
+    }
+
+    fn damage_obstacle(game_state: &mut GameState, id: ObstacleId) {
+        // A box standing next to a toxic trap is shoved onto it instead of taking damage
+        if Self::knock_onto_trap(game_state, id) {
+            return;
+        }
+
+        if game_state.all_maps[game_state.current_map_index].obstacles[id].durability > 0 {
+            game_state.all_maps[game_state.current_map_index].obstacles[id].durability -= 1;
         } else {
-            Self::remove_obstacle(game_state, id.unwrap(), sink);
+            Self::remove_obstacle(game_state, id);
         }
     }
-    fn remove_obstacle(game_state: &mut GameState, box_index: usize, sink: &mut rodio::Sink) {
-        println!("Removing box {}", box_index);
-        let mut to_remove = false;
-        if game_state.all_maps[game_state.current_map_index].obstacles[box_index].active {
-            println!("Box is active");
-            // Obtain the x_left and x_right values of the removed box
-            let removed_box_x_left = game_state.all_maps[game_state.current_map_index].obstacles[box_index].x_left;
-            let removed_box_x_right = game_state.all_maps[game_state.current_map_index].obstacles[box_index].x_right;
-            let removed_box_y_top = game_state.all_maps[game_state.current_map_index].obstacles[box_index].y_top;
-
-            println!("Box x_left: {}, x_right: {}", removed_box_x_left, removed_box_x_right);
-            println!("Box {} removed", box_index);
-
-            append_source_source(&game_state, sink, KICK_BOX_SOUND, 1000);
-
-            // Only bottom obstacles will have their above boxes fall
-            if game_state.all_maps[game_state.current_map_index].obstacles[box_index].is_bottom_obstacle {
-                // Shift all boxes above the removed box down by 16 pixels
-                for i in 0..game_state.all_maps[game_state.current_map_index].obstacles.len() {
-                    println!("Box id: {}", i);
-
-                    let obstacle = &mut game_state.all_maps[game_state.current_map_index].obstacles[i];
-                    println!("Box {} x_left: {}, x_right: {}", i, obstacle.x_left, obstacle.x_right);
-                    if obstacle.x_left >= removed_box_x_left && obstacle.x_right <= removed_box_x_right {
-                        obstacle.falling = true;
-                        obstacle.velocity_y = 0.0;
-                        println!("Box {} is falling", i);
-                        // Remove the box
-                        to_remove = true;
-                    } else {
-                        println!("Box {} is not falling. obs.x_left: {} removed.x_left: {} obs.x_right {} removed.x_right {}", i, obstacle.x_left, removed_box_x_left, obstacle.x_right, removed_box_x_right);
-                    }
-                }
-            } else {
-                to_remove = true;
-            }
 
+    /// Sends a lone box sliding away from the player, to be moved by `ApplyGravity` until it stops.
+    ///
+    /// # Returns
+    /// Whether the box was launched, which a box with others stacked on it or already moving cannot be.
+    fn launch_obstacle(game_state: &mut GameState, id: ObstacleId) -> bool {
+        let obstacle = &mut game_state.all_maps[game_state.current_map_index].obstacles[id];
+        if !obstacle.is_top_obstacle || obstacle.falling || obstacle.velocity_x != 0.0 {
+            return false;
+        }
+
+        obstacle.velocity_x = if game_state.player.direction == Left { -LAUNCH_SPEED } else { LAUNCH_SPEED };
+        println!("Box {} launched at x: {}", id, obstacle.x_left);
+
+        true
+    }
+
+    fn knock_onto_trap(game_state: &mut GameState, id: ObstacleId) -> bool {
+        let map = &mut game_state.all_maps[game_state.current_map_index];
+        let obstacle = map.obstacles[id];
+
+        // Only a lone box can be shoved, stacks are too heavy
+        if !obstacle.is_top_obstacle || !obstacle.is_bottom_obstacle {
+            return false;
+        }
+
+        let shift = if game_state.player.direction == Left { -(TILE_WIDTH as f32) } else { TILE_WIDTH as f32 };
+        let x_left = obstacle.x_left + shift;
+        let x_right = obstacle.x_right + shift;
+
+        let trap_ahead = map.hazards.iter().any(|hazard| {
+            hazard.active && hazard.overlaps(x_left, x_right) && (hazard.y_bottom - obstacle.y_bottom).abs() < 0.5
+        });
+        let destination = Aabb { left: x_left, top: obstacle.y_top, right: x_right, bottom: obstacle.y_bottom };
+        let blocked = map.obstacles_near(destination).into_iter().map(|other| &map.obstacles[other]).any(|other| {
+            other.active && other.x_left < x_right && other.x_right > x_left && other.y_top < obstacle.y_bottom && other.y_bottom > obstacle.y_top
+        });
+
+        if !trap_ahead || blocked {
+            return false;
         }
 
-        if to_remove {
-            game_state.all_maps[game_state.current_map_index].obstacles.remove(box_index);
-            println!("Box {} removed", box_index);
+        map.move_obstacle(id, shift, 0.0);
+        println!("Box {} knocked onto toxic trap at x: {}", id, x_left);
+
+        true
+    }
+
+    fn remove_obstacle(game_state: &mut GameState, id: ObstacleId) {
+        // The boxes stacked on the removed box lose their support and fall in `ApplyGravity`.
+        // The kick which broke the box has already been heard
+        if game_state.all_maps[game_state.current_map_index].remove_obstacle(id).is_some() {
+            game_state.score += BOX_SCORE;
+            println!("Box {} removed", id);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::input::handler::InputLogic;
+    use crate::state::collision::player_hitbox;
+    use crate::state::constants::audio::KICK_BOX_SOUND;
+    use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH};
+    use crate::state::constants::hud::BOX_SCORE;
+    use crate::state::constants::physics::BOX_GROUND;
+    use crate::state::core_logic::CoreLogic;
+    use crate::state::enemy::{Enemy, MoveEnemies};
+    use crate::state::game::testing::{game_state, mixer, place_boxes};
+    use crate::state::gravity::ApplyGravity;
+    use crate::state::structs::EnemyId;
+
+    use super::Kick;
+
+    #[test]
+    fn kicking_a_box_plays_the_box_sound_once_per_kick() {
+        let mut game_state = game_state();
+        let (mut mixer, played) = mixer();
+        let front = player_hitbox(&game_state.player, &game_state.sprites).right;
+        let id = place_boxes(&mut game_state, &[(front + 1.0, BOX_GROUND)])[0];
+
+        // A box takes two kicks to wear down and breaks on the third
+        for kicks in 1..=3 {
+            Kick.execute(&mut game_state, &mut mixer);
+            assert_eq!(played.borrow().iter().filter(|&&sound| sound == KICK_BOX_SOUND).count(), kicks);
+        }
+
+        let map = &game_state.all_maps[game_state.current_map_index];
+        assert!(map.obstacles.get(id).is_none());
+        assert_eq!(played.borrow().len(), 3);
+    }
+
+    #[test]
+    fn a_kicked_enemy_is_pushed_back_until_a_box_stops_it() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        let start_x = game_state.player.x + body.right - body.left + 4.0;
+        let enemy = Enemy::new(EnemyId(0), start_x, game_state.player.y);
+        let id = place_boxes(&mut game_state, &[(enemy.hitbox().right + 4.0, BOX_GROUND)])[0];
+        game_state.all_maps[game_state.current_map_index].enemies.push(enemy);
+
+        Kick.execute(&mut game_state, &mut mixer);
+        for _ in 0..30 {
+            MoveEnemies.execute(&mut game_state, &mut mixer);
+        }
+
+        let map = &game_state.all_maps[game_state.current_map_index];
+        let enemy = &map.enemies[0];
+        assert!(enemy.x > start_x);
+        assert_eq!(enemy.hitbox().right, map.obstacles[id].x_left);
+        assert_eq!(enemy.knockback, 0.0);
+    }
+
+    #[test]
+    fn a_box_dropping_onto_the_stack_below_links_to_it() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let tile = TILE_HEIGHT as f32;
+        let ids = place_boxes(&mut game_state, &[(400.0, BOX_GROUND), (400.0, BOX_GROUND - tile), (400.0, BOX_GROUND - 2.0 * tile)]);
+        let (bottom, middle, top) = (ids[0], ids[1], ids[2]);
+
+        Kick::remove_obstacle(&mut game_state, middle);
+        for _ in 0..30 {
+            ApplyGravity.execute(&mut game_state, &mut mixer);
+        }
+
+        let obstacles = &game_state.all_maps[game_state.current_map_index].obstacles;
+        assert!(obstacles.get(middle).is_none());
+        assert_eq!(obstacles[top].y_bottom, BOX_GROUND - tile);
+        assert!(!obstacles[top].falling);
+        assert_eq!((obstacles[top].under_obstacle, obstacles[top].over_obstacle), (Some(bottom), None));
+        assert_eq!((obstacles[bottom].under_obstacle, obstacles[bottom].over_obstacle), (None, Some(top)));
+        assert!(obstacles[top].is_top_obstacle && !obstacles[top].is_bottom_obstacle);
+        assert!(obstacles[bottom].is_bottom_obstacle && !obstacles[bottom].is_top_obstacle);
+    }
+
+    #[test]
+    fn removing_a_box_leaves_no_links_to_it() {
+        let mut game_state = game_state();
+        let (width, height) = (TILE_WIDTH as f32, TILE_HEIGHT as f32);
+        // A row of three boxes with one more on each of them
+        let ids = place_boxes(&mut game_state, &[
+            (400.0, BOX_GROUND), (400.0 + width, BOX_GROUND), (400.0 + 2.0 * width, BOX_GROUND),
+            (400.0, BOX_GROUND - height), (400.0 + width, BOX_GROUND - height), (400.0 + 2.0 * width, BOX_GROUND - height),
+        ]);
+        let removed = ids[1];
+
+        Kick::remove_obstacle(&mut game_state, removed);
+
+        let obstacles = &game_state.all_maps[game_state.current_map_index].obstacles;
+        for obstacle in obstacles.iter() {
+            let links = [obstacle.left_obstacle, obstacle.right_obstacle, obstacle.over_obstacle, obstacle.under_obstacle];
+            assert!(!links.contains(&Some(removed)), "box {} still links to box {}", obstacle.id, removed);
+        }
+        assert_eq!(obstacles[ids[0]].right_obstacle, None);
+        assert!(obstacles[ids[0]].is_rightmost_obstacle);
+        assert_eq!(obstacles[ids[2]].left_obstacle, None);
+        assert!(obstacles[ids[2]].is_leftmost_obstacle);
+        assert_eq!(obstacles[ids[4]].under_obstacle, None);
+        assert!(obstacles[ids[4]].is_bottom_obstacle);
+        assert_eq!(game_state.score, BOX_SCORE);
+    }
+}
@@ -1,5 +1,8 @@
 pub mod handler;
+pub mod action;
 pub mod kick;
+pub mod recording;
 mod move_right;
 mod move_left;
-mod jump;
\ No newline at end of file
+mod jump;
+mod push;
\ No newline at end of file
@@ -0,0 +1,81 @@
+use crate::input::action::Action;
+use crate::audio::mixer::Mixer;
+use crate::input::handler::InputLogic;
+use crate::state::collision::{obstacle_in_front, slide_obstacle};
+use crate::state::constants::physics::{BOX_GROUND, PUSH_SPEED};
+use crate::state::structs::Direction::Left;
+use crate::state::structs::GameState;
+
+pub struct Push;
+
+impl InputLogic for Push {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        // Only walking into a box pushes it, the move commands have already run this tick
+        let facing_left = game_state.player.direction == Left;
+        let forward = if facing_left { Action::MoveLeft } else { Action::MoveRight };
+        if game_state.held_actions & forward.bit() == 0 {
+            return;
+        }
+
+        let Some(id) = obstacle_in_front(game_state, 1.0) else {
+            return;
+        };
+
+        // Only a lone box standing on the ground can be pushed, stacks are too heavy
+        let map = &mut game_state.all_maps[game_state.current_map_index];
+        let obstacle = map.obstacles[id];
+        if !obstacle.is_top_obstacle || obstacle.falling || obstacle.velocity_x != 0.0 || obstacle.y_bottom < BOX_GROUND {
+            return;
+        }
+
+        // The player follows the box at its pace, a box blocked by another one does not move at all
+        let moved = slide_obstacle(map, id, if facing_left { -PUSH_SPEED } else { PUSH_SPEED });
+        game_state.player.vx = moved.abs();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::action::Action;
+    use crate::input::handler::InputLogic;
+    use crate::state::collision::player_hitbox;
+    use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH};
+    use crate::state::constants::physics::{BOX_GROUND, MAX_VELOCITY, PUSH_SPEED};
+    use crate::state::game::testing::{game_state, mixer, place_boxes};
+
+    use super::Push;
+
+    #[test]
+    fn a_lone_box_moves_at_the_push_speed_unless_another_box_blocks_it() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let front = player_hitbox(&game_state.player, &game_state.sprites).right;
+        let ids = place_boxes(&mut game_state, &[(front, BOX_GROUND), (front + TILE_WIDTH as f32 + PUSH_SPEED, BOX_GROUND)]);
+        game_state.held_actions = Action::MoveRight.bit();
+
+        game_state.player.vx = MAX_VELOCITY;
+        Push.execute(&mut game_state, &mut mixer);
+        assert_eq!(game_state.all_maps[game_state.current_map_index].obstacles[ids[0]].x_left, front + PUSH_SPEED);
+        assert_eq!(game_state.player.vx, PUSH_SPEED);
+
+        // Now the boxes touch, so the second push moves nothing and stops the player
+        game_state.player.vx = MAX_VELOCITY;
+        Push.execute(&mut game_state, &mut mixer);
+        assert_eq!(game_state.all_maps[game_state.current_map_index].obstacles[ids[0]].x_left, front + PUSH_SPEED);
+        assert_eq!(game_state.player.vx, 0.0);
+    }
+
+    #[test]
+    fn a_stack_does_not_move() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let front = player_hitbox(&game_state.player, &game_state.sprites).right;
+        let ids = place_boxes(&mut game_state, &[(front, BOX_GROUND), (front, BOX_GROUND - TILE_HEIGHT as f32)]);
+        game_state.held_actions = Action::MoveRight.bit();
+
+        Push.execute(&mut game_state, &mut mixer);
+
+        let obstacles = &game_state.all_maps[game_state.current_map_index].obstacles;
+        assert!(ids.iter().all(|&id| obstacles[id].x_left == front));
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"AOPR";
+const VERSION: u8 = 3;
+const HEADER_LENGTH: usize = 4 + 1 + 8 + 2 + 4; // Magic, version, seed, level, ticks
+const RUN_LENGTH: usize = 1 + 2; // Action mask, ticks it was held for
+const POSITION_LENGTH: usize = 4 + 4; // Player x, y
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(PathBuf, io::Error),
+    Format(PathBuf, &'static str),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordingError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            RecordingError::Format(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+/// The keys held during every tick of a playthrough, together with everything else needed to
+/// reproduce it: the seed of the random number generator and the map it started on. The position
+/// of the player after every tick shows where a replay stops matching the recording.
+///
+/// Input is stored as one action mask per tick (see `Action::bit`), so a recording plays back
+/// the same regardless of the key bindings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub seed: u64,
+    pub level: usize, // Id of the map the recording starts on
+    pub inputs: Vec<u8>, // Pressed actions per tick
+    pub positions: Vec<(f32, f32)>, // Player position after every tick, used to detect a diverging replay
+}
+
+impl Recording {
+    pub fn new(seed: u64, level: usize) -> Self {
+        Recording { seed, level, inputs: Vec::new(), positions: Vec::new() }
+    }
+
+    /// Writes the recording to `path`. Runs of identical inputs are stored once with their length,
+    /// so holding a key for a minute costs only a few bytes, followed by the position of every tick.
+    pub fn save(&self, path: &Path) -> Result<(), RecordingError> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&(self.level as u16).to_le_bytes());
+        bytes.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+
+        for run in self.inputs.chunk_by(|a, b| a == b) {
+            for part in run.chunks(u16::MAX as usize) {
+                bytes.push(part[0]);
+                bytes.extend_from_slice(&(part.len() as u16).to_le_bytes());
+            }
+        }
+
+        for (x, y) in &self.positions {
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+        }
+
+        fs::write(path, bytes).map_err(|e| RecordingError::Io(path.to_path_buf(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, RecordingError> {
+        let bytes = fs::read(path).map_err(|e| RecordingError::Io(path.to_path_buf(), e))?;
+        let format_error = |message| RecordingError::Format(path.to_path_buf(), message);
+
+        if bytes.len() < HEADER_LENGTH || &bytes[0..4] != MAGIC {
+            return Err(format_error("not a recording"));
+        }
+        if bytes[4] != VERSION {
+            return Err(format_error("unsupported recording version"));
+        }
+
+        let seed = u64::from_le_bytes(bytes[5..13].try_into().unwrap());
+        let level = u16::from_le_bytes(bytes[13..15].try_into().unwrap()) as usize;
+        let ticks = u32::from_le_bytes(bytes[15..19].try_into().unwrap()) as usize;
+
+        // Input runs follow until they cover every tick, then come the positions
+        let mut inputs = Vec::with_capacity(ticks);
+        let mut offset = HEADER_LENGTH;
+        while inputs.len() < ticks {
+            let run = bytes.get(offset..offset + RUN_LENGTH).ok_or_else(|| format_error("truncated input run"))?;
+            let length = u16::from_le_bytes([run[1], run[2]]) as usize;
+            inputs.extend(std::iter::repeat_n(run[0], length));
+            offset += RUN_LENGTH;
+        }
+        if inputs.len() != ticks {
+            return Err(format_error("input runs do not add up to the recorded number of ticks"));
+        }
+
+        let positions = &bytes[offset..];
+        if positions.len() != ticks * POSITION_LENGTH {
+            return Err(format_error("positions do not match the recorded number of ticks"));
+        }
+        let positions = positions.chunks(POSITION_LENGTH).map(|position| {
+            (f32::from_le_bytes(position[0..4].try_into().unwrap()), f32::from_le_bytes(position[4..8].try_into().unwrap()))
+        }).collect();
+
+        Ok(Recording { seed, level, inputs, positions })
+    }
+}
+
+/// Where the actions handled each tick come from.
+pub enum InputMode {
+    Live, // Read from the display backend
+    Record(Recording), // Read from the display backend and appended to the recording
+    Replay(Recording), // Read from the recording, ignoring the keyboard
+}
+
+impl InputMode {
+    /// Returns the action mask for `tick`, calling `live` for the actions currently held on the
+    /// keyboard unless replaying.
+    pub fn next(&mut self, tick: u64, live: impl FnOnce() -> u8) -> u8 {
+        match self {
+            InputMode::Live => live(),
+            InputMode::Record(recording) => {
+                let keys = live();
+                recording.inputs.push(keys);
+                keys
+            }
+            InputMode::Replay(recording) => recording.inputs.get(tick as usize).copied().unwrap_or(0),
+        }
+    }
+
+    /// Keeps where the player ended `tick` when recording, or compares it with the recording when replaying.
+    ///
+    /// # Parameters
+    /// - `previous`: The position at the end of the previous tick, which tells whether the replay still matched then.
+    /// - `position`: The position at the end of `tick`.
+    ///
+    /// # Returns
+    /// The recorded position if the replay diverged from the recording on `tick`.
+    pub fn track_position(&mut self, tick: u64, previous: (f32, f32), position: (f32, f32)) -> Option<(f32, f32)> {
+        match self {
+            InputMode::Live => None,
+            InputMode::Record(recording) => {
+                recording.positions.push(position);
+                None
+            }
+            InputMode::Replay(recording) => {
+                let tick = tick as usize;
+                let recorded = *recording.positions.get(tick)?;
+                let matched_before = tick == 0 || recording.positions.get(tick - 1) == Some(&previous);
+                (recorded != position && matched_before).then_some(recorded)
+            }
+        }
+    }
+
+    /// Returns true once a replay has fed back every recorded tick.
+    pub fn is_finished(&self, tick: u64) -> bool {
+        matches!(self, InputMode::Replay(recording) if tick as usize >= recording.inputs.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Recording {
+        let mut recording = Recording::new(42, 2);
+        recording.inputs = [vec![0; 3], vec![2; 70000], vec![6, 4]].concat();
+        recording.positions = (0..recording.inputs.len()).map(|tick| (tick as f32, 205.0 - tick as f32 * 0.5)).collect();
+        recording
+    }
+
+    #[test]
+    fn recordings_load_as_saved() {
+        let path = std::env::temp_dir().join(format!("age_of_panda_{}.rec", std::process::id()));
+        let recording = recording();
+
+        recording.save(&path).unwrap();
+        let loaded = Recording::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), recording);
+    }
+
+    #[test]
+    fn recordings_missing_positions_are_rejected() {
+        let path = std::env::temp_dir().join(format!("age_of_panda_{}_truncated.rec", std::process::id()));
+        recording().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - POSITION_LENGTH]).unwrap();
+
+        let error = Recording::load(&path).err().unwrap().to_string();
+        fs::remove_file(&path).unwrap();
+
+        assert!(error.ends_with("positions do not match the recorded number of ticks"), "{}", error);
+    }
+
+    #[test]
+    fn recording_keeps_the_position_of_every_tick() {
+        let mut input_mode = InputMode::Record(Recording::new(0, 1));
+        for tick in 0..3 {
+            assert_eq!(input_mode.track_position(tick, (0.0, 0.0), (tick as f32, 1.0)), None);
+        }
+
+        let InputMode::Record(recording) = input_mode else { unreachable!() };
+        assert_eq!(recording.positions, [(0.0, 1.0), (1.0, 1.0), (2.0, 1.0)]);
+    }
+
+    #[test]
+    fn replays_report_the_tick_they_diverge_on_once() {
+        let mut recording = Recording::new(0, 1);
+        recording.positions = vec![(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)];
+        let mut input_mode = InputMode::Replay(recording);
+
+        assert_eq!(input_mode.track_position(0, (0.0, 0.0), (0.0, 0.0)), None);
+        assert_eq!(input_mode.track_position(1, (0.0, 0.0), (1.0, 0.0)), None);
+        assert_eq!(input_mode.track_position(2, (1.0, 0.0), (2.5, 0.0)), Some((2.0, 0.0)));
+        assert_eq!(input_mode.track_position(3, (2.5, 0.0), (3.5, 0.0)), None);
+    }
+}
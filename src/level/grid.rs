@@ -0,0 +1,172 @@
+use std::fmt;
+
+use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH};
+use crate::state::constants::level::TILE_Y_OFFSET;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileType {
+    Obstacle,
+    Grass,
+    Sky,
+    Exit,
+    Enemy,
+    Hazard,
+}
+
+impl TileType {
+    fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "X" => Some(TileType::Obstacle),
+            "G" => Some(TileType::Grass),
+            "O" => Some(TileType::Sky),
+            "D" => Some(TileType::Exit),
+            "E" => Some(TileType::Enemy),
+            "T" => Some(TileType::Hazard),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tile {
+    pub tile_type: TileType,
+    pub x_left: f32,
+    pub x_right: f32,
+    pub y_bottom: f32,
+    pub y_top: f32,
+}
+
+/// A parsed level file: every tile in row-major order together with the grid dimensions.
+pub struct Grid {
+    pub tiles: Vec<Tile>,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownTile(String),
+    RowLength { expected: usize, found: usize },
+    Empty,
+}
+
+/// Error raised while parsing a level grid, pointing at the offending line and column (both 1-based).
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnknownTile(symbol) => write!(f, "{}:{}: unknown tile '{}'", self.line, self.column, symbol),
+            ParseErrorKind::RowLength { expected, found } => write!(f, "{}:{}: expected {} tiles in row but found {}", self.line, self.column, expected, found),
+            ParseErrorKind::Empty => write!(f, "{}:{}: level contains no tiles", self.line, self.column),
+        }
+    }
+}
+
+/// Parses a level grid.
+///
+/// Each non-empty line is one row of whitespace separated tile symbols:
+/// - `X`: an obstacle (metal box)
+/// - `G`: grass, the ground, which is drawn by the parallax layers
+/// - `O`: sky
+/// - `D`: exit door, walking past it leads to the next map
+/// - `E`: an enemy, spawned standing on the tile below it
+/// - `T`: a toxic trap
+///
+/// Lines starting with `#` are comments. All rows must have the same number of tiles.
+///
+/// # Parameters
+/// - `source`: The contents of the level file.
+///
+/// # Returns
+/// The parsed grid, or the first error encountered.
+pub fn parse_grid(source: &str) -> Result<Grid, ParseError> {
+    let mut tiles = Vec::new();
+    let mut columns = 0;
+    let mut rows = 0;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut tiles_in_row = 0;
+        for (char_index, symbol) in symbols_with_columns(line) {
+            let tile_type = TileType::from_symbol(symbol).ok_or_else(|| ParseError {
+                line: line_index + 1,
+                column: char_index + 1,
+                kind: ParseErrorKind::UnknownTile(symbol.to_string()),
+            })?;
+
+            let x_left = (tiles_in_row * TILE_WIDTH) as f32;
+            let y_bottom = (rows * TILE_HEIGHT) as f32 + TILE_Y_OFFSET;
+            tiles.push(Tile {
+                tile_type,
+                x_left,
+                x_right: x_left + TILE_WIDTH as f32,
+                y_bottom,
+                y_top: y_bottom - TILE_HEIGHT as f32,
+            });
+            tiles_in_row += 1;
+        }
+
+        if rows == 0 {
+            columns = tiles_in_row;
+        } else if tiles_in_row != columns {
+            return Err(ParseError {
+                line: line_index + 1,
+                column: line.chars().count() + 1,
+                kind: ParseErrorKind::RowLength { expected: columns, found: tiles_in_row },
+            });
+        }
+        rows += 1;
+    }
+
+    if tiles.is_empty() {
+        return Err(ParseError { line: 1, column: 1, kind: ParseErrorKind::Empty });
+    }
+
+    Ok(Grid { tiles, columns, rows })
+}
+
+/// Splits a line on whitespace, keeping the character column at which each symbol starts.
+fn symbols_with_columns(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split_whitespace().map(move |symbol| {
+        let offset = symbol.as_ptr() as usize - line.as_ptr() as usize;
+        (line[..offset].chars().count(), symbol)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_length_errors_point_past_the_last_character() {
+        // The ideographic space at the end takes three bytes, but is a single column
+        let error = parse_grid("O O O\nO O\u{3000}\n").err().unwrap();
+
+        assert_eq!(error.to_string(), "2:5: expected 3 tiles in row but found 2");
+    }
+
+    #[test]
+    fn unknown_tiles_report_their_character_column() {
+        let error = parse_grid("É O Q\n").err().unwrap();
+
+        assert_eq!(error.to_string(), "1:1: unknown tile 'É'");
+    }
+
+    #[test]
+    fn grass_is_parsed_as_ground_tiles() {
+        let grid = parse_grid("# ground\nO X\nG G\n").unwrap();
+
+        assert_eq!((grid.columns, grid.rows), (2, 2));
+        assert!(grid.tiles[2..].iter().all(|tile| tile.tile_type == TileType::Grass));
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::graphics::parallax::{parse_parallax, ParallaxError, ParallaxLayer};
+use crate::level::grid::{parse_grid, ParseError, Tile, TileType};
+use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH};
+use crate::state::constants::level::{DEFAULT_PARALLAX_FILE, LEVEL_FILE_EXTENSION, PARALLAX_FILE_EXTENSION};
+use crate::state::arena::ObstacleArena;
+use crate::state::constants::physics::UPPER_BOUND_MARGIN;
+use crate::state::enemy::Enemy;
+use crate::state::hazard::Hazard;
+use crate::state::spatial::{link_all, link_neighbours, ObstacleGrid};
+use crate::state::structs::{EnemyId, Map, Obstacle, ObstacleId};
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ParseError),
+    Parallax(PathBuf, ParallaxError),
+    NoLevels(PathBuf),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            LevelError::Parse(path, e) => write!(f, "{}:{}", path.display(), e),
+            LevelError::Parallax(path, e) => write!(f, "{}:{}", path.display(), e),
+            LevelError::NoLevels(path) => write!(f, "{}: no *.{} level files found", path.display(), LEVEL_FILE_EXTENSION),
+        }
+    }
+}
+
+/// Loads every level file in `directory` as a `Map`, ordered by file name.
+///
+/// Designers add a stage by dropping a new file such as `04_cliffs.lvl` into the directory.
+pub fn load_levels(directory: &Path) -> Result<Vec<Map>, LevelError> {
+    let entries = fs::read_dir(directory).map_err(|e| LevelError::Io(directory.to_path_buf(), e))?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| LevelError::Io(directory.to_path_buf(), e))?.path();
+        if path.extension().is_some_and(|extension| extension == LEVEL_FILE_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(LevelError::NoLevels(directory.to_path_buf()));
+    }
+
+    paths.iter().enumerate().map(|(index, path)| load_level(path, index + 1)).collect()
+}
+
+/// Loads a single level file into a `Map` with the given id.
+pub fn load_level(path: &Path, id: usize) -> Result<Map, LevelError> {
+    let source = fs::read_to_string(path).map_err(|e| LevelError::Io(path.to_path_buf(), e))?;
+    let grid = parse_grid(&source).map_err(|e| LevelError::Parse(path.to_path_buf(), e))?;
+
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let width = grid.columns * TILE_WIDTH;
+    let parallax = load_parallax(path)?;
+
+    // Leave the map through its exit door, or at the right edge if the level has none
+    let transition_x = grid.tiles.iter()
+        .find(|tile| tile.tile_type == TileType::Exit)
+        .map_or(width as f32 - UPPER_BOUND_MARGIN, |tile| tile.x_left);
+
+    println!("Loaded level {} '{}': {}x{} tiles", id, name, grid.columns, grid.rows);
+
+    Ok(Map {
+        id,
+        name,
+        width,
+        height: grid.rows * TILE_HEIGHT,
+        obstacles: ObstacleArena::default(),
+        obstacle_grid: ObstacleGrid::default(),
+        obstacle_layout: extract_obstacles(&grid.tiles),
+        enemies: Vec::new(),
+        enemy_layout: extract_enemies(&grid.tiles),
+        hazards: Vec::new(),
+        hazard_layout: extract_hazards(&grid.tiles),
+        transition_x,
+        parallax,
+    })
+}
+
+/// Loads the scenery of the level file at `path` from the parallax file next to it with the same name,
+/// or from the default parallax file in the same directory if the level has none.
+pub fn load_parallax(path: &Path) -> Result<Vec<ParallaxLayer>, LevelError> {
+    let mut parallax_path = path.with_extension(PARALLAX_FILE_EXTENSION);
+    if !parallax_path.exists() {
+        parallax_path = path.with_file_name(DEFAULT_PARALLAX_FILE);
+    }
+
+    let source = fs::read_to_string(&parallax_path).map_err(|e| LevelError::Io(parallax_path.clone(), e))?;
+    parse_parallax(&source).map_err(|e| LevelError::Parallax(parallax_path, e))
+}
+
+/// Creates an obstacle for every obstacle tile and links each obstacle to its neighbours, which
+/// are looked up in an `ObstacleGrid` of the obstacles.
+pub fn extract_obstacles(grid: &[Tile]) -> ObstacleArena {
+    let mut obstacles = create_obstacles(grid);
+    let index = ObstacleGrid::build(&obstacles);
+    link_all(&mut obstacles, &index);
+    obstacles
+}
+
+/// The original way of linking obstacles, which compares every obstacle with every other one.
+/// Kept as the baseline for `benchmark_obstacles`.
+pub fn extract_obstacles_by_scan(grid: &[Tile]) -> ObstacleArena {
+    let mut obstacles = create_obstacles(grid);
+
+    let ids = obstacles.ids();
+    for &id in &ids {
+        link_neighbours(&mut obstacles, id, ids.iter().copied());
+    }
+
+    obstacles
+}
+
+fn create_obstacles(grid: &[Tile]) -> ObstacleArena {
+    grid.iter()
+        .filter(|tile| tile.tile_type == TileType::Obstacle)
+        .map(|tile| Obstacle {
+            id: ObstacleId::default(),
+            x_left: tile.x_left,
+            x_right: tile.x_right,
+            y_bottom: tile.y_bottom,
+            y_top: tile.y_top,
+            active: true,
+            durability: 2,
+            falling: false,
+            velocity_y: 0.0,
+            velocity_x: 0.0,
+            fall_start_y: tile.y_bottom,
+            left_obstacle: None,
+            right_obstacle: None,
+            over_obstacle: None,
+            under_obstacle: None,
+            is_bottom_obstacle: false,
+            is_top_obstacle: false,
+            is_leftmost_obstacle: false,
+            is_rightmost_obstacle: false,
+        })
+        .collect()
+}
+
+/// Creates an enemy for every enemy tile. Enemies drop onto whatever is below them when spawned.
+pub fn extract_enemies(grid: &[Tile]) -> Vec<Enemy> {
+    grid.iter()
+        .filter(|tile| tile.tile_type == TileType::Enemy)
+        .enumerate()
+        .map(|(index, tile)| Enemy::new(EnemyId(index), tile.x_left, tile.y_bottom - 10.0))
+        .collect()
+}
+
+/// Creates a toxic trap for every hazard tile.
+pub fn extract_hazards(grid: &[Tile]) -> Vec<Hazard> {
+    grid.iter()
+        .filter(|tile| tile.tile_type == TileType::Hazard)
+        .map(|tile| Hazard::new(tile.x_left, tile.x_right, tile.y_bottom))
+        .collect()
+}
@@ -0,0 +1,8 @@
+//! Age of Panda, a 2D platformer. The game itself starts in `main.rs`; its modules are a library
+//! so integration tests can run the simulation through a `HeadlessBackend`.
+
+pub mod state;
+pub mod graphics;
+pub mod audio;
+pub mod input;
+pub mod level;
@@ -1,302 +1,226 @@
-use std::fs::File;
-use std::io;
-use std::io::{BufRead, Read};
-use std::path::Path;
-use minifb::{Window, WindowOptions};
-use winit::event_loop::EventLoop;
-use winit::monitor::MonitorHandle;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::state::player::Player;
-use crate::{
-    graphics::sprites::SpriteMaps,
-    state::core_logic::initialize_core_logic_map,
+use age_of_panda::{
+    state::core_logic::initialize_scheduler,
     state::event_loop::start_event_loop,
 };
-use rodio::{OutputStream, Sink};
-use input::handler::initialize_input_logic_map;
-use crate::state::constants::graphics::{SCALED_WINDOW_HEIGHT, SCALED_WINDOW_WIDTH};
-use crate::state::structs::{GameState, Map, Obstacle, ObstacleId};
-
-mod state;
-mod graphics;
-mod audio;
-mod input;
+use age_of_panda::input::handler::initialize_input_logic_map;
+use age_of_panda::audio::backend::{AudioBackend, NullBackend, RodioBackend};
+use age_of_panda::audio::engine::load_sounds;
+use age_of_panda::audio::mixer::Mixer;
+use age_of_panda::state::constants::animation::SPRITE_MANIFEST;
+use age_of_panda::state::constants::audio::{FOOTSTEP_VOLUME, WALK_SOUND_1, WALK_SOUND_2, WALK_SOUND_3, WALK_SOUND_4};
+use age_of_panda::level::loader::load_levels;
+use age_of_panda::state::constants::graphics::{BENCHMARK_FRAMES, HEADLESS_CAPTURE_INTERVAL, SCALED_WINDOW_HEIGHT, SCALED_WINDOW_WIDTH, TICK_RATE, WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH, WINDOW_TITLE};
+use age_of_panda::state::constants::level::{BENCHMARK_BOXES, LEVELS_DIRECTORY};
+use age_of_panda::graphics::atlas::SpriteAtlas;
+use age_of_panda::graphics::font::BitmapFont;
+use age_of_panda::graphics::display::{load_display_settings, save_display_settings, DisplaySettings};
+use age_of_panda::graphics::render_graphics::{benchmark_scaling, ScaleMode};
+use age_of_panda::graphics::backend::{HeadlessBackend, MinifbBackend};
+use age_of_panda::input::recording::{InputMode, Recording};
+use age_of_panda::state::constants::hud::FONT_FILE;
+use age_of_panda::state::rng::Rng;
+use age_of_panda::state::scene::Scene;
+use age_of_panda::state::spatial::benchmark_obstacles;
+use age_of_panda::state::game::new_game_state;
+use age_of_panda::state::structs::GameState;
 
 fn main() {
-    // Initialize the audio output stream and sink
-    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-    let mut sink = Sink::try_new(&stream_handle).unwrap();
-    let sprites = SpriteMaps::new();
-    let mut player = Player::new(100.0, 176.0);
-    let input_logic = initialize_input_logic_map();
-    let core_logic = initialize_core_logic_map();
-    let fullscreen = false;
-
-    // Determine window size based on fullscreen flag
-    let (window_width, window_height) = if fullscreen {
-        let primary_monitor: MonitorHandle =  EventLoop::new().primary_monitor().expect("Failed to get primary monitor");
-        let screen_size = primary_monitor.size();
-        (screen_size.width as usize, screen_size.height as usize)
-    } else {
-        (SCALED_WINDOW_WIDTH, SCALED_WINDOW_HEIGHT)
-    };
-
-    // Create a window with the dimensions of the primary monitor
-    let mut window = Window::new(
-        "Age of Panda",
-        window_width,
-        window_height,
-        WindowOptions::default(),
-    ).unwrap_or_else(|e| {
-        panic!("{}", e);
-    });
-
+    let options = Options::from_args();
 
-    // Initialize window and scaled buffer
-    let mut window_buffer = vec![0; 256 * 224];
-    let mut scaled_buffer = vec![0; window_width * window_height];
-
-    let map_one = Map {
-        id: 1,
-        obstacles: &mut Vec::new(),
-        width: 256,
-        height: 224,
-        transition_x: None,
-    };
+    if options.benchmark {
+        benchmark_scaling(SCALED_WINDOW_WIDTH, SCALED_WINDOW_HEIGHT, BENCHMARK_FRAMES);
+        for boxes in BENCHMARK_BOXES {
+            benchmark_obstacles(boxes, BENCHMARK_FRAMES);
+        }
+        return;
+    }
 
-    let map_two = Map {
-        id: 2,
-        obstacles: &mut Vec::new(),
-        width: 256,
-        height: 224,
-        transition_x: Some(500.0)
+    // Initialize the audio mixer, falling back to silence if no output device is available or running headless
+    let null_backend = NullBackend::default();
+    let played_sounds = null_backend.played();
+    let audio_device = match options.headless_frames {
+        Some(_) => Err("running headless".to_string()),
+        None => RodioBackend::try_default(),
     };
-
-    let map_three = Map {
-        id: 3,
-        obstacles: &mut Vec::new(),
-        width: 256,
-        height: 224,
-        transition_x: Some(1500.0)
+    let backend: Box<dyn AudioBackend> = match audio_device {
+        Ok(backend) => Box::new(backend),
+        Err(e) => {
+            println!("Audio disabled: {}", e);
+            Box::new(null_backend)
+        }
     };
+    let mut mixer = Mixer::new(backend, &load_sounds());
+    if let Some(volume) = options.volume {
+        mixer.set_master_volume(volume as f32 / 100.0);
+    }
+    for footstep in [WALK_SOUND_1, WALK_SOUND_2, WALK_SOUND_3, WALK_SOUND_4] {
+        mixer.set_volume(footstep, FOOTSTEP_VOLUME);
+    }
+    // Load every stage from the levels directory
+    let all_maps = load_levels(Path::new(LEVELS_DIRECTORY)).unwrap_or_else(|e| {
+        panic!("Failed to load levels: {}", e);
+    });
+    let sprites = SpriteAtlas::load(Path::new(SPRITE_MANIFEST)).unwrap_or_else(|e| {
+        panic!("Failed to load sprites: {}", e);
+    });
+    let font = BitmapFont::load(Path::new(FONT_FILE)).unwrap_or_else(|e| {
+        panic!("Failed to load font: {}", e);
+    });
 
-    let all_maps = vec![map_one, map_two, map_three];
-
-    let sounds: Vec<Vec<u8>> = load_sounds();
-
+    let mut scheduler = initialize_scheduler(initialize_input_logic_map()).unwrap_or_else(|e| {
+        panic!("Invalid system schedule: {}", e);
+    });
+    println!("System order: {:?}", scheduler.order());
 
-    let game_state = GameState {
-        player,
-        sprites,
-        window_buffer: &mut window_buffer,
-        window_width,
-        window_height,
-        window: &mut window,
-        scaled_buffer: &mut scaled_buffer,
-        game_over_index: 0,
-        all_maps,
-        current_map_index: 0,
-        footstep_index: 0,
-        footstep_active: false,
-        sounds,
-        heart_sprite_frame_index: 0,
-        lighthouse_sprite_frame_index: 0,
-        ground_sprite_frame_index: 0,
-        mountains_sprite_frame_index: 0,
-        last_heart_sprite_frame_index_change: std::time::Instant::now(),
-        last_ground_sprite_frame_index_change: std::time::Instant::now(),
-        last_light_house_sprite_frame_index_change: std::time::Instant::now(),
-        obstacle_spawned: false,
-        designated_x: 0.0,
-        damage_taken: false,
-    };
+    if let Some(frames) = options.headless_frames {
+        // Run the simulation without a window, presenting frames at their native resolution
+        let mut display = HeadlessBackend::new(Vec::new(), frames).capture_frames(HEADLESS_CAPTURE_INTERVAL);
+        let mut window_buffer = vec![0; WINDOW_BUFFER_WIDTH * WINDOW_BUFFER_HEIGHT];
+        let mut scaled_buffer = Vec::new();
 
-    start_event_loop(game_state, input_logic, core_logic, &mut sink);
-}
+        let mut game_state = new_game_state(&mut display, &mut window_buffer, &mut scaled_buffer, DisplaySettings::default(), all_maps, sprites, font);
+        start_session(&mut game_state, &options);
+        start_event_loop(&mut game_state, &mut scheduler, &mut mixer, options.tick_rate);
+        end_session(&mut game_state, &options);
 
-fn print_obstacles(map_one_obstacles: &mut Vec<Obstacle>, id: &str) {
-    println!("------------------------- {id} -----------------------------");
-    // output obstacle one info for debugging
-    for obstacle in map_one_obstacles {
         println!(
-            "Obstacle one info: ID: {:?}, X.LEFT: {}, X.RIGHT: {}, Y.BOTTOM: {}, Y.TOP: {}, ACTIVE: {}, DURABILITY: {}, FALLING: {}, VELOCITY_Y: {}, LEFT_OBSTACLE: {:?}, RIGHT_OBSTACLE: {:?}, OVER_OBSTACLE: {:?}, UNDER_OBSTACLE: {:?}, IS_BOTTOM_OBSTACLE: {}, IS_TOP_OBSTACLE: {}, IS_LEFTMOST_OBSTACLE: {}, IS_RIGHTMOST_OBSTACLE: {}",
-            obstacle.id,
-            obstacle.x_left,
-            obstacle.x_right,
-            obstacle.y_bottom,
-            obstacle.y_top,
-            obstacle.active,
-            obstacle.durability,
-            obstacle.falling,
-            obstacle.velocity_y,
-            obstacle.left_obstacle,
-            obstacle.right_obstacle,
-            obstacle.over_obstacle,
-            obstacle.under_obstacle,
-            obstacle.is_bottom_obstacle,
-            obstacle.is_top_obstacle,
-            obstacle.is_leftmost_obstacle,
-            obstacle.is_rightmost_obstacle
+            "Headless run finished after {} ticks: map {}, player x: {}, y: {}, health: {}, sounds played: {}",
+            game_state.tick, game_state.all_maps[game_state.current_map_index].name,
+            game_state.player.x, game_state.player.y, game_state.player.health, played_sounds.borrow().len()
         );
-    }
-}
 
-fn load_sound(path: &str) -> Vec<u8> {
-    let mut file = File::open(path).expect("Failed to open sounds file");
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).expect("Failed to read file");
-    buffer
-}
+        // Checksums of sampled frames make it easy to spot rendering changes between runs
+        for (index, frame) in display.frames().iter().enumerate() {
+            let checksum = frame.iter().fold(0u32, |hash, &pixel| hash.rotate_left(5) ^ pixel);
+            println!("Frame {}: {:08x}", index as u64 * HEADLESS_CAPTURE_INTERVAL, checksum);
+        }
+        return;
+    }
 
+    // Open the window as it was left, unless the command line asks for other scaling
+    let mut display_settings = load_display_settings();
+    if let Some(scale_mode) = options.scale_mode {
+        display_settings.scale_mode = scale_mode;
+    }
+    display_settings.crt |= options.crt;
 
-fn load_sounds() -> Vec<Vec<u8>> {
-    let mut sounds = Vec::new();
-    sounds.push(load_sound("assets/sounds/walk_1.wav"));
-    sounds.push(load_sound("assets/sounds/walk_2.wav"));
-    sounds.push(load_sound("assets/sounds/walk_3.wav"));
-    sounds.push(load_sound("assets/sounds/walk_4.wav"));
-    sounds.push(load_sound("assets/sounds/jump.wav"));
-    sounds.push(load_sound("assets/sounds/fall_mild.wav"));
-    sounds.push(load_sound("assets/sounds/fall_heavy.wav"));
-    sounds.push(load_sound("assets/sounds/down.wav"));
-    sounds.push(load_sound("assets/sounds/explosion.wav"));
-    sounds.push(load_sound("assets/sounds/kick.wav"));
-    sounds.push(load_sound("assets/sounds/kick_box.wav"));
-    sounds
-}
+    let (window_width, window_height) = display_settings.size();
+    let mut display = MinifbBackend::open(WINDOW_TITLE, window_width, window_height, display_settings.fullscreen).unwrap_or_else(|e| {
+        panic!("Failed to open window: {}", e);
+    });
 
-fn read_grid_from_file(filename: &str) -> io::Result<(Vec<Tile>, usize, usize)> {
-    let path = Path::new(filename);
-    let file = File::open(&path)?;
-    let reader = io::BufReader::new(file);
+    // Initialize window and scaled buffer
+    let mut window_buffer = vec![0; WINDOW_BUFFER_WIDTH * WINDOW_BUFFER_HEIGHT];
+    let mut scaled_buffer = Vec::new();
 
-    let mut grid = Vec::new();
+    let mut game_state = new_game_state(&mut display, &mut window_buffer, &mut scaled_buffer, display_settings, all_maps, sprites, font);
+    start_session(&mut game_state, &options);
 
-    for (y, line) in reader.lines().enumerate() {
-        let line = line?.trim().to_string();
-        for (x, c) in line.split_whitespace().enumerate() {
-            let x_left = x as f32 * 16.0;
-            let x_right = x_left + 16.0;
-            let y_bottom = y as f32 * 16.0;
-            let y_top = y_bottom - 16.0;
-            let tile_type = match c {
-                "X" => TileType::Obstacle,
-                "G" => TileType::Grass,
-                "O" => TileType::Sky,
-                _ => TileType::Unknown,
-            };
-            grid.push(Tile {
-                tile_type,
-                x_left,
-                x_right,
-                y_bottom,
-                y_top,
-            });
-        }
+    // Recordings and replays start straight on the map they cover
+    if matches!(game_state.input_mode, InputMode::Live) {
+        game_state.scene = Scene::Title { selected: 0 };
     }
+    start_event_loop(&mut game_state, &mut scheduler, &mut mixer, options.tick_rate);
+    end_session(&mut game_state, &options);
+    save_display_settings(&game_state.display_settings);
+}
 
-    // Automatically detect resolution based on grid size
-    let (width, height) = if !grid.is_empty() {
-        let width = grid.iter().map(|tile| tile.x_right).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(0.0) as usize;
-        let height = grid.iter().map(|tile| tile.y_bottom).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(0.0) as usize;
+/// Seeds the simulation and sets up recording or replaying of the keyboard input.
+fn start_session(game_state: &mut GameState, options: &Options) {
+    let replay = options.replay.as_ref().map(|path| {
+        Recording::load(path).unwrap_or_else(|e| panic!("Failed to load recording: {}", e))
+    });
 
-        println!("Detected resolution: {}x{}", width, height);
-        (width, height)
+    // Without a seed to reproduce, pick a different one each run
+    let seed = replay.as_ref().map(|recording| recording.seed).or(options.seed).unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+    });
+    game_state.rng = Rng::new(seed);
+    println!("Random seed: {}", seed);
+
+    game_state.input_mode = if let Some(recording) = replay {
+        game_state.current_map_index = game_state.all_maps.iter().position(|map| map.id == recording.level).unwrap_or_else(|| {
+            panic!("Recording starts on level {}, which does not exist", recording.level);
+        });
+        InputMode::Replay(recording)
+    } else if options.record.is_some() {
+        InputMode::Record(Recording::new(seed, game_state.all_maps[game_state.current_map_index].id))
     } else {
-        (0, 0)
+        InputMode::Live
     };
-
-    Ok((grid, width, height))
 }
 
-fn extract_obstacles(grid: &Vec<Tile>) -> Vec<Obstacle> {
-    let mut obstacles = Vec::new();
-
-    for tile in grid {
-        if let TileType::Obstacle = tile.tile_type {
-            obstacles.push(Obstacle {
-                id: ObstacleId(obstacles.len()),
-                x_left: tile.x_left,
-                x_right: tile.x_right,
-                y_bottom: tile.y_bottom,
-                y_top: tile.y_top,
-                active: true,
-                durability: 2,
-                falling: false,
-                velocity_y: 0.0,
-                left_obstacle: None,
-                right_obstacle: None,
-                over_obstacle: None,
-                under_obstacle: None,
-                is_bottom_obstacle: false,
-                is_top_obstacle: false,
-                is_leftmost_obstacle: false,
-                is_rightmost_obstacle: false,
-            });
-        }
-    }
-
-    // Determine obstacle relationships and positions
-    for i in 0..obstacles.len() {
-        let mut is_bottom = true;
-        let mut is_top = true;
-        let mut is_leftmost = true;
-        let mut is_rightmost = true;
-
-        for j in 0..obstacles.len() {
-            if i != j {
-                if obstacles[j].x_left < obstacles[i].x_right && obstacles[j].x_right > obstacles[i].x_left {
-                    if obstacles[j].y_bottom > obstacles[i].y_bottom {
-                        obstacles[i].under_obstacle = Some(obstacles[j].id);
-                        is_bottom = false;
-                    }
-                    if obstacles[j].y_top < obstacles[i].y_top {
-                        obstacles[i].over_obstacle = Some(obstacles[j].id);
-                        is_top = false;
-                    }
-                }
-                if obstacles[j].y_bottom < obstacles[i].y_top && obstacles[j].y_top > obstacles[i].y_bottom {
-                    if obstacles[j].x_right < obstacles[i].x_left {
-                        obstacles[i].left_obstacle = Some(obstacles[j].id);
-                        is_leftmost = false;
-                    }
-                    if obstacles[j].x_left > obstacles[i].x_right {
-                        obstacles[i].right_obstacle = Some(obstacles[j].id);
-                        is_rightmost = false;
-                    }
-                }
+/// Saves a recording, or reports whether a replay ended where its recording did. Where a replay
+/// first diverged is reported as it happens, by `step`.
+fn end_session(game_state: &mut GameState, options: &Options) {
+    let position = (game_state.player.x, game_state.player.y);
+
+    match &mut game_state.input_mode {
+        InputMode::Record(recording) => {
+            let path = options.record.as_ref().unwrap();
+            match recording.save(path) {
+                Ok(()) => println!("Recorded {} ticks to {}", recording.inputs.len(), path.display()),
+                Err(e) => println!("Failed to save recording: {}", e),
             }
         }
-
-        obstacles[i].is_bottom_obstacle = is_bottom;
-        obstacles[i].is_top_obstacle = is_top;
-        obstacles[i].is_leftmost_obstacle = is_leftmost;
-        obstacles[i].is_rightmost_obstacle = is_rightmost;
-
-
-        // Ensure the obstacle is marked as both top and bottom if it is alone
-        if is_bottom && is_top {
-            obstacles[i].is_bottom_obstacle = true;
-            obstacles[i].is_top_obstacle = true;
+        InputMode::Replay(recording) if recording.positions.last() == Some(&position) => {
+            println!("Replay of {} ticks matched the recording", recording.inputs.len());
+        }
+        InputMode::Replay(recording) => {
+            println!("Replay diverged: player ended at {:?} instead of {:?}", position, recording.positions.last());
         }
+        InputMode::Live => {}
     }
-
-    obstacles
 }
 
-#[derive(Debug)]
-pub enum TileType {
-    Obstacle,
-    Grass,
-    Sky,
-    Unknown,
+/// Command line options.
+struct Options {
+    tick_rate: u32, // `--tick-rate <hz>`: Simulation ticks per second. Movement is tuned per tick at 60 Hz, so other rates change the game speed
+    headless_frames: Option<u64>, // `--headless <frames>`: Run this many frames without a window or audio device, then exit
+    seed: Option<u64>, // `--seed <n>`: Seed of the random number generator
+    record: Option<PathBuf>, // `--record <file>`: Record the keyboard input to a file
+    replay: Option<PathBuf>, // `--replay <file>`: Play back a recording instead of reading the keyboard
+    scale_mode: Option<ScaleMode>, // `--scale <stretch|integer>`: Stretch the picture over the window, or scale it by whole multiples with bars around it
+    volume: Option<u64>, // `--volume <percent>`: Master volume from 0 to 100
+    crt: bool, // `--crt`: Draw scanlines over the picture, which is kept in the display settings
+    benchmark: bool, // `--benchmark`: Time the ways of scaling the picture to the window and of finding boxes, then exit
 }
 
-#[derive(Debug)]
-pub struct Tile {
-    tile_type: TileType,
-    x_left: f32,
-    x_right: f32,
-    y_bottom: f32,
-    y_top: f32,
-}
\ No newline at end of file
+impl Options {
+    fn from_args() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let value_of = |flag: &str| -> Option<&String> {
+            let index = args.iter().position(|arg| arg == flag)?;
+            Some(args.get(index + 1).unwrap_or_else(|| panic!("{} expects a value", flag)))
+        };
+        let number_of = |flag: &str| -> Option<u64> {
+            value_of(flag).map(|value| value.parse().unwrap_or_else(|_| panic!("{} expects a number", flag)))
+        };
+
+        let options = Options {
+            tick_rate: number_of("--tick-rate").map_or(TICK_RATE, |rate| rate as u32),
+            headless_frames: number_of("--headless"),
+            seed: number_of("--seed"),
+            record: value_of("--record").map(PathBuf::from),
+            replay: value_of("--replay").map(PathBuf::from),
+            scale_mode: value_of("--scale").map(|name| {
+                ScaleMode::parse(name).unwrap_or_else(|| panic!("--scale expects 'stretch' or 'integer'"))
+            }),
+            volume: number_of("--volume"),
+            crt: args.iter().any(|arg| arg == "--crt"),
+            benchmark: args.iter().any(|arg| arg == "--benchmark"),
+        };
+
+        if options.tick_rate == 0 {
+            panic!("--tick-rate expects a positive number of ticks per second");
+        }
+        if options.record.is_some() && options.replay.is_some() {
+            panic!("--record and --replay cannot be combined");
+        }
+
+        options
+    }
+}
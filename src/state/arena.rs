@@ -0,0 +1,181 @@
+use std::ops::{Index, IndexMut};
+
+use crate::state::structs::{Obstacle, ObstacleId};
+
+/// A slot of the arena, holding an obstacle or waiting to be reused.
+#[derive(Clone)]
+struct Slot {
+    generation: u32, // Raised every time the obstacle in the slot is removed
+    obstacle: Option<Obstacle>,
+}
+
+/// The obstacles of a map, each reached through the `ObstacleId` handed out when it was inserted.
+///
+/// Removing an obstacle leaves the others where they are, so their ids stay valid. The slot of a
+/// removed obstacle is reused by the next one inserted, but under a new generation, so ids of
+/// removed obstacles are recognised as dead instead of reaching the new one.
+#[derive(Clone, Default)]
+pub struct ObstacleArena {
+    slots: Vec<Slot>,
+    free: Vec<usize>, // Empty slots, reused last in first out
+    len: usize, // Obstacles currently in the arena
+}
+
+impl ObstacleArena {
+    /// Adds `obstacle` and returns its id, which is also stored in `Obstacle::id`.
+    pub fn insert(&mut self, mut obstacle: Obstacle) -> ObstacleId {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot { generation: 0, obstacle: None });
+            self.slots.len() - 1
+        });
+
+        let id = ObstacleId { index, generation: self.slots[index].generation };
+        obstacle.id = id;
+        self.slots[index].obstacle = Some(obstacle);
+        self.len += 1;
+        id
+    }
+
+    /// Takes the obstacle with `id` out of the arena, or returns None if it was already removed.
+    pub fn remove(&mut self, id: ObstacleId) -> Option<Obstacle> {
+        let slot = self.slots.get_mut(id.index).filter(|slot| slot.generation == id.generation)?;
+        let obstacle = slot.obstacle.take()?;
+
+        slot.generation += 1;
+        self.free.push(id.index);
+        self.len -= 1;
+        Some(obstacle)
+    }
+
+    pub fn get(&self, id: ObstacleId) -> Option<&Obstacle> {
+        self.slots.get(id.index).filter(|slot| slot.generation == id.generation)?.obstacle.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: ObstacleId) -> Option<&mut Obstacle> {
+        self.slots.get_mut(id.index).filter(|slot| slot.generation == id.generation)?.obstacle.as_mut()
+    }
+
+    /// The obstacles in the order of their slots, which is the order they were placed in unless slots were reused.
+    pub fn iter(&self) -> impl Iterator<Item = &Obstacle> {
+        self.slots.iter().filter_map(|slot| slot.obstacle.as_ref())
+    }
+
+    /// The ids of the obstacles, collected so the arena can be changed while going through them.
+    pub fn ids(&self) -> Vec<ObstacleId> {
+        self.iter().map(|obstacle| obstacle.id).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl FromIterator<Obstacle> for ObstacleArena {
+    fn from_iter<I: IntoIterator<Item = Obstacle>>(obstacles: I) -> Self {
+        let mut arena = ObstacleArena::default();
+        for obstacle in obstacles {
+            arena.insert(obstacle);
+        }
+        arena
+    }
+}
+
+/// Panics if the obstacle was removed, like indexing past the end of a `Vec`.
+impl Index<ObstacleId> for ObstacleArena {
+    type Output = Obstacle;
+
+    fn index(&self, id: ObstacleId) -> &Obstacle {
+        self.get(id).unwrap_or_else(|| panic!("obstacle {} was removed", id))
+    }
+}
+
+impl IndexMut<ObstacleId> for ObstacleArena {
+    fn index_mut(&mut self, id: ObstacleId) -> &mut Obstacle {
+        self.get_mut(id).unwrap_or_else(|| panic!("obstacle {} was removed", id))
+    }
+}
+
+/// Prints every field of the obstacles, for debugging.
+pub fn print_obstacles(obstacles: &ObstacleArena, id: &str) {
+    println!("------------------------- {id} -----------------------------");
+    // output obstacle one info for debugging
+    for obstacle in obstacles.iter() {
+        println!(
+            "Obstacle one info: ID: {:?}, X.LEFT: {}, X.RIGHT: {}, Y.BOTTOM: {}, Y.TOP: {}, ACTIVE: {}, DURABILITY: {}, FALLING: {}, VELOCITY_Y: {}, VELOCITY_X: {}, LEFT_OBSTACLE: {:?}, RIGHT_OBSTACLE: {:?}, OVER_OBSTACLE: {:?}, UNDER_OBSTACLE: {:?}, IS_BOTTOM_OBSTACLE: {}, IS_TOP_OBSTACLE: {}, IS_LEFTMOST_OBSTACLE: {}, IS_RIGHTMOST_OBSTACLE: {}",
+            obstacle.id,
+            obstacle.x_left,
+            obstacle.x_right,
+            obstacle.y_bottom,
+            obstacle.y_top,
+            obstacle.active,
+            obstacle.durability,
+            obstacle.falling,
+            obstacle.velocity_y,
+            obstacle.velocity_x,
+            obstacle.left_obstacle,
+            obstacle.right_obstacle,
+            obstacle.over_obstacle,
+            obstacle.under_obstacle,
+            obstacle.is_bottom_obstacle,
+            obstacle.is_top_obstacle,
+            obstacle.is_leftmost_obstacle,
+            obstacle.is_rightmost_obstacle
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::level::grid::{Tile, TileType};
+    use crate::level::loader::extract_obstacles;
+    use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH};
+    use crate::state::constants::physics::BOX_GROUND;
+
+    use super::ObstacleArena;
+
+    /// A row of `count` boxes on the ground.
+    fn boxes(count: usize) -> ObstacleArena {
+        let tiles: Vec<Tile> = (0..count).map(|column| Tile {
+            tile_type: TileType::Obstacle,
+            x_left: (column * TILE_WIDTH) as f32,
+            x_right: ((column + 1) * TILE_WIDTH) as f32,
+            y_bottom: BOX_GROUND,
+            y_top: BOX_GROUND - TILE_HEIGHT as f32,
+        }).collect();
+        extract_obstacles(&tiles)
+    }
+
+    #[test]
+    fn removed_ids_reach_nothing_once_their_slot_is_reused() {
+        let mut obstacles = boxes(2);
+        let ids = obstacles.ids();
+
+        let removed = obstacles.remove(ids[0]).unwrap();
+        assert!(obstacles.get(ids[0]).is_none());
+        let reused = obstacles.insert(removed);
+
+        assert_eq!(reused.index, ids[0].index);
+        assert_ne!(reused, ids[0]);
+        assert!(obstacles.get(ids[0]).is_none());
+        assert!(obstacles.get_mut(ids[0]).is_none());
+        assert!(obstacles.remove(ids[0]).is_none());
+        assert_eq!(obstacles[reused].id, reused);
+        assert_eq!(obstacles[ids[1]].id, ids[1]);
+        assert_eq!(obstacles.len(), 2);
+    }
+
+    #[test]
+    fn removing_a_removed_id_leaves_the_arena_alone() {
+        let mut obstacles = boxes(1);
+        let id = obstacles.ids()[0];
+
+        assert!(obstacles.remove(id).is_some());
+        assert!(obstacles.remove(id).is_none());
+        assert!(obstacles.is_empty());
+        assert_eq!(obstacles.iter().count(), 0);
+    }
+}
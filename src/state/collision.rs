@@ -1,65 +1,352 @@
-use std::thread::sleep;
 use crate::state::core_logic::CoreLogic;
-use rodio::Sink;
-use crate::graphics::sprites::SpriteMaps;
-use crate::state::player::Player;
-use crate::state::structs::{Direction, GameState, Obstacle};
+use crate::audio::mixer::Mixer;
+use crate::state::constants::audio::FALL_MILD_SOUND;
+use crate::graphics::animation::Animator;
+use crate::graphics::atlas::SpriteAtlas;
+use crate::state::constants::animation::{HITBOX, WALK};
+use crate::state::constants::graphics::TILE_HEIGHT;
+use crate::state::constants::physics::{CONTACT_TOLERANCE, GROUND, JUMP_APEX_SPEED, PLAYER_FEET, PLAYER_HITBOX_HEIGHT, PLAYER_HITBOX_WIDTH, PLAYER_HITBOX_X};
+use crate::state::player::{Player, PlayerState};
+use crate::state::structs::{Direction, GameState, Map, Obstacle, ObstacleId};
 
-pub struct CollisionDetection;
+/// An axis-aligned rectangle in world coordinates, `top` being above `bottom`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
 
-impl CoreLogic for CollisionDetection {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        let obstacles = &game_state.all_maps[game_state.current_map_index].obstacles;
-        let direction = game_state.player.direction;
-        let (obstacle, _id) = check_collision(obstacles, &game_state.sprites, &game_state.player, direction == Direction::Left);
+impl Aabb {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Aabb { left: x, top: y, right: x + width, bottom: y + height }
+    }
 
-        if obstacle {
-            game_state.player.vx = 0.0;
-            game_state.player.obstacle_detected = true;
-        } else {
-            game_state.player.obstacle_detected = false;
-        }
+    /// Whether the rectangles share any area. Rectangles which only touch, or overlap by no more than
+    /// `CONTACT_TOLERANCE`, do not.
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.overlaps_horizontally(other) && self.overlaps_vertically(other)
+    }
+
+    fn overlaps_horizontally(&self, other: &Aabb) -> bool {
+        self.left < other.right - CONTACT_TOLERANCE && self.right > other.left + CONTACT_TOLERANCE
+    }
+
+    fn overlaps_vertically(&self, other: &Aabb) -> bool {
+        self.top < other.bottom - CONTACT_TOLERANCE && self.bottom > other.top + CONTACT_TOLERANCE
+    }
 
+    pub fn translated(&self, dx: f32, dy: f32) -> Self {
+        Aabb { left: self.left + dx, top: self.top + dy, right: self.right + dx, bottom: self.bottom + dy }
     }
+
+    pub fn centre_x(&self) -> f32 {
+        (self.left + self.right) / 2.0
+    }
+
+    /// The smallest rectangle covering both rectangles.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Aabb { left: self.left.min(other.left), top: self.top.min(other.top), right: self.right.max(other.right), bottom: self.bottom.max(other.bottom) }
+    }
+}
+
+/// The side of a solid which a moving body ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normal {
+    Left, // Hit while moving right
+    Right, // Hit while moving left
+    Top, // Landed on
+    Bottom, // Hit from below
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    pub solid: ObstacleId, // Obstacle which was hit
+    pub normal: Normal,
+}
+
+/// How far a body could move and what it ran into on the way.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sweep {
+    pub dx: f32,
+    pub dy: f32,
+    pub contacts: Vec<Contact>, // At most one per axis
 }
 
-pub fn check_collision(obstacles: &Vec<Obstacle>, sprites: &SpriteMaps, player: &Player, is_left: bool) -> (bool, Option<usize>) {
-    let mut collision_id: Option<usize> = None;
-    // println!("----------------------------------------------------------------------");
-    let collision = obstacles.iter().enumerate().any(|(index, obstacle)| {
-        // println!("Checking collision: _id: {:?}, x_left: {}, x_right: {}, y_bottom: {}, y_top: {}", obstacle._id, obstacle.x_left, obstacle.x_right, obstacle.y_bottom, obstacle.y_top);
+/// Moves `body` by `dx` and then by `dy`, stopping each axis at the first solid in the way.
+///
+/// Solids the body already overlaps, e.g. a box which fell onto it, are ignored so it can move out of them.
+///
+/// # Parameters
+/// - `body`: The rectangle to move.
+/// - `dx`, `dy`: The movement wanted this tick.
+/// - `solids`: The rectangles which block the body, with the id reported in contacts.
+pub fn sweep(body: Aabb, dx: f32, dy: f32, solids: &[(ObstacleId, Aabb)]) -> Sweep {
+    let mut sweep = Sweep::default();
 
-        if obstacle.active == false {
-            // println!("- - - - Obstacle is not active - - - -");
-            return false;
+    let (dx, contact) = sweep_axis(body, dx, true, solids);
+    sweep.dx = dx;
+    sweep.contacts.extend(contact);
+
+    let (dy, contact) = sweep_axis(body.translated(dx, 0.0), dy, false, solids);
+    sweep.dy = dy;
+    sweep.contacts.extend(contact);
+
+    sweep
+}
+
+fn sweep_axis(body: Aabb, delta: f32, horizontal: bool, solids: &[(ObstacleId, Aabb)]) -> (f32, Option<Contact>) {
+    let mut allowed = delta;
+    let mut contact = None;
+
+    for &(id, solid) in solids {
+        // Only solids level with the body on the other axis can be in the way
+        let level = if horizontal { body.overlaps_vertically(&solid) } else { body.overlaps_horizontally(&solid) };
+        if !level || body.overlaps(&solid) {
+            continue;
         }
 
-        let player_x = if is_left {
-            player.x + (sprites.player[player.left_increment].width as f32 / 2.5)
-        } else {
-            player.x + (sprites.player[player.right_increment].width as f32 / 1.5)
+        let (gap, normal) = match (horizontal, delta > 0.0) {
+            (true, true) => (solid.left - body.right, Normal::Left),
+            (true, false) => (solid.right - body.left, Normal::Right),
+            (false, true) => (solid.top - body.bottom, Normal::Top),
+            (false, false) => (solid.bottom - body.top, Normal::Bottom),
         };
 
-        if player_x > obstacle.x_left && player_x < obstacle.x_right {
-            // println!("Player y: {}, obs.y_bottom: {}, obs.y_top: {}", player.y, obstacle.y_bottom, obstacle.y_top);
+        // A solid is in the way if it lies ahead, closer than where the body would end up
+        let ahead = if delta > 0.0 { gap > -CONTACT_TOLERANCE && gap <= allowed } else { gap < CONTACT_TOLERANCE && gap >= allowed };
+        if delta != 0.0 && ahead {
+            allowed = if delta > 0.0 { gap.max(0.0) } else { gap.min(0.0) };
+            contact = Some(Contact { solid: id, normal });
+        }
+    }
+
+    (allowed, contact)
+}
+
+/// Returns the id of the first solid which `body` would overlap if it moved by (`dx`, `dy`) but
+/// does not overlap now, e.g. the box beneath a body standing on it.
+pub fn touching(body: Aabb, dx: f32, dy: f32, solids: &[(ObstacleId, Aabb)]) -> Option<ObstacleId> {
+    let moved = body.translated(dx, dy);
+    solids.iter().find(|(_, solid)| moved.overlaps(solid) && !body.overlaps(solid)).map(|&(id, _)| id)
+}
 
-            if player.y >= obstacle.y_top + 10.0 && player.y <= obstacle.y_bottom + 25.0 {
-                // println!("Collision of x detected: p_x: {}, obs.x_left: {}, obs.x_right: {}", player_x, obstacle.x_left, obstacle.x_right);
+/// Returns the solid part of the player: the `hitbox` slice of the walk frame if drawn in Aseprite,
+/// otherwise a box of `PLAYER_HITBOX_WIDTH` x `PLAYER_HITBOX_HEIGHT` standing on their feet.
+pub fn player_hitbox(player: &Player, sprites: &SpriteAtlas) -> Aabb {
+    let feet = player.y + PLAYER_FEET;
 
-                collision_id = Some(index);
-                // println!("Collision detected with obstacle _id {:?} x.left {}, x.right: {}, obstacle.y_bottom: {}, obstacle.y_top: {}", obstacle._id, obstacle.x_left, obstacle.x_right , obstacle.y_bottom + 25.0, obstacle.y_top + 25.0);
-                true
-            } else {
-                false
-            }
-        } else {
-            false
+    match Animator::new(WALK).slice(sprites, player.direction, HITBOX) {
+        Some(rect) => {
+            let top = feet - sprites.clip_frame(WALK, 0, player.direction).height as f32;
+            Aabb::new(player.x + rect.x as f32, top + rect.y as f32, rect.width as f32, rect.height as f32)
         }
-    });
+        None => Aabb::new(player.x + PLAYER_HITBOX_X, feet - PLAYER_HITBOX_HEIGHT, PLAYER_HITBOX_WIDTH, PLAYER_HITBOX_HEIGHT),
+    }
+}
+
+/// Returns the solid area of an obstacle. Boxes are drawn with their top edge at `y_bottom`, so
+/// it is the tile below the stored coordinates.
+pub fn obstacle_hitbox(obstacle: &Obstacle) -> Aabb {
+    Aabb::new(obstacle.x_left, obstacle.y_bottom, obstacle.x_right - obstacle.x_left, TILE_HEIGHT as f32)
+}
 
-    if let Some(_id) = collision_id {
+/// Returns the hitboxes of the active obstacles of `map` which may lie within `area`, with the
+/// id of their obstacle.
+pub fn obstacle_hitboxes(map: &Map, area: Aabb) -> Vec<(ObstacleId, Aabb)> {
+    map.obstacles_near(area).into_iter()
+        .filter(|&id| map.obstacles[id].active)
+        .map(|id| (id, obstacle_hitbox(&map.obstacles[id])))
+        .collect()
+}
+
+/// Returns the area covered by `body` and the `reach` pixels in front of it when facing `direction`.
+pub fn reach_box(body: Aabb, direction: Direction, reach: f32) -> Aabb {
+    let reach = if direction == Direction::Left { -reach } else { reach };
+    body.union(&body.translated(reach, 0.0))
+}
+
+/// Returns the id of the first obstacle of `map` within `reach` of the front of `body` when facing `direction`.
+pub fn solid_in_front(map: &Map, body: Aabb, direction: Direction, reach: f32) -> Option<ObstacleId> {
+    let solids = obstacle_hitboxes(map, reach_box(body, direction, reach));
+    touching(body, if direction == Direction::Left { -reach } else { reach }, 0.0, &solids)
+}
+
+/// Returns the id of the first obstacle within `reach` of the front of the player.
+pub fn obstacle_in_front(game_state: &GameState, reach: f32) -> Option<ObstacleId> {
+    let body = player_hitbox(&game_state.player, &game_state.sprites);
+    solid_in_front(&game_state.all_maps[game_state.current_map_index], body, game_state.player.direction, reach)
+}
+
+/// How a body moved through the boxes of a map, see `move_body`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Movement {
+    pub dx: f32,
+    pub dy: f32,
+    pub on_obstacle: bool, // Came down on the top of a box
+    pub on_ground: bool, // Came down on the ground
+    pub hit_wall: bool, // Stopped by the side of a box
+    pub hit_ceiling: bool, // Stopped by the bottom of a box
+}
+
+/// Moves `body` by up to (`dx`, `dy`), stopping at the boxes of `map` in the way and at the ground,
+/// which stops a body like the top of a box. Shared by the player and the enemies.
+///
+/// # Parameters
+/// - `to_ground`: Distance the body can fall before it stands on the ground.
+pub fn move_body(map: &Map, body: Aabb, dx: f32, dy: f32, to_ground: f32) -> Movement {
+    let solids = obstacle_hitboxes(map, body.union(&body.translated(dx, dy)));
+    let sweep = sweep(body, dx, dy, &solids);
+    let to_ground = to_ground.max(0.0);
+    let dy = sweep.dy.min(to_ground);
+
+    let mut movement = Movement { dx: sweep.dx, dy, on_obstacle: false, on_ground: false, hit_wall: false, hit_ceiling: false };
+    for contact in sweep.contacts {
+        match contact.normal {
+            Normal::Left | Normal::Right => movement.hit_wall = true,
+            Normal::Bottom => movement.hit_ceiling = true,
+            // The ground may come before the box
+            Normal::Top => movement.on_obstacle = dy == sweep.dy,
+        }
+    }
+    movement.on_ground = !movement.on_obstacle && dy >= to_ground;
+    movement
+}
+
+/// Moves the box with `id` sideways by up to `dx`, stopping at the boxes in the way and at the edges of the map.
+///
+/// # Returns
+/// How far the box moved, which is `dx` unless it was stopped.
+pub fn slide_obstacle(map: &mut Map, id: ObstacleId, dx: f32) -> f32 {
+    let obstacle = map.obstacles[id];
+    let body = obstacle_hitbox(&obstacle);
+    let solids: Vec<(ObstacleId, Aabb)> = obstacle_hitboxes(map, body.union(&body.translated(dx, 0.0)))
+        .into_iter()
+        .filter(|&(other, _)| other != id)
+        .collect();
+    let dx = sweep(body, dx, 0.0, &solids).dx.max(-obstacle.x_left).min(map.width as f32 - obstacle.x_right);
+
+    if dx != 0.0 {
+        map.move_obstacle(id, dx, 0.0);
+    }
+    dx
+}
+
+/// Moves the player by their velocity, stopping at the boxes in the way, and lands them on the box
+/// or the ground they come down on.
+///
+/// Gravity pulls the player down every tick, so a player standing on a box keeps running into its
+/// top and walks off the edge as soon as it is no longer below them.
+pub fn move_player(game_state: &mut GameState, mixer: &mut Mixer) {
+    let player = &game_state.player;
+    // A knockback pushes the player whichever way they face
+    let dx = if player.direction == Direction::Left { -player.vx } else { player.vx } + player.knockback;
+    let body = player_hitbox(player, &game_state.sprites);
+    let movement = move_body(&game_state.all_maps[game_state.current_map_index], body, dx, player.vy, GROUND - player.y);
+
+    let player = &mut game_state.player;
+    player.x += movement.dx;
+    player.y += movement.dy;
+
+    if movement.hit_wall {
+        player.vx = 0.0;
+        player.knockback = 0.0;
+    }
+    if movement.hit_ceiling {
+        player.vy = 0.0;
+    }
+    let (on_obstacle, on_ground) = (movement.on_obstacle, movement.on_ground);
+
+    if on_obstacle || on_ground {
+        if on_ground && player.state == PlayerState::InAir {
+            mixer.play(FALL_MILD_SOUND, 2500);
+        }
+        player.vy = 0.0;
+        player.is_jumping = false;
+        player.state = if on_obstacle { PlayerState::OnObstacle } else { PlayerState::OnGround };
+    } else {
+        player.state = PlayerState::InAir;
+        player.is_jumping = true;
     }
+    player.on_obstacle = on_obstacle;
+    player.on_ground = on_ground;
+    player.almost_ground = !on_obstacle && !on_ground && player.vy.abs() < JUMP_APEX_SPEED;
+}
+
+pub struct CollisionDetection;
 
-    (collision, collision_id)
+impl CoreLogic for CollisionDetection {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        // Pressing against a box keeps the player from building up speed
+        game_state.player.obstacle_detected = obstacle_in_front(game_state, 1.0).is_some();
+        if game_state.player.obstacle_detected {
+            game_state.player.vx = 0.0;
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::state::constants::audio::FALL_MILD_SOUND;
+    use crate::state::constants::physics::{BOX_GROUND, GROUND, MAX_VELOCITY, PLAYER_FEET};
+    use crate::state::core_logic::{damage_player, CoreLogic, ModifyPosition};
+    use crate::state::game::testing::{game_state, mixer, place_boxes};
+    use crate::state::gravity::ApplyGravity;
+    use crate::state::player::PlayerState;
+    use crate::state::structs::{Direction, GameState};
+    use crate::audio::mixer::Mixer;
+
+    use super::player_hitbox;
+
+    fn tick(game_state: &mut GameState, mixer: &mut Mixer, ticks: usize) {
+        for _ in 0..ticks {
+            ApplyGravity.execute(game_state, mixer);
+            ModifyPosition.execute(game_state, mixer);
+        }
+    }
+
+    #[test]
+    fn lands_on_a_box_stays_on_it_and_falls_off_its_edge() {
+        let mut game_state = game_state();
+        let (mut mixer, played) = mixer();
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        place_boxes(&mut game_state, &[(body.left, BOX_GROUND)]);
+        game_state.player.y = BOX_GROUND - PLAYER_FEET - 40.0;
+
+        tick(&mut game_state, &mut mixer, 30);
+        assert_eq!(game_state.player.state, PlayerState::OnObstacle);
+        assert!(game_state.player.on_obstacle && !game_state.player.on_ground);
+        assert_eq!(game_state.player.y, BOX_GROUND - PLAYER_FEET);
+        assert!(played.borrow().is_empty());
+
+        // Walking on past the edge of the box drops the player to the ground
+        game_state.player.vx = MAX_VELOCITY;
+        tick(&mut game_state, &mut mixer, 30);
+        assert_eq!(game_state.player.state, PlayerState::OnGround);
+        assert!(game_state.player.on_ground && !game_state.player.on_obstacle);
+        assert_eq!(game_state.player.y, GROUND);
+        assert_eq!(*played.borrow(), vec![FALL_MILD_SOUND]);
+    }
+
+    #[test]
+    fn knockback_pushes_away_from_the_source_and_stops_at_a_box() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        let start_x = game_state.player.x;
+        let id = place_boxes(&mut game_state, &[(body.right + 4.0, BOX_GROUND)])[0];
+
+        // Hit from the left while facing it, so the knockback goes backwards
+        game_state.player.direction = Direction::Left;
+        damage_player(&mut game_state, body.left - 10.0);
+        assert!(game_state.player.knockback > 0.0);
+
+        tick(&mut game_state, &mut mixer, 30);
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        let obstacle = &game_state.all_maps[game_state.current_map_index].obstacles[id];
+        assert!(game_state.player.x > start_x);
+        assert_eq!(body.right, obstacle.x_left);
+        assert_eq!(game_state.player.knockback, 0.0);
+    }
+}
@@ -2,36 +2,156 @@ pub mod graphics {
     use std::time::Duration;
 
     pub const FRAME_DURATION: Duration = Duration::from_nanos(16666667); // 16.6666667 ms = 60 FPS
+    pub const TICK_RATE: u32 = 60; // Simulation ticks per second, the physics constants are tuned for 60
+    pub const MAX_TICKS_PER_FRAME: u32 = 5; // Ticks run to catch up after a slow frame before the backlog is dropped
+
+    pub const HEADLESS_CAPTURE_INTERVAL: u64 = 60; // Frames between the frames kept by a headless run
     pub const BACKGROUND_CHANGE_INTERVAL: Duration = Duration::from_secs(1);
 
+    pub const WINDOW_BUFFER_WIDTH: usize = 256;
+    pub const WINDOW_BUFFER_HEIGHT: usize = 224;
     pub const SCALED_WINDOW_WIDTH: usize = 960;
     pub const SCALED_WINDOW_HEIGHT: usize = 540;
+    pub const DISPLAY_SETTINGS_FILE: &str = "display.cfg";
+    pub const WINDOW_TITLE: &str = "Age of Panda";
     pub const TILE_WIDTH: usize = 16;
     pub const TILE_HEIGHT: usize = 16;
 
-    pub const FIXED_PLAYER_X: isize = 109;
-
-    pub const KICK_FRAME_DURATION: u32 = 8;
-
-    pub const RIGHT_JUMP_INITIATED: usize = 1;
-    pub const RIGHT_JUMP_MID_AIR: usize = 2;
-    pub const LEFT_JUMP_INITIATED: usize = 4;
-    pub const LEFT_JUMP_MID_AIR: usize = 5;
-
     pub const SHADOW_SMALL: usize = 0;
     pub const SHADOW_LARGE: usize = 1;
     pub const SHADOW_MEDIUM: usize = 2;
+
+    pub const STAGE_CLEAR_BRIGHTNESS: f32 = 0.5; // Share of the original colour kept when washing out the screen
+    pub const SCANLINE_BRIGHTNESS: f32 = 0.6; // Share of the original colour kept on the scanlines of the CRT filter
+    pub const LETTERBOX_COLOUR: u32 = 0xFF000000; // Bars around the picture when scaling by whole multiples
+    pub const BENCHMARK_FRAMES: u32 = 500; // Frames scaled per setting by `--benchmark`
+}
+
+pub mod animation {
+    pub const SPRITE_MANIFEST: &str = "assets/sprites/sprites.manifest"; // Sprite sheets and animation clips
+
+    // Sheets and clips the game looks up by name
+    pub const METAL_BOX: &str = "metal_box";
+    pub const SHADOW: &str = "shadow";
+    pub const GAME_OVER: &str = "game_over"; // Played once the player runs out of hearts
+    pub const WALK: &str = "walk";
+    pub const KICK: &str = "kick";
+    pub const JUMP_START: &str = "jump_start";
+    pub const JUMP_AIR: &str = "jump_air";
+    pub const ENEMY_WALK: &str = "enemy_walk";
+    pub const HEART: &str = "heart";
+    pub const HEART_EMPTY: &str = "heart_empty";
+    pub const TOXIC_TRAP: &str = "toxic_trap";
+    pub const HITBOX: &str = "hitbox"; // Slice marking the solid part of a sprite
+
+    pub const INVINCIBLE_FLASH_TICKS: u64 = 4; // Ticks the player spends lit up and then unlit while invincible
+    pub const INVINCIBLE_FLASH_COLOUR: u32 = 0xFFFFFF;
 }
 
 pub mod physics {
     pub const GRAVITY: f32 = 0.5;
-    pub const JUMP_VELOCITY: f32 = -5.0;
+    pub const PLAYER_GRAVITY: f32 = 1.0; // The player falls twice as fast as boxes and enemies
+    pub const JUMP_VELOCITY: f32 = -10.0;
+    pub const JUMP_APEX_SPEED: f32 = 5.0; // Vertical speed below which the player is drawn at the top of a jump
     pub const MAX_VELOCITY: f32 = 2.0;
     pub const ACCELERATION: f32 = 0.1;
     pub const FRICTION: f32 = 0.2;
     pub const GROUND: f32 = 205.0;
     pub const LOWER_BOUND: f32 = 0.0;
-    pub const UPPER_BOUND: f32 = 225.0;
+    pub const KNOCKBACK_DISTANCE: f32 = 32.0; // Distance the player is pushed back when hit
+    pub const KNOCKBACK_SPEED: f32 = 2.0; // Speed at which the player is pushed back
+    pub const UPPER_BOUND_MARGIN: f32 = 31.0; // Distance from the right edge of the map the player cannot pass
+
+    pub const PLAYER_FEET: f32 = 10.0; // Distance from the y-coordinate of the player down to their feet
+    pub const PLAYER_HITBOX_X: f32 = 5.0; // Solid part of the walk frame, unless drawn as a slice in Aseprite
+    pub const PLAYER_HITBOX_WIDTH: f32 = 13.0;
+    pub const PLAYER_HITBOX_HEIGHT: f32 = 30.0;
+    pub const CONTACT_TOLERANCE: f32 = 0.01; // Overlap still treated as touching, so rounding cannot push a body into a box
+    pub const BOX_KICK_REACH: f32 = 4.0; // Distance in front of the player within which a box can be kicked
+    pub const BOX_GROUND: f32 = 200.0; // `y_bottom` of a box standing on the ground
+    pub const BOX_GRAVITY: f32 = 0.5;
+    pub const MAX_BOX_FALL_SPEED: f32 = 8.0; // Less than a tile per tick, so a falling box never skips past another
+    pub const HEAVY_FALL_HEIGHT: f32 = 32.0; // Boxes falling at least this far land with the heavy sound
+    pub const PUSH_SPEED: f32 = 0.5; // Speed at which the player walks a box along the ground
+    pub const LAUNCH_SPEED: f32 = 5.0; // Speed of a box sent sliding by a strong kick
+    pub const SLIDE_FRICTION: f32 = 0.1; // Speed a sliding box loses each tick on the ground or on other boxes
+}
+
+pub mod level {
+    pub const LEVELS_DIRECTORY: &str = "levels";
+    pub const LEVEL_FILE_EXTENSION: &str = "lvl";
+    pub const PARALLAX_FILE_EXTENSION: &str = "parallax"; // Scenery of the level file with the same name
+    pub const DEFAULT_PARALLAX_FILE: &str = "default.parallax"; // Scenery of levels without their own parallax file
+    pub const TILE_Y_OFFSET: f32 = 8.0; // Shifts the grid down so that boxes in the second to last row rest on the ground
+    pub const PLAYER_START_X: f32 = 100.0;
+    pub const PLAYER_START_Y: f32 = 176.0;
+    pub const TRANSITION_FRAMES: usize = 60; // Duration of the fade between two maps, the map is swapped halfway
+    pub const BENCHMARK_BOXES: [usize; 3] = [1000, 5000, 20000]; // Boxes in the generated levels of `--benchmark`
+}
+
+pub mod enemy {
+    pub const ENEMY_HEALTH: u8 = 2;
+    pub const PATROL_SPEED: f32 = 0.5;
+    pub const PATROL_RANGE: f32 = 32.0; // Distance an enemy patrols to either side of its spawn point
+    pub const CHASE_SPEED: f32 = 1.2;
+    pub const CHASE_RANGE: f32 = 80.0; // Distance at which an enemy starts chasing the player
+    pub const ENEMY_JUMP_VELOCITY: f32 = -5.0;
+    pub const OBSTACLE_LOOKAHEAD: f32 = 4.0; // How far ahead an enemy looks for boxes to jump over
+    pub const STUN_FRAMES: usize = 45;
+    pub const KICK_RANGE: f32 = 12.0; // Distance in front of the player within which an enemy can be kicked
+    pub const KICK_KNOCKBACK: f32 = 3.0; // Speed at which a kicked enemy is pushed back
+    pub const KNOCKBACK_FRICTION: f32 = 0.5; // Knockback speed an enemy loses each tick, so a kick pushes it back about 10 pixels
+}
+
+pub mod hazard {
+    pub const HAZARD_MARGIN: f32 = 3.0; // Transparent border of the toxic trap sprite which does not hurt
+}
+
+pub mod camera {
+    pub const DEAD_ZONE_LEFT: f32 = 96.0; // Screen area in which the player moves without the camera following
+    pub const DEAD_ZONE_RIGHT: f32 = 144.0;
+    pub const LOOK_AHEAD: f32 = 24.0; // Distance the camera leads the player by in the facing direction
+    pub const LOOK_AHEAD_SMOOTHING: f32 = 0.05; // Share of the remaining look-ahead change applied per tick
+    pub const DAMAGE_SHAKE_MAGNITUDE: f32 = 3.0;
+    pub const DAMAGE_SHAKE_TICKS: u32 = 15;
+    pub const DEFEAT_SHAKE_MAGNITUDE: f32 = 2.0;
+    pub const DEFEAT_SHAKE_TICKS: u32 = 10;
+}
+
+pub mod input {
+    pub const CONTROLS_FILE: &str = "controls.cfg";
+    pub const REBINDING_BRIGHTNESS: f32 = 0.25;
+}
+
+pub mod menu {
+    use minifb::Key;
+
+    // Fixed keys for moving through menus, on top of the bound pause keys which open and close the pause menu
+    pub const UP_KEYS: [Key; 2] = [Key::Up, Key::W];
+    pub const DOWN_KEYS: [Key; 2] = [Key::Down, Key::S];
+    pub const CONFIRM_KEYS: [Key; 3] = [Key::Enter, Key::Space, Key::NumPadEnter];
+    pub const BACK_KEY: Key = Key::Escape;
+    pub const PAUSED_SYSTEMS: [&str; 2] = ["AnimateHearts", "AnimateHazards"]; // Systems which keep running behind the pause menu
+
+    pub const TITLE: &str = "AGE OF PANDA";
+    pub const HEADING_Y: isize = 64; // Top of the heading above a menu
+    pub const GAME_OVER_HEADING_Y: isize = 12; // Above the face shown at the end of the game over animation
+    pub const HEADING_GAP: isize = 12; // Pixels between a heading and the first item of its menu
+    pub const HINT: &str = "F1 CONTROLS   F11 FULLSCREEN"; // Shown at the bottom of the title screen
+    pub const HIGHLIGHT_COLOUR: u32 = 0xF4D35E; // Colour of the selected menu item
+    pub const DIM_BRIGHTNESS: f32 = 0.5; // Share of the original colour kept when dimming the game behind the title and pause menus
+    pub const STAGE_CLEAR_MENU_DELAY: u64 = 60; // Ticks the stage clear screen is shown before its menu, so held keys do not skip it
+}
+
+pub mod hud {
+    pub const FONT_FILE: &str = "assets/fonts/small.font";
+    pub const TEXT_COLOUR: u32 = 0xFFFFFF;
+    pub const SHADOW_COLOUR: u32 = 0x1A1C2C;
+    pub const DEBUG_COLOUR: u32 = 0xF4D35E;
+    pub const MARGIN: isize = 2; // Pixels between the text and the edges of the screen
+
+    pub const DEFEAT_SCORE: u32 = 100; // Points for defeating an enemy
+    pub const BOX_SCORE: u32 = 25; // Points for destroying a box
 }
 
 pub mod audio {
@@ -46,5 +166,9 @@ pub mod audio {
     pub const EXPLOSION_SOUND: usize = 8;
     pub const KICK_SOUND: usize = 9;
     pub const KICK_BOX_SOUND: usize = 10;
+
+    pub const MASTER_VOLUME: f32 = 0.8;
+    pub const FOOTSTEP_VOLUME: f32 = 0.5;
+    pub const MAX_VOICES: usize = 8; // Number of sounds which may play simultaneously
 }
 
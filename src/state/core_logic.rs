@@ -1,30 +1,29 @@
-use crate::graphics::render_graphics::render_pixel_buffer;
-use crate::graphics::sprites::draw_sprite;
-use crate::state::collision::CollisionDetection;
+use std::cmp::Ordering;
+
+use crate::state::collision::{move_player, player_hitbox, CollisionDetection};
 use crate::state::gravity::{ApplyGravity, JumpingObstacles};
-use crate::state::player::Player;
-use rodio::Sink;
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
-use std::thread::sleep;
-use crate::state::constants::physics::{ACCELERATION, GROUND, LOWER_BOUND, MAX_VELOCITY, UPPER_BOUND};
-use crate::state::structs::{Direction, GameState, Obstacle, ObstacleId};
-
-pub fn execute_core_logic(game_state: &mut GameState, core_logic_operations: &HashMap<String, Rc<RefCell<dyn CoreLogic>>>, sink: &mut Sink) {
-    for (_, core_logic_operation) in core_logic_operations.iter() {
-        core_logic_operation.borrow().execute(game_state, sink);
-    }
-}
+use crate::state::enemy::{EnemyContact, MoveEnemies, SpawnEnemies};
+use crate::state::hazard::{AnimateHazards, HazardContact, SpawnHazards};
+use crate::state::scheduler::{ScheduleError, Scheduler, Stage};
+use crate::state::scene::Scene;
+use crate::state::transition::MapTransition;
+use crate::input::action::Action;
+use crate::input::handler::{InputLogicMap, ProcessInput};
+use crate::audio::mixer::Mixer;
+use crate::state::constants::animation::{JUMP_AIR, JUMP_START, KICK, WALK};
+use crate::state::constants::physics::{ACCELERATION, GROUND, KNOCKBACK_DISTANCE, KNOCKBACK_SPEED, LOWER_BOUND, MAX_VELOCITY, UPPER_BOUND_MARGIN};
+use crate::state::constants::camera::{DAMAGE_SHAKE_MAGNITUDE, DAMAGE_SHAKE_TICKS};
+use crate::state::structs::{Direction, GameState};
+use crate::graphics::camera::FollowCamera;
 
 pub trait CoreLogic {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink);
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer);
 }
 
 pub struct VerticalBounds;
 
 impl CoreLogic for VerticalBounds {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
         // println!("Player X: {}, Y: {}", game_state.player.x, game_state.player.y);
 
         // Prevent the player from moving out vertical (y) bounds
@@ -38,15 +37,17 @@ impl CoreLogic for VerticalBounds {
 pub struct HorizontalBounds;
 
 impl CoreLogic for HorizontalBounds {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
         // Prevent the player from moving out horizontal (x) bounds
+        let map_upper_bound = game_state.all_maps[game_state.current_map_index].width as f32 - UPPER_BOUND_MARGIN;
         if game_state.player.x < LOWER_BOUND {
             game_state.player.x = LOWER_BOUND;
             game_state.player.vx = 0.0;
-        } else if game_state.player.x >= UPPER_BOUND {
-            // game_state.player.x = 0.0;
-            // game_state.player.vx = 0.0;
-            // game_state.current_map_index += 1
+            game_state.player.knockback = 0.0;
+        } else if game_state.player.x >= map_upper_bound {
+            game_state.player.x = map_upper_bound;
+            game_state.player.vx = 0.0;
+            game_state.player.knockback = 0.0;
         }
     }
 }
@@ -54,48 +55,52 @@ impl CoreLogic for HorizontalBounds {
 pub struct CheckGameOver;
 
 impl CoreLogic for CheckGameOver {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
 
-        // if damage has been taken the player must be displaced back to the left based on designated_x
+        // After taking damage the player is knocked back by `move_player` until reaching designated_x or a box
         if game_state.damage_taken {
-            if game_state.player.x > game_state.designated_x {
-                game_state.player.x -= 2.0;
-                game_state.player.invincible = true;
+            let player = &mut game_state.player;
+            let reached = if player.knockback < 0.0 { player.x <= game_state.designated_x } else { player.x >= game_state.designated_x };
+            if player.knockback != 0.0 && !reached {
+                player.invincible = true;
             } else {
+                player.knockback = 0.0;
+                player.invincible = false;
                 game_state.damage_taken = false;
-                game_state.player.invincible = false;
             }
         }
 
 
-        if game_state.player.game_over {
-
-            for _ in 0..9 {
-                draw_sprite(0,0,
-                            &game_state.sprites.game_over[game_state.game_over_index],
-                            game_state.window_buffer,
-                            game_state.all_maps[game_state.current_map_index].width
-                );
-                render_pixel_buffer(game_state);
-                game_state.game_over_index += 1;
-
-                let amount_to_sleep = if game_state.game_over_index >= 6 {
-                    500
-                } else {
-                    100
-                };
-
-                sleep(std::time::Duration::from_millis(amount_to_sleep));
-            }
-
-            // Reset game state
-            game_state.game_over_index = 0;
-            game_state.player = Player::new(0.0, GROUND); // Reset player state
-            game_state.mountains_sprite_frame_index = 0;
+        // The game over scene plays its animation and lets the player try again
+        if game_state.player.game_over && game_state.scene == Scene::Playing {
+            game_state.scene = Scene::GameOver { ticks: 0, selected: 0 };
+            println!("Game over");
         }
     }
 }
 
+/// Takes one heart from the player and knocks them back, away from `from_x`, the centre of whatever
+/// hurt them. A hit from straight above knocks them back against the way they face.
+pub fn damage_player(game_state: &mut GameState, from_x: f32) {
+    let centre_x = player_hitbox(&game_state.player, &game_state.sprites).centre_x();
+    let away = match from_x.total_cmp(&centre_x) {
+        Ordering::Less => 1.0,
+        Ordering::Greater => -1.0,
+        Ordering::Equal if game_state.player.direction == Direction::Left => 1.0,
+        Ordering::Equal => -1.0,
+    };
+
+    game_state.player.health = game_state.player.health.saturating_sub(1);
+    game_state.player.knockback = away * KNOCKBACK_SPEED;
+    game_state.damage_taken = true;
+    game_state.designated_x = game_state.player.x + away * KNOCKBACK_DISTANCE;
+    game_state.camera.shake(DAMAGE_SHAKE_MAGNITUDE, DAMAGE_SHAKE_TICKS);
+
+    if game_state.player.health == 0 {
+        game_state.player.game_over = true;
+    }
+}
+
 pub fn increase_velocity(game_state: &mut GameState) {
     game_state.player.vx += ACCELERATION;
 
@@ -123,157 +128,110 @@ pub fn decrease_velocity(game_state: &mut GameState) {
 pub struct ModifyPosition;
 
 impl CoreLogic for ModifyPosition {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        if game_state.player.direction == Direction::Left {
-            game_state.player.x -= game_state.player.vx;
-        } else {
-            game_state.player.x += game_state.player.vx;
-        }
-        game_state.player.y += game_state.player.vy;
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer) {
+        move_player(game_state, mixer);
     }
 }
 
-pub struct AlternateHeartSpriteFrames;
+/// Counts the ticks shown by the timer, which stops once the stage is cleared or the game is over.
+pub struct AdvanceTimer;
 
-impl CoreLogic for AlternateHeartSpriteFrames {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        // Alternate between the heart sprite frames every 200 milliseconds
-        if game_state.last_heart_sprite_frame_index_change.elapsed() >= std::time::Duration::from_millis(500) {
-            game_state.heart_sprite_frame_index = (game_state.heart_sprite_frame_index + 1) % 2; // Cycle between 0 and 1
-            game_state.last_heart_sprite_frame_index_change = std::time::Instant::now(); // Reset the timer to current time
+impl CoreLogic for AdvanceTimer {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        if game_state.scene == Scene::Playing {
+            game_state.play_ticks += 1;
         }
     }
 }
 
-pub struct AlternateGroundSpriteFrames;
+pub struct AnimateHearts;
 
-impl CoreLogic for AlternateGroundSpriteFrames {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        // Alternate between the grass sprite frames every 200 milliseconds
-        if game_state.last_ground_sprite_frame_index_change.elapsed() >= std::time::Duration::from_millis(200) {
-            game_state.ground_sprite_frame_index = (game_state.ground_sprite_frame_index + 1) % 2; // Cycle between 0 and 1
-            game_state.last_ground_sprite_frame_index_change = std::time::Instant::now(); // Reset the timer to current time
-        }
+impl CoreLogic for AnimateHearts {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        game_state.heart_animator.update();
     }
 }
 
-pub struct AlternateLightHouseSpriteFrames;
+pub struct AnimatePlayer;
+
+impl CoreLogic for AnimatePlayer {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        let player = &mut game_state.player;
+        let walking = game_state.held_actions & (Action::MoveLeft.bit() | Action::MoveRight.bit()) != 0;
+
+        // Pick the clip for what the player is doing, the legs only move while a move key is held
+        let clip = if player.is_kicking {
+            KICK
+        } else if player.almost_ground && !player.on_obstacle {
+            JUMP_START
+        } else if !player.on_ground && !player.on_obstacle {
+            JUMP_AIR
+        } else {
+            WALK
+        };
+
+        player.animator.play(clip);
+        if clip != WALK || walking {
+            player.animator.update();
+        }
 
-impl CoreLogic for AlternateLightHouseSpriteFrames {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        // Alternate between the lighthouse sprite frames every 200 milliseconds
-        if game_state.last_light_house_sprite_frame_index_change.elapsed() >= std::time::Duration::from_millis(750) {
-            game_state.lighthouse_sprite_frame_index = (game_state.lighthouse_sprite_frame_index + 1) % 4; // Cycle between 0 and 3
-            game_state.last_light_house_sprite_frame_index_change = std::time::Instant::now(); // Reset the timer to current time
+        // Return to the other clips once the kick has played
+        if player.is_kicking && player.animator.is_finished(&game_state.sprites) {
+            player.is_kicking = false;
         }
     }
 }
 
-
 pub struct SpawnObstacles;
 
 impl CoreLogic for SpawnObstacles {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        // Populate the current map with the obstacles placed in its level file
         if !game_state.obstacle_spawned {
-            spawn_obstacle(200.0, 200.0, game_state.all_maps[game_state.current_map_index].obstacles);
-            spawn_obstacle(350.0, 200.0, &mut game_state.all_maps[game_state.current_map_index].obstacles);
+            let map = &mut game_state.all_maps[game_state.current_map_index];
+            map.set_obstacles(map.obstacle_layout.clone());
             game_state.obstacle_spawned = true;
-        }
-
-
-    }
-}
-
 
-fn spawn_obstacle(x: f32, y: f32, obstacles: &mut Vec<Obstacle>) {
-    let x_left = x;
-    let x_right = x + 16.0;
-    let y_bottom = y;
-    let y_top = y_bottom - 16.0;
-
-    // Add a new obstacle
-    obstacles.push(Obstacle {
-        id: ObstacleId(obstacles.len()),
-        x_left,
-        x_right,
-        y_bottom,
-        y_top,
-        active: true,
-        durability: 2,
-        falling: false,
-        velocity_y: 0.0,
-        left_obstacle: None,
-        right_obstacle: None,
-        over_obstacle: None,
-        under_obstacle: None,
-        is_bottom_obstacle: false,
-        is_top_obstacle: true,
-        is_leftmost_obstacle: false,
-        is_rightmost_obstacle: false,
-    });
-
-    println!("Spawned obstacle at x: {}, y: {}", x, y_bottom);
-}
-
-
-fn spawn_stacked_obstacles(
-    x: f32,
-    y_start: f32,
-    count: usize,
-    obstacles: &mut Vec<Obstacle>,
-) {
-    let mut y_bottom = y_start;
-    let mut y_top = y_bottom - 16.0;
-
-    for i in 0..count {
-        // Add a new obstacle
-        obstacles.push(Obstacle {
-            id: ObstacleId(obstacles.len()),
-            x_left: x,
-            x_right: x + 16.0,
-            y_bottom,
-            y_top,
-            active: true,
-            durability: 2,
-            falling: false,
-            velocity_y: 0.0,
-            left_obstacle: None,
-            right_obstacle: None,
-            over_obstacle: if i > 0 { Some(ObstacleId(obstacles.len() - 1)) } else { None },
-            under_obstacle: None,
-            is_bottom_obstacle: i == 0,
-            is_top_obstacle: i == count - 1,
-            is_leftmost_obstacle: false,
-            is_rightmost_obstacle: false,
-        });
-
-        // Update y-coordinates for the next obstacle
-        y_bottom = y_top;
-        y_top -= 16.0;
+            println!("Spawned {} obstacles for map {} '{}'", map.obstacles.len(), map.id, map.name);
+        }
     }
-
-    println!(
-        "Spawned {} stacked obstacles at x: {}, starting y: {}",
-        count, x, y_start
-    );
 }
 
-pub fn initialize_core_logic_map() -> HashMap<String, Rc<RefCell<dyn CoreLogic>>> {
-    let mut logic_map: HashMap<String, Rc<RefCell<dyn CoreLogic>>> = HashMap::new();
-
-    logic_map.insert("AlternateLayerThreeSpriteFrames".to_string(), Rc::new(RefCell::new(AlternateGroundSpriteFrames)));
-    logic_map.insert("AlternateHeartSprites".to_string(), Rc::new(RefCell::new(AlternateHeartSpriteFrames)));
-    logic_map.insert("AlternateLightHouseSprites".to_string(), Rc::new(RefCell::new(AlternateLightHouseSpriteFrames)));
-
-    logic_map.insert("SpawnObstacles".to_string(), Rc::new(RefCell::new(SpawnObstacles)));
-
-    logic_map.insert("JumpingObstacles".to_string(), Rc::new(RefCell::new(JumpingObstacles)));
-    logic_map.insert("CollisionDetection".to_string(), Rc::new(RefCell::new(CollisionDetection)));
-    logic_map.insert("ApplyGravity".to_string(), Rc::new(RefCell::new(ApplyGravity)));
-    logic_map.insert("VerticalBounds".to_string(), Rc::new(RefCell::new(VerticalBounds)));
-    logic_map.insert("HorizontalBounds".to_string(), Rc::new(RefCell::new(HorizontalBounds)));
-    logic_map.insert("CheckGameOver".to_string(), Rc::new(RefCell::new(CheckGameOver)));
-    logic_map.insert("ModifyPosition".to_string(), Rc::new(RefCell::new(ModifyPosition)));
-
-    logic_map
+/// Registers every core logic system and resolves the order in which they run each frame.
+///
+/// # Parameters
+/// - `input_logic_map`: The commands executed for pressed keys during the input stage.
+///
+/// # Returns
+/// The scheduler, or an error if the declared dependencies cannot be satisfied.
+pub fn initialize_scheduler(input_logic_map: InputLogicMap) -> Result<Scheduler, ScheduleError> {
+    let mut systems = Scheduler::builder();
+
+    systems.add("ProcessInput", Stage::Input, ProcessInput { commands: input_logic_map });
+
+    systems.add("ApplyGravity", Stage::Physics, ApplyGravity);
+    systems.add("ModifyPosition", Stage::Physics, ModifyPosition).after("ApplyGravity");
+    systems.add("MoveEnemies", Stage::Physics, MoveEnemies);
+
+    systems.add("JumpingObstacles", Stage::Collision, JumpingObstacles);
+    systems.add("CollisionDetection", Stage::Collision, CollisionDetection).after("JumpingObstacles");
+    systems.add("VerticalBounds", Stage::Collision, VerticalBounds).after("JumpingObstacles");
+    systems.add("HorizontalBounds", Stage::Collision, HorizontalBounds).after("CollisionDetection");
+    systems.add("EnemyContact", Stage::Collision, EnemyContact).after("HorizontalBounds");
+    systems.add("HazardContact", Stage::Collision, HazardContact).after("HorizontalBounds");
+
+    systems.add("AnimateHearts", Stage::Animation, AnimateHearts);
+    systems.add("AnimateHazards", Stage::Animation, AnimateHazards);
+    systems.add("AnimatePlayer", Stage::Animation, AnimatePlayer);
+
+    // Damage is resolved before leaving the map, and a newly entered map is populated right away
+    systems.add("CheckGameOver", Stage::Rules, CheckGameOver).before("MapTransition");
+    systems.add("MapTransition", Stage::Rules, MapTransition);
+    systems.add("AdvanceTimer", Stage::Rules, AdvanceTimer).after("CheckGameOver").after("MapTransition");
+    systems.add("SpawnObstacles", Stage::Rules, SpawnObstacles).after("MapTransition");
+    systems.add("SpawnEnemies", Stage::Rules, SpawnEnemies).after("MapTransition");
+    systems.add("SpawnHazards", Stage::Rules, SpawnHazards).after("MapTransition");
+    systems.add("FollowCamera", Stage::Rules, FollowCamera).after("MapTransition");
+
+    systems.build()
 }
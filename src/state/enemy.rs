@@ -0,0 +1,269 @@
+use crate::audio::mixer::Mixer;
+use crate::graphics::animation::Animator;
+use crate::state::collision::{move_body, player_hitbox, solid_in_front, Aabb};
+use crate::state::constants::animation::ENEMY_WALK;
+use crate::state::constants::audio::EXPLOSION_SOUND;
+use crate::state::constants::camera::{DEFEAT_SHAKE_MAGNITUDE, DEFEAT_SHAKE_TICKS};
+use crate::state::constants::enemy::{CHASE_RANGE, CHASE_SPEED, ENEMY_HEALTH, ENEMY_JUMP_VELOCITY, KNOCKBACK_FRICTION, OBSTACLE_LOOKAHEAD, PATROL_RANGE, PATROL_SPEED, STUN_FRAMES};
+use crate::state::constants::hud::DEFEAT_SCORE;
+use crate::state::constants::physics::{GRAVITY, GROUND, PLAYER_FEET, PLAYER_HITBOX_HEIGHT, PLAYER_HITBOX_WIDTH, PLAYER_HITBOX_X, UPPER_BOUND_MARGIN};
+use crate::state::core_logic::{damage_player, CoreLogic};
+use crate::state::structs::Direction::{Left, Right};
+use crate::state::structs::{Direction, EnemyId, GameState, Map};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EnemyBehaviour {
+    Patrol, // Walks back and forth around its spawn point
+    Chase,  // Walks towards the player
+}
+
+#[derive(Clone, Copy)]
+pub struct Enemy {
+    pub id: EnemyId,
+    pub x: f32,
+    pub y: f32,
+    pub previous_x: f32, // Position at the start of the current tick, used to interpolate drawing
+    pub previous_y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    pub knockback: f32, // Sideways speed of the knockback from a kick, negative to the left, lost over the following ticks
+    pub direction: Direction,
+    pub health: u8,
+    pub behaviour: EnemyBehaviour,
+    pub home_x: f32, // Centre of the patrol route
+    pub on_ground: bool,
+    pub animator: Animator,
+    pub stun_frames: usize, // Remaining frames in which the enemy stands still after a hit
+}
+
+impl Enemy {
+    pub fn new(id: EnemyId, x: f32, y: f32) -> Self {
+        Enemy {
+            id,
+            x,
+            y,
+            previous_x: x,
+            previous_y: y,
+            vx: 0.0,
+            vy: 0.0,
+            knockback: 0.0,
+            direction: Left,
+            health: ENEMY_HEALTH,
+            behaviour: EnemyBehaviour::Patrol,
+            home_x: x,
+            on_ground: false,
+            animator: Animator::new(ENEMY_WALK),
+            stun_frames: 0,
+        }
+    }
+
+    /// Returns the solid part of the enemy, which is drawn with the player sprite and so shares
+    /// the hitbox the player has without a `hitbox` slice.
+    pub fn hitbox(&self) -> Aabb {
+        let feet = self.y + PLAYER_FEET;
+        Aabb::new(self.x + PLAYER_HITBOX_X, feet - PLAYER_HITBOX_HEIGHT, PLAYER_HITBOX_WIDTH, PLAYER_HITBOX_HEIGHT)
+    }
+}
+
+pub struct SpawnEnemies;
+
+impl CoreLogic for SpawnEnemies {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        // Populate the current map with the enemies placed in its level file
+        if !game_state.enemies_spawned {
+            let map = &mut game_state.all_maps[game_state.current_map_index];
+            map.enemies = map.enemy_layout.clone();
+            game_state.enemies_spawned = true;
+
+            // Vary the way each enemy sets off on its patrol
+            for enemy in map.enemies.iter_mut() {
+                enemy.direction = if game_state.rng.coin_flip() { Left } else { Right };
+            }
+        }
+    }
+}
+
+pub struct MoveEnemies;
+
+impl CoreLogic for MoveEnemies {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        let player_x = game_state.player.x;
+        let map = &mut game_state.all_maps[game_state.current_map_index];
+
+        for index in 0..map.enemies.len() {
+            let mut enemy = map.enemies[index];
+            Self::choose_behaviour(&mut enemy, player_x);
+            Self::steer(&mut enemy, player_x);
+
+            // Patrolling enemies turn around at boxes, chasing enemies jump over them
+            if solid_in_front(map, enemy.hitbox(), enemy.direction, OBSTACLE_LOOKAHEAD).is_some() {
+                if enemy.behaviour == EnemyBehaviour::Patrol {
+                    enemy.direction = if enemy.direction == Right { Left } else { Right };
+                    enemy.vx = 0.0;
+                } else if enemy.on_ground {
+                    enemy.vy = ENEMY_JUMP_VELOCITY;
+                    enemy.on_ground = false;
+                } else if enemy.vy > 0.0 {
+                    enemy.vx = 0.0;
+                }
+            }
+
+            move_enemy(map, &mut enemy);
+            Self::advance_walking_animation(&mut enemy);
+            map.enemies[index] = enemy;
+        }
+    }
+}
+
+impl MoveEnemies {
+    fn choose_behaviour(enemy: &mut Enemy, player_x: f32) {
+        enemy.behaviour = if (player_x - enemy.x).abs() <= CHASE_RANGE {
+            EnemyBehaviour::Chase
+        } else {
+            EnemyBehaviour::Patrol
+        };
+    }
+
+    fn steer(enemy: &mut Enemy, player_x: f32) {
+        if enemy.stun_frames > 0 {
+            enemy.stun_frames -= 1;
+            enemy.vx = 0.0;
+            return;
+        }
+
+        match enemy.behaviour {
+            EnemyBehaviour::Patrol => {
+                // Turn around at either end of the patrol route
+                if enemy.x <= enemy.home_x - PATROL_RANGE {
+                    enemy.direction = Right;
+                } else if enemy.x >= enemy.home_x + PATROL_RANGE {
+                    enemy.direction = Left;
+                }
+                enemy.vx = if enemy.direction == Right { PATROL_SPEED } else { -PATROL_SPEED };
+            }
+            EnemyBehaviour::Chase => {
+                enemy.direction = if player_x >= enemy.x { Right } else { Left };
+                enemy.vx = if enemy.direction == Right { CHASE_SPEED } else { -CHASE_SPEED };
+            }
+        }
+    }
+
+    fn advance_walking_animation(enemy: &mut Enemy) {
+        if enemy.vx == 0.0 {
+            return;
+        }
+
+        enemy.animator.update();
+    }
+}
+
+/// Moves an enemy of `map` by its velocity and knockback under gravity, through the same sweep
+/// against the boxes as the player, landing it on the box or the ground below.
+fn move_enemy(map: &Map, enemy: &mut Enemy) {
+    enemy.vy += GRAVITY;
+    let upper_bound = map.width as f32 - UPPER_BOUND_MARGIN;
+    let dx = (enemy.vx + enemy.knockback).clamp(-enemy.x, (upper_bound - enemy.x).max(0.0));
+    let movement = move_body(map, enemy.hitbox(), dx, enemy.vy, GROUND - enemy.y);
+
+    enemy.x += movement.dx;
+    enemy.y += movement.dy;
+    enemy.on_ground = movement.on_obstacle || movement.on_ground;
+    if enemy.on_ground || movement.hit_ceiling {
+        enemy.vy = 0.0;
+    }
+    enemy.knockback = if movement.hit_wall { 0.0 } else { (enemy.knockback.abs() - KNOCKBACK_FRICTION).max(0.0).copysign(enemy.knockback) };
+}
+
+/// Takes one point of health from the enemy at `index` and stuns it, removing it with a bang once it has none left.
+///
+/// # Returns
+/// Whether the enemy was defeated.
+pub fn damage_enemy(game_state: &mut GameState, mixer: &mut Mixer, index: usize) -> bool {
+    let enemies = &mut game_state.all_maps[game_state.current_map_index].enemies;
+    let enemy = &mut enemies[index];
+    enemy.health = enemy.health.saturating_sub(1);
+    enemy.stun_frames = STUN_FRAMES;
+
+    if enemy.health > 0 {
+        return false;
+    }
+
+    println!("Enemy {:?} defeated", enemy.id);
+    enemies.remove(index);
+    game_state.score += DEFEAT_SCORE;
+    mixer.play(EXPLOSION_SOUND, 1500);
+    game_state.camera.shake(DEFEAT_SHAKE_MAGNITUDE, DEFEAT_SHAKE_TICKS);
+    true
+}
+
+pub struct EnemyContact;
+
+impl CoreLogic for EnemyContact {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        if game_state.player.invincible || game_state.damage_taken || game_state.player.game_over {
+            return;
+        }
+
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        let enemies = &mut game_state.all_maps[game_state.current_map_index].enemies;
+
+        if let Some(enemy) = enemies.iter_mut().find(|enemy| body.overlaps(&enemy.hitbox())) {
+            // Let the enemy catch its breath so it does not hit again right after the knockback
+            enemy.stun_frames = STUN_FRAMES;
+
+            let from_x = enemy.hitbox().centre_x();
+            damage_player(game_state, from_x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::collision::player_hitbox;
+    use crate::state::constants::enemy::STUN_FRAMES;
+    use crate::state::constants::physics::{BOX_GROUND, PLAYER_FEET};
+    use crate::state::core_logic::CoreLogic;
+    use crate::state::game::testing::{game_state, mixer, place_boxes};
+    use crate::state::structs::EnemyId;
+
+    use super::{Enemy, EnemyContact, MoveEnemies};
+
+    #[test]
+    fn only_an_enemy_overlapping_the_player_hurts_them() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        let health = game_state.player.health;
+
+        // Right next to the player, with the hitboxes only touching
+        let beside = Enemy::new(EnemyId(0), game_state.player.x + body.right - body.left, game_state.player.y);
+        assert!(!body.overlaps(&beside.hitbox()));
+        game_state.all_maps[game_state.current_map_index].enemies.push(beside);
+        EnemyContact.execute(&mut game_state, &mut mixer);
+        assert_eq!(game_state.player.health, health);
+
+        game_state.all_maps[game_state.current_map_index].enemies[0].x -= 4.0;
+        EnemyContact.execute(&mut game_state, &mut mixer);
+        assert_eq!(game_state.player.health, health - 1);
+    }
+
+    #[test]
+    fn a_falling_enemy_lands_on_a_box() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let mut enemy = Enemy::new(EnemyId(0), 400.0, BOX_GROUND - PLAYER_FEET - 40.0);
+        // Stunned, so it falls straight down
+        enemy.stun_frames = STUN_FRAMES;
+        place_boxes(&mut game_state, &[(enemy.hitbox().left, BOX_GROUND)]);
+        game_state.all_maps[game_state.current_map_index].enemies.push(enemy);
+
+        for _ in 0..30 {
+            MoveEnemies.execute(&mut game_state, &mut mixer);
+        }
+
+        let enemy = &game_state.all_maps[game_state.current_map_index].enemies[0];
+        assert!(enemy.on_ground);
+        assert_eq!(enemy.vy, 0.0);
+        assert_eq!(enemy.y, BOX_GROUND - PLAYER_FEET);
+    }
+}
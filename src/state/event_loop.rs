@@ -1,40 +1,112 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
 use std::thread;
 use std::time::Instant;
 
-use minifb::Key;
-
+use crate::audio::mixer::Mixer;
+use crate::graphics::display::handle_display_keys;
 use crate::graphics::render_graphics::render_pixel_buffer;
 use crate::graphics::update_graphics::update_pixel_buffer;
-use crate::input::handler::{handle_user_input, InputLogicMap};
-use crate::state::constants::graphics::FRAME_DURATION;
-use crate::state::core_logic::{execute_core_logic, CoreLogic};
+use crate::input::action::Action;
+use crate::input::handler::handle_rebinding;
+use crate::state::constants::graphics::{FRAME_DURATION, MAX_TICKS_PER_FRAME};
+use crate::state::scene::{handle_scene_keys, update_scene, Scene};
+use crate::state::scheduler::Scheduler;
 use crate::state::structs::GameState;
+use crate::state::timestep::FixedTimestep;
 
-pub fn start_event_loop(mut game_state: GameState, input_logic_map: InputLogicMap, core_logic_map: HashMap<String, Rc<RefCell<dyn CoreLogic>>>, sink: &mut rodio::Sink) {
+pub fn start_event_loop(game_state: &mut GameState, scheduler: &mut Scheduler, mixer: &mut Mixer, tick_rate: u32) {
+    let realtime = game_state.display.is_realtime();
+    let mut timestep = FixedTimestep::new(tick_rate, MAX_TICKS_PER_FRAME);
+    let mut last_frame = Instant::now();
 
-    // Main event loop: runs as long as the window is open and the Escape key is not pressed
-    while game_state.window.is_open() && !game_state.window.is_key_down(Key::Escape) {
+    // Main event loop: runs as long as the window is open, the game has not been quit from a menu and a replay has input left
+    while game_state.display.is_open() && !game_state.quit && !game_state.input_mode.is_finished(game_state.tick) {
         let start = Instant::now();
 
-        // Handle basic user input, which influence the player's state such as velocity, direction, etc.
-        handle_user_input(&mut game_state, &input_logic_map, sink);
+        // The simulation stands still while the controls are being rebound
+        handle_display_keys(game_state);
+        handle_rebinding(game_state);
+        if game_state.rebinding.is_some() {
+            last_frame = start;
+        } else if realtime {
+            handle_scene_keys(game_state, scheduler);
+
+            // Simulate as many fixed ticks as real time has passed, independent of how long drawing takes
+            let ticks = timestep.advance(start - last_frame);
+            simulate_frame(game_state, scheduler, mixer, ticks);
+            last_frame = start;
 
-        // Process game logic such as obstacle detection, physics, sounds etc.
-        execute_core_logic(&mut game_state, &core_logic_map, sink);
+            // Nothing moves outside the game, so the last tick is drawn as it is
+            game_state.render_alpha = if game_state.scene == Scene::Playing { timestep.alpha() } else { 1.0 };
+        } else {
+            // Without a clock to follow every frame is exactly one tick
+            handle_scene_keys(game_state, scheduler);
+            simulate_frame(game_state, scheduler, mixer, 1);
+            game_state.render_alpha = 1.0;
+        }
 
         // Update the pixel buffer with the current game state
-        update_pixel_buffer(&mut game_state);
+        update_pixel_buffer(game_state);
 
         // Render the updated buffer
-        render_pixel_buffer(&mut game_state);
+        render_pixel_buffer(game_state);
 
-        // Maintain a frame rate of 60 fps
+        // Present at most 60 fps
         let elapsed = start.elapsed();
-        if elapsed < FRAME_DURATION {
+        if realtime && elapsed < FRAME_DURATION {
             thread::sleep(FRAME_DURATION - elapsed);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Runs the `ticks` simulation ticks of a frame on the actions held when the frame started.
+///
+/// The keyboard is read once, as it only changes between presented frames. Actions which happen
+/// once per press, such as a kick, only reach the first tick, so catching up after a slow frame
+/// does not repeat them.
+pub fn simulate_frame(game_state: &mut GameState, scheduler: &Scheduler, mixer: &mut Mixer, ticks: u32) {
+    let actions = game_state.bindings.action_mask(&*game_state.display);
+
+    for index in 0..ticks {
+        game_state.frame_actions = if index == 0 { actions } else { Action::held_only(actions) };
+        update_scene(game_state, scheduler, mixer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minifb::Key;
+
+    use crate::graphics::backend::{HeadlessBackend, KeyEvent};
+    use crate::input::action::Action;
+    use crate::input::handler::initialize_input_logic_map;
+    use crate::input::recording::{InputMode, Recording};
+    use crate::state::collision::player_hitbox;
+    use crate::state::constants::audio::KICK_BOX_SOUND;
+    use crate::state::constants::physics::BOX_GROUND;
+    use crate::state::core_logic::initialize_scheduler;
+    use crate::state::game::testing::{game_state, mixer, place_boxes};
+
+    use super::simulate_frame;
+
+    #[test]
+    fn catching_up_repeats_held_actions_but_not_presses() {
+        let mut game_state = game_state();
+        let (mut mixer, played) = mixer();
+        let scheduler = initialize_scheduler(initialize_input_logic_map()).unwrap();
+        let script = [Key::X, Key::D].map(|key| KeyEvent { frame: 0, key, pressed: true }).to_vec();
+        game_state.display = Box::leak(Box::new(HeadlessBackend::new(script, u64::MAX)));
+        game_state.input_mode = InputMode::Record(Recording::new(0, 0));
+        let front = player_hitbox(&game_state.player, &game_state.sprites).right;
+        let id = place_boxes(&mut game_state, &[(front + 1.0, BOX_GROUND)])[0];
+
+        // A slow frame is caught up on with five ticks
+        simulate_frame(&mut game_state, &scheduler, &mut mixer, 5);
+
+        let durability = game_state.all_maps[game_state.current_map_index].obstacles[id].durability;
+        assert_eq!(durability, 1);
+        assert_eq!(played.borrow().iter().filter(|&&sound| sound == KICK_BOX_SOUND).count(), 1);
+        let InputMode::Record(recording) = &game_state.input_mode else { unreachable!() };
+        let (kick, right) = (Action::Kick.bit(), Action::MoveRight.bit());
+        assert_eq!(recording.inputs, vec![kick | right, right, right, right, right]);
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+
+use crate::graphics::animation::Animator;
+use crate::graphics::atlas::SpriteAtlas;
+use crate::graphics::backend::DisplayBackend;
+use crate::graphics::camera::Camera;
+use crate::graphics::display::DisplaySettings;
+use crate::graphics::font::BitmapFont;
+use crate::graphics::render_graphics::Scaler;
+use crate::input::action::Bindings;
+use crate::input::recording::InputMode;
+use crate::state::constants::animation::HEART;
+use crate::state::constants::graphics::{WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH};
+use crate::state::constants::input::CONTROLS_FILE;
+use crate::state::constants::level::{PLAYER_START_X, PLAYER_START_Y};
+use crate::state::player::Player;
+use crate::state::rng::Rng;
+use crate::state::scene::Scene;
+use crate::state::structs::{GameState, Map};
+
+/// Creates the state of a new session on the first map, with the player at the start.
+///
+/// # Parameters
+/// - `display`: The window or headless display the frames are presented on, also the source of key input.
+/// - `window_buffer`: The native resolution buffer the game draws into.
+/// - `scaled_buffer`: The buffer the window buffer is scaled into, resized to the display.
+///
+/// # Returns
+/// The game state, playing the first map.
+pub fn new_game_state<'a>(
+    display: &'a mut dyn DisplayBackend,
+    window_buffer: &'a mut Vec<u32>,
+    scaled_buffer: &'a mut Vec<u32>,
+    display_settings: DisplaySettings,
+    all_maps: Vec<Map>,
+    sprites: SpriteAtlas,
+    font: BitmapFont,
+) -> GameState<'a> {
+    // Displays without a size of their own are shown the window buffer as it is
+    let (window_width, window_height) = display.size().unwrap_or((WINDOW_BUFFER_WIDTH, WINDOW_BUFFER_HEIGHT));
+    let scaler = Scaler::new(WINDOW_BUFFER_WIDTH, WINDOW_BUFFER_HEIGHT, window_width, window_height, display_settings.scale_mode, display_settings.crt);
+    scaled_buffer.resize(window_width * window_height, 0);
+
+    GameState {
+        player: Player::new(PLAYER_START_X, PLAYER_START_Y),
+        sprites,
+        camera: Camera::new(WINDOW_BUFFER_WIDTH, WINDOW_BUFFER_HEIGHT),
+        window_buffer,
+        window_width,
+        window_height,
+        display,
+        scaled_buffer,
+        scaler,
+        display_settings,
+        all_maps,
+        current_map_index: 0,
+        footstep_index: 0,
+        footstep_active: false,
+        heart_animator: Animator::new(HEART),
+        font,
+        score: 0,
+        play_ticks: 0,
+        show_debug: false,
+        obstacle_spawned: false,
+        enemies_spawned: false,
+        hazards_spawned: false,
+        designated_x: 0.0,
+        damage_taken: false,
+        transition_frame: None,
+        tick: 0,
+        render_alpha: 1.0,
+        rng: Rng::new(0),
+        input_mode: InputMode::Live,
+        bindings: load_bindings(),
+        held_actions: 0,
+        frame_actions: 0,
+        rebinding: None,
+        scene: Scene::Playing,
+        quit: false,
+    }
+}
+
+/// Loads the key bindings from the controls file, using the defaults if it is missing or invalid.
+pub fn load_bindings() -> Bindings {
+    let path = Path::new(CONTROLS_FILE);
+    if !path.exists() {
+        return Bindings::default();
+    }
+
+    Bindings::load(path).unwrap_or_else(|e| {
+        println!("Using default controls: {}", e);
+        Bindings::default()
+    })
+}
+
+/// Game states for unit tests, set up without a window or audio device.
+#[cfg(test)]
+pub mod testing {
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use crate::audio::backend::NullBackend;
+    use crate::audio::engine::load_sounds;
+    use crate::audio::mixer::Mixer;
+    use crate::graphics::atlas::SpriteAtlas;
+    use crate::graphics::backend::HeadlessBackend;
+    use crate::graphics::display::DisplaySettings;
+    use crate::graphics::font::BitmapFont;
+    use crate::input::action::Bindings;
+    use crate::level::grid::{Tile, TileType};
+    use crate::level::loader::{extract_obstacles, load_levels};
+    use crate::state::arena::ObstacleArena;
+    use crate::state::constants::animation::SPRITE_MANIFEST;
+    use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH, WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH};
+    use crate::state::constants::hud::FONT_FILE;
+    use crate::state::constants::level::LEVELS_DIRECTORY;
+    use crate::state::constants::physics::GROUND;
+    use crate::state::structs::{GameState, ObstacleId};
+
+    use super::new_game_state;
+
+    /// A game state on an empty first map: nothing is spawned, so each test places the boxes and
+    /// enemies it needs. The player stands on the ground and the keys are the default bindings.
+    /// The display and buffers are leaked, which is fine for a test.
+    pub fn game_state() -> GameState<'static> {
+        let display = Box::leak(Box::new(HeadlessBackend::new(Vec::new(), u64::MAX)));
+        let window_buffer = Box::leak(Box::new(vec![0; WINDOW_BUFFER_WIDTH * WINDOW_BUFFER_HEIGHT]));
+        let scaled_buffer = Box::leak(Box::new(Vec::new()));
+
+        let all_maps = load_levels(Path::new(LEVELS_DIRECTORY)).unwrap();
+        let sprites = SpriteAtlas::load(Path::new(SPRITE_MANIFEST)).unwrap();
+        let font = BitmapFont::load(Path::new(FONT_FILE)).unwrap();
+
+        let mut game_state = new_game_state(display, window_buffer, scaled_buffer, DisplaySettings::default(), all_maps, sprites, font);
+        game_state.bindings = Bindings::default();
+        game_state.obstacle_spawned = true;
+        game_state.enemies_spawned = true;
+        game_state.hazards_spawned = true;
+        game_state.player.y = GROUND;
+        game_state
+    }
+
+    /// A silent mixer, together with the list of the sounds played through it.
+    pub fn mixer() -> (Mixer, Rc<RefCell<Vec<usize>>>) {
+        let backend = NullBackend::default();
+        let played = backend.played();
+        (Mixer::new(Box::new(backend), &load_sounds()), played)
+    }
+
+    /// Replaces the boxes of the current map with boxes whose top left corners are at `boxes`
+    /// (`x_left`, `y_bottom`), linked as the loader links them.
+    ///
+    /// # Returns
+    /// The ids of the boxes, in the order given.
+    pub fn place_boxes(game_state: &mut GameState, boxes: &[(f32, f32)]) -> Vec<ObstacleId> {
+        let tiles: Vec<Tile> = boxes.iter().map(|&(x, y)| Tile {
+            tile_type: TileType::Obstacle,
+            x_left: x,
+            x_right: x + TILE_WIDTH as f32,
+            y_bottom: y,
+            y_top: y - TILE_HEIGHT as f32,
+        }).collect();
+        let obstacles: ObstacleArena = extract_obstacles(&tiles);
+        let ids = obstacles.ids();
+
+        game_state.all_maps[game_state.current_map_index].set_obstacles(obstacles);
+        ids
+    }
+}
@@ -1,49 +1,114 @@
-use rodio::Sink;
-use crate::audio::engine::append_source_source;
-use crate::state::core_logic::CoreLogic;
-use crate::state::constants::audio::{DOWN_SOUND, FALL_MILD_SOUND};
-use crate::state::constants::physics::{GRAVITY, GROUND};
-use crate::state::player::PlayerState;
-use crate::state::structs::GameState;
+use crate::audio::mixer::Mixer;
+use crate::state::collision::{obstacle_hitbox, obstacle_hitboxes, player_hitbox, slide_obstacle, sweep, touching, Aabb, Normal};
+use crate::state::core_logic::{damage_player, CoreLogic};
+use crate::state::constants::audio::{FALL_HEAVY_SOUND, FALL_MILD_SOUND};
+use crate::state::constants::physics::{BOX_GRAVITY, BOX_GROUND, HEAVY_FALL_HEIGHT, MAX_BOX_FALL_SPEED, PLAYER_GRAVITY, SLIDE_FRICTION};
+use crate::state::enemy::damage_enemy;
+use crate::state::structs::{GameState, Map, Obstacle, ObstacleId};
 
 pub struct ApplyGravity;
 
 impl CoreLogic for ApplyGravity {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer) {
         Self::handle_player_gravity(game_state);
-        Self::handle_falling_obstacles(game_state, sink);
+        Self::handle_sliding_obstacles(game_state, mixer);
+        Self::handle_falling_obstacles(game_state, mixer);
     }
 }
 
 impl ApplyGravity {
-    fn handle_falling_obstacles(game_state: &mut GameState, sink: &mut Sink) {
-        let mut obstacle_landed = false;
-
-        // Apply gravity to all obstacles which has the falling boolean
-        for obstacle in game_state.all_maps[game_state.current_map_index].obstacles.iter_mut() {
-            if obstacle.active && obstacle.falling {
-                if obstacle.velocity_y >= 16.0 {
-                    obstacle_landed = true;
-                    obstacle.falling = false;
-                } else {
-                    obstacle.y_bottom += GRAVITY * 3.0;
-                    obstacle.y_top += GRAVITY * 3.0;
-                    obstacle.velocity_y += GRAVITY * 3.0;
-                }
+    /// Moves the boxes launched by a strong kick, which slow down while resting on something, hit
+    /// the enemies they slide into and stop against walls. A box sliding off a ledge falls through
+    /// `handle_falling_obstacles` and keeps its speed.
+    fn handle_sliding_obstacles(game_state: &mut GameState, mixer: &mut Mixer) {
+        let map = &game_state.all_maps[game_state.current_map_index];
+        let sliding: Vec<ObstacleId> = map.obstacles.iter().filter(|obstacle| obstacle.active && obstacle.velocity_x != 0.0).map(|obstacle| obstacle.id).collect();
+
+        for id in sliding {
+            let map = &mut game_state.all_maps[game_state.current_map_index];
+            let obstacle = map.obstacles[id];
+            let body = obstacle_hitbox(&obstacle);
+            let moved = slide_obstacle(map, id, obstacle.velocity_x);
+            strike_enemies(game_state, mixer, body, body.translated(moved, 0.0));
+
+            let obstacle = &mut game_state.all_maps[game_state.current_map_index].obstacles[id];
+            if moved != obstacle.velocity_x {
+                obstacle.velocity_x = 0.0;
+                mixer.play(FALL_MILD_SOUND, 2500);
+                println!("Box {} stopped against a wall at x: {}", id, obstacle.x_left);
+            } else if !obstacle.falling {
+                obstacle.velocity_x = (obstacle.velocity_x.abs() - SLIDE_FRICTION).max(0.0).copysign(obstacle.velocity_x);
             }
         }
+    }
+
+    fn handle_falling_obstacles(game_state: &mut GameState, mixer: &mut Mixer) {
+        let map = &mut game_state.all_maps[game_state.current_map_index];
 
-        if obstacle_landed {
-            append_source_source(&game_state, sink, DOWN_SOUND, 3000);
-            game_state.all_maps[game_state.current_map_index].obstacles.sort_by(|a, b| a.y_bottom.partial_cmp(&b.y_bottom).unwrap());
+        // Boxes left without support, e.g. when the box below was destroyed, start to fall
+        for id in map.obstacles.ids() {
+            let obstacle = &map.obstacles[id];
+            let resting_on_box = obstacle.under_obstacle.is_some_and(|under| !map.obstacles[under].falling);
+            if obstacle.active && !obstacle.falling && !resting_on_box && !is_supported(map, id) {
+                start_falling(map, id);
+            }
+        }
+
+        // Move the lowest boxes first, so a falling stack keeps together and lands from the bottom up
+        let mut falling: Vec<ObstacleId> = map.obstacles.iter().filter(|obstacle| obstacle.active && obstacle.falling).map(|obstacle| obstacle.id).collect();
+        falling.sort_by(|a, b| map.obstacles[*b].y_bottom.total_cmp(&map.obstacles[*a].y_bottom));
+
+        let mut heaviest_landing = None;
+        for id in falling {
+            if let Some(height) = Self::fall(game_state, mixer, id) {
+                heaviest_landing = Some(heaviest_landing.map_or(height, |heaviest: f32| heaviest.max(height)));
+            }
+        }
+
+        // A falling stack lands all at once, so only the longest fall is heard
+        match heaviest_landing {
+            Some(height) if height >= HEAVY_FALL_HEIGHT => mixer.play(FALL_HEAVY_SOUND, 3000),
+            Some(_) => mixer.play(FALL_MILD_SOUND, 2500),
+            None => {}
         }
     }
 
-    fn handle_player_gravity(game_state: &mut GameState) {
-        // Apply gravity to the player
-        if !game_state.player.on_ground && !game_state.player.on_obstacle {
-            game_state.player.vy += GRAVITY;
+    /// Moves a falling box down until it lands on the ground or on a box which is not falling,
+    /// hurting the player and enemies it drops onto.
+    ///
+    /// # Returns
+    /// The height the box fell from if it landed.
+    fn fall(game_state: &mut GameState, mixer: &mut Mixer, id: ObstacleId) -> Option<f32> {
+        let map = &mut game_state.all_maps[game_state.current_map_index];
+        let obstacle = map.obstacles[id];
+        let velocity = (obstacle.velocity_y + BOX_GRAVITY).min(MAX_BOX_FALL_SPEED);
+
+        let body = obstacle_hitbox(&obstacle);
+        let solids: Vec<(ObstacleId, Aabb)> = obstacle_hitboxes(map, body.union(&body.translated(0.0, velocity)))
+            .into_iter()
+            .filter(|&(other, _)| other != id)
+            .collect();
+        let sweep = sweep(body, 0.0, velocity, &solids);
+        let dy = sweep.dy.min((BOX_GROUND - obstacle.y_bottom).max(0.0));
+        let landed_on = sweep.contacts.iter().find(|contact| contact.normal == Normal::Top).map(|contact| map.obstacles[contact.solid]);
+
+        map.move_obstacle(id, 0.0, dy);
+        crush(game_state, mixer, body, body.translated(0.0, dy));
+
+        let obstacle = &mut game_state.all_maps[game_state.current_map_index].obstacles[id];
+        match landed_on {
+            // Boxes falling together keep the pace of the box below
+            Some(below) if below.falling => obstacle.velocity_y = below.velocity_y,
+            Some(_) => return Some(land(obstacle)),
+            None if obstacle.y_bottom >= BOX_GROUND => return Some(land(obstacle)),
+            None => obstacle.velocity_y = velocity,
         }
+        None
+    }
+
+    fn handle_player_gravity(game_state: &mut GameState) {
+        // Apply gravity to the player, also while standing, so `move_player` finds what holds them up
+        game_state.player.vy += PLAYER_GRAVITY;
     }
 }
 
@@ -51,90 +116,215 @@ impl ApplyGravity {
 pub struct JumpingObstacles;
 
 impl CoreLogic for JumpingObstacles {
-    fn execute(&self, game_state: &mut GameState, sink: &mut Sink) {
-        jump_obstacles(game_state, sink);
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        jump_obstacles(game_state);
+    }
+}
+
+/// Marks whether the player is jumping over a box, which hides their shadow on the ground. Landing
+/// on the box is resolved by `move_player`.
+pub fn jump_obstacles(game_state: &mut GameState) {
+    let player = &game_state.player;
+    let body = player_hitbox(player, &game_state.sprites);
+    // The boxes below the player
+    let below = Aabb { left: body.left, top: body.bottom, right: body.right, bottom: f32::INFINITY };
+    let above_obstacle = !player.on_ground && !player.on_obstacle
+        && obstacle_hitboxes(&game_state.all_maps[game_state.current_map_index], below).iter().any(|(_, solid)| solid.top >= body.bottom && body.left < solid.right && body.right > solid.left);
+
+    game_state.player.above_obstacle = above_obstacle;
+}
+
+/// Whether the box with `id` stands on the ground or on a box which is not falling.
+fn is_supported(map: &Map, id: ObstacleId) -> bool {
+    let obstacle = &map.obstacles[id];
+    if obstacle.y_bottom >= BOX_GROUND {
+        return true;
+    }
+
+    let body = obstacle_hitbox(obstacle);
+    let below: Vec<(ObstacleId, Aabb)> = obstacle_hitboxes(map, body.translated(0.0, 1.0))
+        .into_iter()
+        .filter(|&(other, _)| other != id && !map.obstacles[other].falling)
+        .collect();
+    touching(body, 0.0, 1.0, &below).is_some()
+}
+
+/// Starts the box with `id` falling, together with the boxes stacked on it which nothing else holds up.
+fn start_falling(map: &mut Map, id: ObstacleId) {
+    let mut next = Some(id);
+    while let Some(id) = next {
+        let obstacle = &mut map.obstacles[id];
+        obstacle.falling = true;
+        obstacle.velocity_y = 0.0;
+        obstacle.fall_start_y = obstacle.y_bottom;
+
+        next = obstacle.over_obstacle.filter(|&over| !map.obstacles[over].falling && !is_supported(map, over));
     }
 }
 
-pub fn jump_obstacles(mut game_state: &mut GameState, sink: &mut rodio::Sink) {
+/// Stops a falling box and returns the height it fell from.
+fn land(obstacle: &mut Obstacle) -> f32 {
+    obstacle.falling = false;
+    obstacle.velocity_y = 0.0;
+    obstacle.y_bottom - obstacle.fall_start_y
+}
 
-    // Apply vertical velocity if jumping
-    if game_state.player.is_jumping {
-        game_state.player.y += game_state.player.vy;
+/// Hurts the player and every enemy which a box falling from `from` to `to` has just dropped onto.
+fn crush(game_state: &mut GameState, mixer: &mut Mixer, from: Aabb, to: Aabb) {
+    let player = &game_state.player;
+    let body = player_hitbox(player, &game_state.sprites);
+    if !player.invincible && !game_state.damage_taken && !player.game_over && to.overlaps(&body) && !from.overlaps(&body) {
+        println!("Player crushed by a box");
+        damage_player(game_state, to.centre_x());
     }
 
-    // Check if game_state.player is almost on the ground
-    if game_state.player.y >= 140.0 && game_state.player.y <= 160.0 {
-        game_state.player.almost_ground = true;
-    } else {
-        game_state.player.almost_ground = false;
+    strike_enemies(game_state, mixer, from, to);
+}
+
+/// Hurts every enemy which a box moving from `from` to `to` has just run into.
+fn strike_enemies(game_state: &mut GameState, mixer: &mut Mixer, from: Aabb, to: Aabb) {
+    // Go backwards, so defeated enemies can be removed without skipping the next one
+    for index in (0..game_state.all_maps[game_state.current_map_index].enemies.len()).rev() {
+        let hitbox = game_state.all_maps[game_state.current_map_index].enemies[index].hitbox();
+        if to.overlaps(&hitbox) && !from.overlaps(&hitbox) {
+            damage_enemy(game_state, mixer, index);
+        }
     }
+}
 
-    let mut on_any_obstacle = false;
+#[cfg(test)]
+mod tests {
+    use crate::state::collision::player_hitbox;
+    use crate::state::constants::audio::{EXPLOSION_SOUND, FALL_HEAVY_SOUND, FALL_MILD_SOUND};
+    use crate::state::constants::graphics::TILE_HEIGHT;
+    use crate::state::constants::enemy::{ENEMY_HEALTH, STUN_FRAMES};
+    use crate::state::constants::graphics::TILE_WIDTH;
+    use crate::state::constants::physics::{BOX_GROUND, HEAVY_FALL_HEIGHT, SLIDE_FRICTION};
+    use crate::state::core_logic::CoreLogic;
+    use crate::state::enemy::Enemy;
+    use crate::state::game::testing::{game_state, mixer, place_boxes};
+    use crate::state::structs::{EnemyId, GameState, ObstacleId};
+    use crate::audio::mixer::Mixer;
 
-    // Check for each obstacle
-    for obstacle in game_state.all_maps[game_state.current_map_index].obstacles.iter() {
+    use super::ApplyGravity;
 
-        if obstacle.active == false {
-            continue;
+    fn tick(game_state: &mut GameState, mixer: &mut Mixer, ticks: usize) {
+        for _ in 0..ticks {
+            ApplyGravity.execute(game_state, mixer);
         }
+    }
 
-        if game_state.player.x + 10.0 > obstacle.x_left && game_state.player.x + 5.0 < obstacle.x_right {
-            if game_state.player.y <= obstacle.y_bottom && game_state.player.y >= obstacle.y_top && obstacle.is_top_obstacle {
-                // println!("game_state.player.y: {}, obstacle.y_bottom: {}, obstacle.y_top: {}", game_state.player.y, obstacle.y_bottom, obstacle.y_top);
-                if game_state.player.state != PlayerState::OnObstacle {
-                    // player just landed on the obstacle
-                    game_state.player.y = obstacle.y_bottom - 10.0;
-                    game_state.player.on_obstacle = true;
-                    game_state.player.on_ground = false;
-                    game_state.player.is_jumping = false;
-                    game_state.player.state = PlayerState::OnObstacle;
-                    game_state.player.vy = 0.0;
-                    // println!("Player is on an obstacle");
-                } else {
-                    // game_state.player is already on the obstacle
-                    game_state.player.on_obstacle = true;
-                    game_state.player.on_ground = false;
-                }
-                on_any_obstacle = true;
-                break;
-            } else if game_state.player.y < obstacle.y_top {
-                // player is above the obstacle but not touching it
-                game_state.player.on_ground = false;
-                game_state.player.on_obstacle = false;
-                game_state.player.above_obstacle = true;
-                game_state.player.state = PlayerState::InAir;
-                game_state.player.is_jumping = true;
-                on_any_obstacle = true;
-                break;
-            }
+    fn launch(game_state: &mut GameState, id: ObstacleId, velocity_x: f32) {
+        game_state.all_maps[game_state.current_map_index].obstacles[id].velocity_x = velocity_x;
+    }
+
+    #[test]
+    fn a_launched_box_slows_down_on_the_ground() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let id = place_boxes(&mut game_state, &[(400.0, BOX_GROUND)])[0];
+        launch(&mut game_state, id, -3.0);
+
+        tick(&mut game_state, &mut mixer, 2);
+
+        let obstacle = &game_state.all_maps[game_state.current_map_index].obstacles[id];
+        assert!((obstacle.x_left - (400.0 - 3.0 - (3.0 - SLIDE_FRICTION))).abs() < 1e-4);
+        assert!((obstacle.velocity_x - (-3.0 + 2.0 * SLIDE_FRICTION)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn a_launched_box_stops_at_a_wall_and_at_the_edge_of_the_map() {
+        let mut game_state = game_state();
+        let (mut mixer, played) = mixer();
+        let width = game_state.all_maps[game_state.current_map_index].width as f32;
+        let tile = TILE_WIDTH as f32;
+        let ids = place_boxes(&mut game_state, &[(400.0, BOX_GROUND), (400.0 + tile + 5.0, BOX_GROUND), (width - tile - 2.0, BOX_GROUND)]);
+        launch(&mut game_state, ids[0], 3.0);
+        launch(&mut game_state, ids[2], 3.0);
+
+        tick(&mut game_state, &mut mixer, 30);
+
+        let obstacles = &game_state.all_maps[game_state.current_map_index].obstacles;
+        assert_eq!((obstacles[ids[0]].x_right, obstacles[ids[0]].velocity_x), (obstacles[ids[1]].x_left, 0.0));
+        assert_eq!((obstacles[ids[1]].x_left, obstacles[ids[1]].velocity_x), (400.0 + tile + 5.0, 0.0));
+        assert_eq!((obstacles[ids[2]].x_right, obstacles[ids[2]].velocity_x), (width, 0.0));
+        assert_eq!(*played.borrow(), vec![FALL_MILD_SOUND, FALL_MILD_SOUND]);
+    }
+
+    #[test]
+    fn a_launched_box_hurts_the_enemy_it_slides_into() {
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+        let enemy = Enemy::new(EnemyId(0), 440.0, game_state.player.y);
+        let id = place_boxes(&mut game_state, &[(enemy.hitbox().left - 20.0, BOX_GROUND)])[0];
+        game_state.all_maps[game_state.current_map_index].enemies.push(enemy);
+        launch(&mut game_state, id, 3.0);
+
+        tick(&mut game_state, &mut mixer, 30);
+
+        // Hit once on the way in, not again while the box passes through
+        let enemy = &game_state.all_maps[game_state.current_map_index].enemies[0];
+        assert_eq!(enemy.health, ENEMY_HEALTH - 1);
+        assert_eq!(enemy.stun_frames, STUN_FRAMES);
+    }
+
+    #[test]
+    fn a_falling_box_crushes_the_player_and_the_enemies_below() {
+        let mut game_state = game_state();
+        let (mut mixer, played) = mixer();
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        let health = game_state.player.health;
+        let weak = Enemy { health: 1, ..Enemy::new(EnemyId(0), 300.0, game_state.player.y) };
+        let strong = Enemy::new(EnemyId(1), 400.0, game_state.player.y);
+        let drop = BOX_GROUND - 4.0 * TILE_HEIGHT as f32;
+        place_boxes(&mut game_state, &[(body.left, drop), (weak.hitbox().left, drop), (strong.hitbox().left, drop)]);
+        game_state.all_maps[game_state.current_map_index].enemies.extend([weak, strong]);
+
+        tick(&mut game_state, &mut mixer, 30);
+
+        // The weak enemy is defeated, the other one only hurt
+        let enemies = &game_state.all_maps[game_state.current_map_index].enemies;
+        assert_eq!(game_state.player.health, health - 1);
+        assert_eq!(enemies.len(), 1);
+        assert_eq!((enemies[0].id, enemies[0].health), (strong.id, strong.health - 1));
+        assert_eq!(*played.borrow(), vec![EXPLOSION_SOUND, FALL_HEAVY_SOUND]);
+    }
+
+    #[test]
+    fn the_landing_sound_depends_on_the_fall_height() {
+        for (height, sound) in [(HEAVY_FALL_HEIGHT - TILE_HEIGHT as f32, FALL_MILD_SOUND), (HEAVY_FALL_HEIGHT, FALL_HEAVY_SOUND)] {
+            let mut game_state = game_state();
+            let (mut mixer, played) = mixer();
+            let id = place_boxes(&mut game_state, &[(400.0, BOX_GROUND - height)])[0];
+
+            tick(&mut game_state, &mut mixer, 30);
+
+            let obstacle = &game_state.all_maps[game_state.current_map_index].obstacles[id];
+            assert_eq!(obstacle.y_bottom, BOX_GROUND);
+            assert!(!obstacle.falling);
+            assert_eq!(*played.borrow(), vec![sound], "fall of {} pixels", height);
         }
     }
 
-    if !on_any_obstacle {
-        if game_state.player.y >= GROUND {
-            // player is on the ground (not on an obstacle)
-            game_state.player.y = GROUND;
-            game_state.player.vy = 0.0;
-            game_state.player.on_ground = true;
-            game_state.player.on_obstacle = false;
-            game_state.player.is_jumping = false;
+    #[test]
+    fn a_column_falls_together_when_its_bottom_box_is_removed() {
+        let mut game_state = game_state();
+        let (mut mixer, played) = mixer();
+        let tile = TILE_HEIGHT as f32;
+        let ids = place_boxes(&mut game_state, &[(400.0, BOX_GROUND), (400.0, BOX_GROUND - tile), (400.0, BOX_GROUND - 2.0 * tile), (400.0, BOX_GROUND - 3.0 * tile)]);
 
-            if game_state.player.state == PlayerState::InAir {
-                append_source_source(&game_state, sink, FALL_MILD_SOUND, 2500);
-            }
+        game_state.all_maps[game_state.current_map_index].remove_obstacle(ids[0]);
+        tick(&mut game_state, &mut mixer, 30);
 
-            game_state.player.state = PlayerState::OnGround;
-
-            // println!("Player is on the ground");
-        } else {
-            // player is in the air (not above any obstacle)
-            game_state.player.on_ground = false;
-            game_state.player.on_obstacle = false;
-            game_state.player.above_obstacle = false;
-            game_state.player.state = PlayerState::InAir;
-            game_state.player.is_jumping = true;
-            // println!("Player is in the air");
+        // The column lands as one, a tile lower and still stacked
+        let obstacles = &game_state.all_maps[game_state.current_map_index].obstacles;
+        for (level, &id) in ids[1..].iter().enumerate() {
+            assert_eq!(obstacles[id].y_bottom, BOX_GROUND - level as f32 * tile);
+            assert!(!obstacles[id].falling);
         }
+        assert_eq!(obstacles[ids[1]].under_obstacle, None);
+        assert_eq!(obstacles[ids[2]].under_obstacle, Some(ids[1]));
+        assert_eq!(obstacles[ids[3]].under_obstacle, Some(ids[2]));
+        assert_eq!(*played.borrow(), vec![FALL_MILD_SOUND]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,90 @@
+use crate::audio::mixer::Mixer;
+use crate::graphics::animation::Animator;
+use crate::state::collision::{player_hitbox, Aabb};
+use crate::state::constants::animation::TOXIC_TRAP;
+use crate::state::constants::audio::DOWN_SOUND;
+use crate::state::constants::hazard::HAZARD_MARGIN;
+use crate::state::constants::physics::GROUND;
+use crate::state::core_logic::{damage_player, CoreLogic};
+use crate::state::structs::GameState;
+
+/// A toxic trap placed on the ground which hurts the player until a box is put on top of it.
+#[derive(Clone, Copy)]
+pub struct Hazard {
+    pub x_left: f32,
+    pub x_right: f32,
+    pub y_bottom: f32,
+    pub active: bool, // If false, the trap has been covered and is harmless
+    pub animator: Animator,
+}
+
+impl Hazard {
+    pub fn new(x_left: f32, x_right: f32, y_bottom: f32) -> Self {
+        Hazard { x_left, x_right, y_bottom, active: true, animator: Animator::new(TOXIC_TRAP) }
+    }
+
+    /// Returns true if the horizontal span `x_left..x_right` overlaps the trap.
+    pub fn overlaps(&self, x_left: f32, x_right: f32) -> bool {
+        x_left < self.x_right - HAZARD_MARGIN && x_right > self.x_left + HAZARD_MARGIN
+    }
+}
+
+pub struct SpawnHazards;
+
+impl CoreLogic for SpawnHazards {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        // Populate the current map with the traps placed in its level file
+        if !game_state.hazards_spawned {
+            let map = &mut game_state.all_maps[game_state.current_map_index];
+            map.hazards = map.hazard_layout.clone();
+            game_state.hazards_spawned = true;
+        }
+    }
+}
+
+pub struct AnimateHazards;
+
+impl CoreLogic for AnimateHazards {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        for hazard in game_state.all_maps[game_state.current_map_index].hazards.iter_mut() {
+            hazard.animator.update();
+        }
+    }
+}
+
+pub struct HazardContact;
+
+impl CoreLogic for HazardContact {
+    fn execute(&self, game_state: &mut GameState, mixer: &mut Mixer) {
+        let map = &mut game_state.all_maps[game_state.current_map_index];
+
+        // A box resting on a trap seals it for good
+        for hazard in map.hazards.iter_mut().filter(|hazard| hazard.active) {
+            let below = Aabb { left: hazard.x_left, top: hazard.y_bottom, right: hazard.x_right, bottom: f32::INFINITY };
+            let covered = map.obstacle_grid.query(below).into_iter().map(|id| &map.obstacles[id]).any(|obstacle| {
+                obstacle.active && !obstacle.falling && obstacle.velocity_x == 0.0 && hazard.overlaps(obstacle.x_left, obstacle.x_right)
+                    && obstacle.y_bottom >= hazard.y_bottom
+            });
+
+            if covered {
+                hazard.active = false;
+                mixer.play(DOWN_SOUND, 1000);
+                println!("Toxic trap at x: {} covered", hazard.x_left);
+            }
+        }
+
+        if game_state.player.invincible || game_state.damage_taken || game_state.player.game_over {
+            return;
+        }
+
+        // Only hurt the player when standing in the trap, not when jumping over it
+        let body = player_hitbox(&game_state.player, &game_state.sprites);
+        let standing = game_state.player.y >= GROUND;
+        let trap = map.hazards.iter().find(|hazard| standing && hazard.active && hazard.overlaps(body.left, body.right));
+
+        if let Some(trap) = trap {
+            let from_x = (trap.x_left + trap.x_right) / 2.0;
+            damage_player(game_state, from_x);
+        }
+    }
+}
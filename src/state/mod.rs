@@ -1,11 +1,19 @@
-use rodio::Source;
-
 pub mod event_loop;
 pub mod player;
+pub mod scheduler;
 pub mod core_logic;
-mod gravity;
+pub mod enemy;
+pub mod hazard;
+pub mod gravity;
 pub mod collision;
+pub mod spatial;
+pub mod arena;
 pub mod constants;
 pub mod structs;
+pub mod game;
+pub mod transition;
+pub mod scene;
+pub mod timestep;
+pub mod rng;
 
 
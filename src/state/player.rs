@@ -1,4 +1,6 @@
-use minifb::Key;
+use crate::graphics::animation::Animator;
+use crate::input::action::Action;
+use crate::state::constants::animation::WALK;
 
 use crate::state::player::PlayerState::OnGround;
 use crate::state::structs::Direction::Right;
@@ -18,21 +20,19 @@ pub enum PlayerState {
     pub struct Player {
         pub x: f32,
         pub y: f32,
+        pub previous_x: f32, // Position at the start of the current tick, used to interpolate drawing
+        pub previous_y: f32,
         pub vx: f32,
         pub vy: f32,
+        pub knockback: f32, // Sideways speed of the knockback after taking damage, negative to the left
         pub on_ground: bool,
         pub on_obstacle: bool,
-        pub last_key: Option<Key>,
-        pub left_increment: usize,
-        pub right_increment: usize,
+        pub last_action: Option<Action>,
         pub direction: Direction,
-        pub right_increment_frame_count: usize,
-        pub left_increment_frame_count: usize,
         pub kick_start_time: u32,
         pub is_kicking: bool,
-        pub kick_frame: usize,
-        pub kick_frame_timer: usize,
-        pub almost_ground: bool,
+        pub animator: Animator,
+        pub almost_ground: bool, // Slow near the top of a jump, drawn with the jump start frame and a medium shadow
         pub is_jumping: bool,
         pub state: PlayerState,
         pub above_obstacle: bool,
@@ -47,19 +47,17 @@ impl Player {
         Player {
             x,
             y,
+            previous_x: x,
+            previous_y: y,
             vx: 0.0,
             vy: 0.0,
+            knockback: 0.0,
             on_ground: false,
-            last_key: None,
+            last_action: None,
             on_obstacle: false,
-            left_increment: 4,
-            right_increment: 0,
             direction: Right,
-            right_increment_frame_count: 0,
-            left_increment_frame_count: 0,
             is_kicking: false,
-            kick_frame: 0,
-            kick_frame_timer: 0,
+            animator: Animator::new(WALK),
             kick_start_time: 0,
             almost_ground: false,
             is_jumping: false,
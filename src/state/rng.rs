@@ -0,0 +1,27 @@
+/// Small seeded pseudo random number generator (xorshift64*).
+///
+/// All randomness in the simulation goes through the generator in `GameState`, so a run can be
+/// reproduced from its seed and inputs.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The all zero state would only ever produce zeroes
+        Rng { state: if seed == 0 { 0x9E3779B97F4A7C15 } else { seed } }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 32) as u32
+    }
+
+    /// Returns true with a chance of one in two.
+    pub fn coin_flip(&mut self) -> bool {
+        self.next_u32() & 1 == 1
+    }
+}
@@ -0,0 +1,200 @@
+use minifb::Key;
+
+use crate::audio::mixer::Mixer;
+use crate::input::action::Action;
+use crate::input::recording::InputMode;
+use crate::state::constants::animation::GAME_OVER;
+use crate::state::constants::level::{PLAYER_START_X, PLAYER_START_Y};
+use crate::state::constants::menu::{BACK_KEY, CONFIRM_KEYS, DOWN_KEYS, PAUSED_SYSTEMS, STAGE_CLEAR_MENU_DELAY, UP_KEYS};
+use crate::state::player::Player;
+use crate::state::scheduler::Scheduler;
+use crate::state::structs::GameState;
+use crate::state::timestep::step;
+use crate::state::transition::enter_map;
+
+/// The screen the game is on. Each scene decides which keys it answers to, what runs every tick
+/// and what is drawn over the world (see `update_pixel_buffer`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scene {
+    Title { selected: usize }, // Menu shown over the first map when the game starts
+    Playing, // The simulation runs
+    Paused { selected: usize }, // The simulation stands still behind the pause menu
+    GameOver { ticks: u64, selected: usize }, // The game over animation plays, then its menu is shown
+    StageClear { ticks: u64, selected: usize }, // The final map has been completed
+}
+
+impl Scene {
+    /// Every item the menu of the scene can hold, before leaving out those unavailable in the current input mode.
+    fn items(self) -> &'static [MenuItem] {
+        match self {
+            Scene::Title { .. } => &[MenuItem::NewGame, MenuItem::Quit],
+            Scene::Playing => &[],
+            Scene::Paused { .. } => &[MenuItem::Resume, MenuItem::Title, MenuItem::Quit],
+            Scene::GameOver { .. } => &[MenuItem::TryAgain, MenuItem::Title, MenuItem::Quit],
+            Scene::StageClear { .. } => &[MenuItem::NewGame, MenuItem::Title, MenuItem::Quit],
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        match self {
+            Scene::Title { selected } | Scene::Paused { selected } | Scene::GameOver { selected, .. } | Scene::StageClear { selected, .. } => *selected = index,
+            Scene::Playing => {}
+        }
+    }
+}
+
+/// An entry of a menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuItem {
+    NewGame, // Starts over from the first map
+    Resume, // Returns to the game
+    TryAgain, // Restarts the current map with full health
+    Title, // Returns to the title screen
+    Quit, // Closes the game
+}
+
+impl MenuItem {
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuItem::NewGame => "NEW GAME",
+            MenuItem::Resume => "RESUME",
+            MenuItem::TryAgain => "TRY AGAIN",
+            MenuItem::Title => "QUIT TO TITLE",
+            MenuItem::Quit => "QUIT GAME",
+        }
+    }
+
+    /// A recording covers a single run from the map it started on, so it cannot start a new game.
+    fn is_available(self, input_mode: &InputMode) -> bool {
+        matches!(input_mode, InputMode::Live) || !matches!(self, MenuItem::NewGame | MenuItem::Title)
+    }
+}
+
+/// Returns the items of the menu currently shown and the index of the selected one, or None if
+/// the scene shows no menu (yet).
+pub fn menu(game_state: &GameState) -> Option<(Vec<MenuItem>, usize)> {
+    let selected = match game_state.scene {
+        Scene::Playing => return None,
+        Scene::GameOver { ticks, .. } if ticks < game_state.sprites.clip(GAME_OVER).length() => return None,
+        Scene::StageClear { ticks, .. } if ticks < STAGE_CLEAR_MENU_DELAY => return None,
+        Scene::Title { selected } | Scene::Paused { selected } | Scene::GameOver { selected, .. } | Scene::StageClear { selected, .. } => selected,
+    };
+
+    let items: Vec<MenuItem> = game_state.scene.items().iter().copied().filter(|item| item.is_available(&game_state.input_mode)).collect();
+    let selected = selected.min(items.len() - 1);
+    Some((items, selected))
+}
+
+/// Handles the keys pressed since the last frame: Escape and the pause keys open and close the
+/// pause menu, and the arrow keys and Enter pick from the menu of the scene. Called once per presented frame.
+///
+/// Opening the pause menu disables every system but those in `PAUSED_SYSTEMS`, and leaving it
+/// enables them again.
+pub fn handle_scene_keys(game_state: &mut GameState, scheduler: &mut Scheduler) {
+    let was_paused = matches!(game_state.scene, Scene::Paused { .. });
+    answer_scene_keys(game_state);
+
+    let paused = matches!(game_state.scene, Scene::Paused { .. });
+    if paused != was_paused {
+        for name in scheduler.order() {
+            if !PAUSED_SYSTEMS.contains(&name) {
+                scheduler.set_enabled(name, !paused);
+            }
+        }
+    }
+}
+
+fn answer_scene_keys(game_state: &mut GameState) {
+    let keys = game_state.display.keys_pressed();
+    let pressed = |list: &[Key]| keys.iter().any(|key| list.contains(key));
+    let back = keys.contains(&BACK_KEY);
+    let pause = back || pressed(game_state.bindings.keys(Action::Pause));
+    let confirm = pressed(&CONFIRM_KEYS);
+
+    // Enter both pauses and confirms, so in the pause menu it picks the selected item
+    match game_state.scene {
+        Scene::Playing if pause => {
+            game_state.scene = Scene::Paused { selected: 0 };
+            println!("Paused");
+            return;
+        }
+        Scene::Paused { .. } if pause && !confirm => {
+            choose(game_state, MenuItem::Resume);
+            return;
+        }
+        Scene::Title { .. } if back => {
+            choose(game_state, MenuItem::Quit);
+            return;
+        }
+        _ => {}
+    }
+
+    let Some((items, mut selected)) = menu(game_state) else {
+        return;
+    };
+    if pressed(&UP_KEYS) {
+        selected = (selected + items.len() - 1) % items.len();
+    }
+    if pressed(&DOWN_KEYS) {
+        selected = (selected + 1) % items.len();
+    }
+    game_state.scene.select(selected);
+
+    if confirm {
+        choose(game_state, items[selected]);
+    }
+}
+
+/// Runs one tick of the current scene. Only `Playing` runs the simulation, the pause menu runs
+/// the systems left enabled behind it and the other scenes count ticks for their own animations.
+pub fn update_scene(game_state: &mut GameState, scheduler: &Scheduler, mixer: &mut Mixer) {
+    match game_state.scene {
+        Scene::Playing => step(game_state, scheduler, mixer),
+        Scene::Paused { .. } => scheduler.run(game_state, mixer),
+        Scene::GameOver { ticks, selected } => game_state.scene = Scene::GameOver { ticks: ticks + 1, selected },
+        Scene::StageClear { ticks, selected } => game_state.scene = Scene::StageClear { ticks: ticks + 1, selected },
+        Scene::Title { .. } => {}
+    }
+
+    // A replay cannot answer the game over menu, and a recording which carried on must have tried again
+    if matches!(game_state.scene, Scene::GameOver { .. }) && matches!(game_state.input_mode, InputMode::Replay(_)) && menu(game_state).is_some() {
+        choose(game_state, MenuItem::TryAgain);
+    }
+}
+
+fn choose(game_state: &mut GameState, item: MenuItem) {
+    match item {
+        MenuItem::NewGame => {
+            start_new_game(game_state);
+            game_state.scene = Scene::Playing;
+        }
+        MenuItem::Resume => {
+            game_state.scene = Scene::Playing;
+            println!("Resumed");
+        }
+        MenuItem::TryAgain => {
+            restart_map(game_state, game_state.current_map_index);
+            game_state.scene = Scene::Playing;
+        }
+        MenuItem::Title => {
+            // The title screen shows the first map, ready to be played
+            start_new_game(game_state);
+            game_state.scene = Scene::Title { selected: 0 };
+        }
+        MenuItem::Quit => game_state.quit = true,
+    }
+}
+
+/// Resets the score and timer and restarts from the first map.
+fn start_new_game(game_state: &mut GameState) {
+    game_state.score = 0;
+    game_state.play_ticks = 0;
+    restart_map(game_state, 0);
+}
+
+/// Restarts the map at `index` with a fresh player.
+fn restart_map(game_state: &mut GameState, index: usize) {
+    game_state.player = Player::new(PLAYER_START_X, PLAYER_START_Y);
+    game_state.transition_frame = None;
+    enter_map(game_state, index);
+}
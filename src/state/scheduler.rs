@@ -0,0 +1,264 @@
+use std::fmt;
+
+use crate::audio::mixer::Mixer;
+use crate::state::core_logic::CoreLogic;
+use crate::state::structs::GameState;
+
+/// Stages of a frame, executed in declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Input,
+    Physics,
+    Collision,
+    Animation,
+    Rules,
+}
+
+const STAGES: [Stage; 5] = [Stage::Input, Stage::Physics, Stage::Collision, Stage::Animation, Stage::Rules];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    DuplicateSystem(&'static str),
+    UnknownSystem { system: &'static str, dependency: &'static str },
+    StageConflict { system: &'static str, dependency: &'static str }, // A dependency would require running a later stage first
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleError::DuplicateSystem(name) => write!(f, "system '{}' is registered twice", name),
+            ScheduleError::UnknownSystem { system, dependency } => write!(f, "system '{}' depends on unknown system '{}'", system, dependency),
+            ScheduleError::StageConflict { system, dependency } => write!(f, "system '{}' cannot be ordered against '{}' as that contradicts their stages", system, dependency),
+            ScheduleError::Cycle(names) => write!(f, "systems form a dependency cycle: {}", names.join(" -> ")),
+        }
+    }
+}
+
+/// A registered system together with its scheduling constraints.
+pub struct System {
+    name: &'static str,
+    stage: Stage,
+    logic: Box<dyn CoreLogic>,
+    enabled: bool,
+    before: Vec<&'static str>, // Systems which must run after this one
+    after: Vec<&'static str>,  // Systems which must run before this one
+}
+
+impl System {
+    /// Declares that this system runs before `name`.
+    pub fn before(&mut self, name: &'static str) -> &mut Self {
+        self.before.push(name);
+        self
+    }
+
+    /// Declares that this system runs after `name`.
+    pub fn after(&mut self, name: &'static str) -> &mut Self {
+        self.after.push(name);
+        self
+    }
+}
+
+/// Collects systems and their dependencies before the execution order is resolved.
+#[derive(Default)]
+pub struct SchedulerBuilder {
+    systems: Vec<System>,
+}
+
+impl SchedulerBuilder {
+    /// Registers a system in a stage. Systems without dependencies run in registration order.
+    pub fn add(&mut self, name: &'static str, stage: Stage, logic: impl CoreLogic + 'static) -> &mut System {
+        self.systems.push(System { name, stage, logic: Box::new(logic), enabled: true, before: Vec::new(), after: Vec::new() });
+        self.systems.last_mut().unwrap()
+    }
+
+    /// Resolves the execution order, rejecting unknown dependencies, dependencies which contradict
+    /// the stage order and dependency cycles.
+    pub fn build(self) -> Result<Scheduler, ScheduleError> {
+        let index_of = |name: &str| self.systems.iter().position(|system| system.name == name);
+
+        for (index, system) in self.systems.iter().enumerate() {
+            if index_of(system.name) != Some(index) {
+                return Err(ScheduleError::DuplicateSystem(system.name));
+            }
+        }
+
+        // Collect every dependency as an edge from the system which runs first to the one which runs second
+        let mut edges = Vec::new();
+        for (index, system) in self.systems.iter().enumerate() {
+            let dependencies = system.before.iter().map(|name| (name, true)).chain(system.after.iter().map(|name| (name, false)));
+
+            for (&dependency, runs_first) in dependencies {
+                let other = index_of(dependency).ok_or(ScheduleError::UnknownSystem { system: system.name, dependency })?;
+                let (first, second) = if runs_first { (index, other) } else { (other, index) };
+
+                if self.systems[first].stage > self.systems[second].stage {
+                    return Err(ScheduleError::StageConflict { system: system.name, dependency });
+                }
+                if self.systems[first].stage == self.systems[second].stage {
+                    edges.push((first, second));
+                }
+            }
+        }
+
+        // Topologically sort each stage, picking the earliest registered system whenever there is a choice
+        let mut order = Vec::with_capacity(self.systems.len());
+        for stage in STAGES {
+            let members: Vec<usize> = (0..self.systems.len()).filter(|&index| self.systems[index].stage == stage).collect();
+            let mut remaining = members.clone();
+
+            while !remaining.is_empty() {
+                let ready = remaining.iter().position(|&candidate| {
+                    !edges.iter().any(|&(first, second)| second == candidate && remaining.contains(&first))
+                });
+
+                match ready {
+                    Some(position) => order.push(remaining.remove(position)),
+                    None => return Err(ScheduleError::Cycle(remaining.iter().map(|&index| self.systems[index].name).collect())),
+                }
+            }
+        }
+
+        let mut slots: Vec<Option<System>> = self.systems.into_iter().map(Some).collect();
+        let systems = order.into_iter().map(|index| slots[index].take().unwrap()).collect();
+
+        Ok(Scheduler { systems })
+    }
+}
+
+/// Runs the core logic systems of a frame in a fixed, dependency-respecting order.
+pub struct Scheduler {
+    systems: Vec<System>,
+}
+
+impl Scheduler {
+    pub fn builder() -> SchedulerBuilder {
+        SchedulerBuilder::default()
+    }
+
+    pub fn run(&self, game_state: &mut GameState, mixer: &mut Mixer) {
+        for system in self.systems.iter().filter(|system| system.enabled) {
+            system.logic.execute(game_state, mixer);
+        }
+    }
+
+    /// Enables or disables a system at runtime. Returns false if no system has the given name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.systems.iter_mut().find(|system| system.name == name) {
+            Some(system) => {
+                system.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of all systems in execution order.
+    pub fn order(&self) -> Vec<&'static str> {
+        self.systems.iter().map(|system| system.name).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::state::game::testing::{game_state, mixer};
+
+    /// Notes its name in a shared log when run.
+    struct Log(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+    impl CoreLogic for Log {
+        fn execute(&self, _game_state: &mut GameState, _mixer: &mut Mixer) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    fn system(name: &'static str) -> Log {
+        Log(name, Rc::default())
+    }
+
+    #[test]
+    fn stages_run_in_order_and_systems_without_dependencies_in_registration_order() {
+        let mut systems = Scheduler::builder();
+        systems.add("Rules", Stage::Rules, system("Rules"));
+        systems.add("Second", Stage::Physics, system("Second"));
+        systems.add("Input", Stage::Input, system("Input"));
+        systems.add("Third", Stage::Physics, system("Third"));
+
+        assert_eq!(systems.build().unwrap().order(), ["Input", "Second", "Third", "Rules"]);
+    }
+
+    #[test]
+    fn dependencies_reorder_systems_within_a_stage() {
+        let mut systems = Scheduler::builder();
+        systems.add("A", Stage::Physics, system("A")).after("C");
+        systems.add("B", Stage::Physics, system("B"));
+        systems.add("C", Stage::Physics, system("C")).after("B");
+        systems.add("D", Stage::Physics, system("D")).before("B");
+
+        assert_eq!(systems.build().unwrap().order(), ["D", "B", "C", "A"]);
+    }
+
+    #[test]
+    fn dependencies_across_stages_in_their_order_are_allowed() {
+        let mut systems = Scheduler::builder();
+        systems.add("Late", Stage::Rules, system("Late")).after("Early");
+        systems.add("Early", Stage::Input, system("Early")).before("Late");
+
+        assert_eq!(systems.build().unwrap().order(), ["Early", "Late"]);
+    }
+
+    #[test]
+    fn dependencies_against_the_stage_order_are_rejected() {
+        let mut systems = Scheduler::builder();
+        systems.add("Input", Stage::Input, system("Input")).after("Rules");
+        systems.add("Rules", Stage::Rules, system("Rules"));
+
+        assert_eq!(systems.build().err(), Some(ScheduleError::StageConflict { system: "Input", dependency: "Rules" }));
+    }
+
+    #[test]
+    fn cycles_are_rejected_naming_the_systems_involved() {
+        let mut systems = Scheduler::builder();
+        systems.add("Free", Stage::Collision, system("Free"));
+        systems.add("A", Stage::Collision, system("A")).after("B");
+        systems.add("B", Stage::Collision, system("B")).after("C");
+        systems.add("C", Stage::Collision, system("C")).after("A");
+
+        assert_eq!(systems.build().err(), Some(ScheduleError::Cycle(vec!["A", "B", "C"])));
+    }
+
+    #[test]
+    fn unknown_and_duplicate_systems_are_rejected() {
+        let mut systems = Scheduler::builder();
+        systems.add("A", Stage::Input, system("A")).before("Missing");
+        assert_eq!(systems.build().err(), Some(ScheduleError::UnknownSystem { system: "A", dependency: "Missing" }));
+
+        let mut systems = Scheduler::builder();
+        systems.add("A", Stage::Input, system("A"));
+        systems.add("A", Stage::Rules, system("A"));
+        assert_eq!(systems.build().err(), Some(ScheduleError::DuplicateSystem("A")));
+    }
+
+    #[test]
+    fn disabled_systems_are_skipped_until_enabled_again() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut systems = Scheduler::builder();
+        systems.add("A", Stage::Input, Log("A", Rc::clone(&log)));
+        systems.add("B", Stage::Physics, Log("B", Rc::clone(&log)));
+        let mut scheduler = systems.build().unwrap();
+        let mut game_state = game_state();
+        let (mut mixer, _) = mixer();
+
+        assert!(scheduler.set_enabled("A", false));
+        assert!(!scheduler.set_enabled("Missing", false));
+        scheduler.run(&mut game_state, &mut mixer);
+        scheduler.set_enabled("A", true);
+        scheduler.run(&mut game_state, &mut mixer);
+
+        assert_eq!(*log.borrow(), ["B", "A", "B"]);
+    }
+}
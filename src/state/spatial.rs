@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::level::loader::{extract_obstacles, extract_obstacles_by_scan};
+use crate::level::grid::{Tile, TileType};
+use crate::state::collision::Aabb;
+use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH, WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH};
+use crate::state::arena::ObstacleArena;
+use crate::state::rng::Rng;
+use crate::state::structs::{Map, Obstacle, ObstacleId};
+
+/// A uniform grid of tile sized cells listing the obstacles within each, so the obstacles near a
+/// position are found without going through every obstacle of the map.
+///
+/// Queries return candidates, which the caller still checks against its own conditions.
+#[derive(Debug, Clone)]
+pub struct ObstacleGrid {
+    cells: HashMap<(i32, i32), Vec<ObstacleId>>, // Obstacles within each cell, keyed by column and row
+    min_cell: (i32, i32), // Smallest and largest cell ever filled, which bound queries reaching off the map
+    max_cell: (i32, i32),
+}
+
+impl ObstacleGrid {
+    /// Indexes every obstacle of `obstacles`.
+    pub fn build(obstacles: &ObstacleArena) -> Self {
+        let mut grid = ObstacleGrid { cells: HashMap::new(), min_cell: (i32::MAX, i32::MAX), max_cell: (i32::MIN, i32::MIN) };
+        for obstacle in obstacles.iter() {
+            grid.insert(obstacle.id, bounds(obstacle));
+        }
+        grid
+    }
+
+    /// Returns the ids of the obstacles listed in the cells `area` reaches, in ascending order
+    /// so that callers see them in the same order as going through `Map::obstacles` would.
+    pub fn query(&self, area: Aabb) -> Vec<ObstacleId> {
+        let (min, max) = cell_range(area);
+        let (min, max) = ((min.0.max(self.min_cell.0), min.1.max(self.min_cell.1)), (max.0.min(self.max_cell.0), max.1.min(self.max_cell.1)));
+
+        let mut found = Vec::new();
+        for column in min.0..=max.0 {
+            for row in min.1..=max.1 {
+                if let Some(cell) = self.cells.get(&(column, row)) {
+                    found.extend_from_slice(cell);
+                }
+            }
+        }
+
+        // Obstacles spanning several cells are listed in each of them
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    /// Lists the obstacle with `id` in every cell `area` reaches.
+    pub fn insert(&mut self, id: ObstacleId, area: Aabb) {
+        let (min, max) = cell_range(area);
+        self.min_cell = (self.min_cell.0.min(min.0), self.min_cell.1.min(min.1));
+        self.max_cell = (self.max_cell.0.max(max.0), self.max_cell.1.max(max.1));
+
+        for column in min.0..=max.0 {
+            for row in min.1..=max.1 {
+                self.cells.entry((column, row)).or_default().push(id);
+            }
+        }
+    }
+
+    /// Takes the obstacle with `id` out of the cells `area` reaches.
+    pub fn remove(&mut self, id: ObstacleId, area: Aabb) {
+        let (min, max) = cell_range(area);
+        for column in min.0..=max.0 {
+            for row in min.1..=max.1 {
+                if let Some(cell) = self.cells.get_mut(&(column, row)) {
+                    cell.retain(|&listed| listed != id);
+                    if cell.is_empty() {
+                        self.cells.remove(&(column, row));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves the obstacle with `id` from the cells `from` reaches to those `to` reaches,
+    /// leaving the grid alone while it stays within the same cells.
+    pub fn relocate(&mut self, id: ObstacleId, from: Aabb, to: Aabb) {
+        if cell_range(from) != cell_range(to) {
+            self.remove(id, from);
+            self.insert(id, to);
+        }
+    }
+}
+
+impl Default for ObstacleGrid {
+    fn default() -> Self {
+        ObstacleGrid::build(&ObstacleArena::default())
+    }
+}
+
+/// The area an obstacle is indexed by: the tile its coordinates describe together with the tile
+/// below it, where the box is drawn and solid (see `obstacle_hitboxes`).
+pub fn bounds(obstacle: &Obstacle) -> Aabb {
+    Aabb { left: obstacle.x_left, top: obstacle.y_top, right: obstacle.x_right, bottom: obstacle.y_bottom + TILE_HEIGHT as f32 }
+}
+
+/// The first and last cell `area` reaches. Edges on a cell border reach into both cells, so
+/// obstacles which only touch are still found. Areas reaching infinitely far end at the last cell there is.
+fn cell_range(area: Aabb) -> ((i32, i32), (i32, i32)) {
+    let column = |x: f32| (x / TILE_WIDTH as f32).floor() as i32;
+    let row = |y: f32| (y / TILE_HEIGHT as f32).floor() as i32;
+    ((column(area.left), row(area.top)), (column(area.right), row(area.bottom)))
+}
+
+/// Grows `area` by a pixel on every side, so neighbours just short of touching are found across a cell border.
+fn widened(area: Aabb) -> Aabb {
+    Aabb { left: area.left - 1.0, top: area.top - 1.0, right: area.right + 1.0, bottom: area.bottom + 1.0 }
+}
+
+/// Links every obstacle of `obstacles` to its neighbours, which are looked up in `grid`.
+pub fn link_all(obstacles: &mut ObstacleArena, grid: &ObstacleGrid) {
+    for id in obstacles.ids() {
+        link_neighbours(obstacles, id, grid.query(widened(bounds(&obstacles[id]))));
+    }
+}
+
+/// Determines the relationships and position of the obstacle with `id` from the obstacles in
+/// `candidates`, which must include all of its neighbours.
+pub fn link_neighbours(obstacles: &mut ObstacleArena, id: ObstacleId, candidates: impl IntoIterator<Item = ObstacleId>) {
+    let obstacle = obstacles[id];
+    let (mut left, mut right, mut over, mut under) = (None, None, None, None);
+
+    for other in candidates.into_iter().filter(|&other| other != id).map(|other| &obstacles[other]) {
+        // Only directly touching obstacles count as neighbours
+        let overlaps_x = other.x_left < obstacle.x_right && other.x_right > obstacle.x_left;
+        let overlaps_y = other.y_bottom > obstacle.y_top && other.y_top < obstacle.y_bottom;
+
+        if overlaps_x && touches(other.y_top, obstacle.y_bottom) {
+            under = Some(other.id);
+        }
+        if overlaps_x && touches(other.y_bottom, obstacle.y_top) {
+            over = Some(other.id);
+        }
+        if overlaps_y && touches(other.x_right, obstacle.x_left) {
+            left = Some(other.id);
+        }
+        if overlaps_y && touches(other.x_left, obstacle.x_right) {
+            right = Some(other.id);
+        }
+    }
+
+    let obstacle = &mut obstacles[id];
+    obstacle.left_obstacle = left;
+    obstacle.right_obstacle = right;
+    obstacle.over_obstacle = over;
+    obstacle.under_obstacle = under;
+    obstacle.is_bottom_obstacle = under.is_none();
+    obstacle.is_top_obstacle = over.is_none();
+    obstacle.is_leftmost_obstacle = left.is_none();
+    obstacle.is_rightmost_obstacle = right.is_none();
+}
+
+fn touches(a: f32, b: f32) -> bool {
+    (a - b).abs() < 0.5
+}
+
+impl Map {
+    /// Puts `obstacles` in play in place of the current ones.
+    pub fn set_obstacles(&mut self, obstacles: ObstacleArena) {
+        self.obstacle_grid = ObstacleGrid::build(&obstacles);
+        self.obstacles = obstacles;
+    }
+
+    /// Returns the ids of the obstacles which may lie within `area`, in ascending order.
+    pub fn obstacles_near(&self, area: Aabb) -> Vec<ObstacleId> {
+        self.obstacle_grid.query(area)
+    }
+
+    /// Moves the obstacle with `id` by (`dx`, `dy`), linking it and the obstacles around where it
+    /// was and where it ends up to their new neighbours.
+    pub fn move_obstacle(&mut self, id: ObstacleId, dx: f32, dy: f32) {
+        let from = bounds(&self.obstacles[id]);
+        let obstacle = &mut self.obstacles[id];
+        obstacle.x_left += dx;
+        obstacle.x_right += dx;
+        obstacle.y_top += dy;
+        obstacle.y_bottom += dy;
+
+        let to = bounds(obstacle);
+        self.obstacle_grid.relocate(id, from, to);
+        self.relink(from.union(&to));
+    }
+
+    /// Takes the obstacle with `id` out of play and unlinks it from its neighbours.
+    /// Returns None if it was already removed.
+    pub fn remove_obstacle(&mut self, id: ObstacleId) -> Option<Obstacle> {
+        let obstacle = self.obstacles.remove(id)?;
+        self.obstacle_grid.remove(id, bounds(&obstacle));
+        self.relink(bounds(&obstacle));
+        Some(obstacle)
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.set_obstacles(ObstacleArena::default());
+    }
+
+    /// Links the obstacles touching `area` to their neighbours again.
+    fn relink(&mut self, area: Aabb) {
+        for id in self.obstacle_grid.query(widened(area)) {
+            let candidates = self.obstacle_grid.query(widened(bounds(&self.obstacles[id])));
+            link_neighbours(&mut self.obstacles, id, candidates);
+        }
+    }
+}
+
+/// Times finding obstacles by scanning every one of them against looking them up in an `ObstacleGrid`,
+/// on a generated level of `boxes` boxes stacked in random columns, and checks that both find the same.
+///
+/// Prints the time to link the boxes to their neighbours when the level is loaded, and the average
+/// time of a frame's worth of lookups: the boxes on screen and the boxes around the player.
+pub fn benchmark_obstacles(boxes: usize, frames: u32) {
+    let tiles = generate_boxes(boxes);
+    let frames = frames.max(1);
+
+    println!("Finding obstacles among {} boxes:", boxes);
+
+    let start = Instant::now();
+    let scanned = extract_obstacles_by_scan(&tiles);
+    let scan_time = start.elapsed().as_secs_f64() * 1000.0;
+
+    let start = Instant::now();
+    let linked = extract_obstacles(&tiles);
+    let grid_time = start.elapsed().as_secs_f64() * 1000.0;
+
+    let links = |obstacle: &Obstacle| {
+        (obstacle.left_obstacle, obstacle.right_obstacle, obstacle.over_obstacle, obstacle.under_obstacle,
+            obstacle.is_bottom_obstacle, obstacle.is_top_obstacle, obstacle.is_leftmost_obstacle, obstacle.is_rightmost_obstacle)
+    };
+    let same_links = scanned.iter().map(links).eq(linked.iter().map(links));
+    println!("  linking, scan:   {:>10.3} ms", scan_time);
+    println!("  linking, grid:   {:>10.3} ms, {}", grid_time, if same_links { "matches the scan" } else { "DIFFERS from the scan" });
+
+    // Walk a view and a player sized area across the level, one tile per frame
+    let grid = ObstacleGrid::build(&linked);
+    let width = linked.iter().map(|obstacle| obstacle.x_right).fold(0.0, f32::max);
+    let areas: Vec<[Aabb; 2]> = (0..frames).map(|frame| {
+        let x = (frame as usize * TILE_WIDTH) as f32 % width.max(1.0);
+        let view = Aabb::new(x, 0.0, WINDOW_BUFFER_WIDTH as f32, WINDOW_BUFFER_HEIGHT as f32);
+        let player = Aabb::new(x + WINDOW_BUFFER_WIDTH as f32 / 2.0, 160.0, 24.0, 48.0);
+        [view, player]
+    }).collect();
+
+    let start = Instant::now();
+    let scan_found: Vec<Vec<ObstacleId>> = areas.iter().flatten().map(|&area| {
+        linked.iter().filter(|obstacle| touches_area(bounds(obstacle), area)).map(|obstacle| obstacle.id).collect()
+    }).collect();
+    let scan_time = start.elapsed().as_secs_f64() * 1000.0 / frames as f64;
+
+    let start = Instant::now();
+    let grid_found: Vec<Vec<ObstacleId>> = areas.iter().flatten().map(|&area| {
+        grid.query(area).into_iter().filter(|&id| touches_area(bounds(&linked[id]), area)).collect()
+    }).collect();
+    let grid_time = start.elapsed().as_secs_f64() * 1000.0 / frames as f64;
+
+    println!("  per frame, scan: {:>10.3} ms", scan_time);
+    println!("  per frame, grid: {:>10.3} ms, {}", grid_time, if scan_found == grid_found { "matches the scan" } else { "DIFFERS from the scan" });
+}
+
+/// Whether two rectangles overlap or touch.
+fn touches_area(a: Aabb, b: Aabb) -> bool {
+    a.left <= b.right && a.right >= b.left && a.top <= b.bottom && a.bottom >= b.top
+}
+
+/// Box tiles piled into columns of one to four boxes standing on the ground, with random gaps between the columns.
+fn generate_boxes(boxes: usize) -> Vec<Tile> {
+    let mut rng = Rng::new(boxes as u64);
+    let mut tiles = Vec::with_capacity(boxes);
+    let mut column = 0;
+
+    while tiles.len() < boxes {
+        let height = 1 + rng.next_u32() as usize % 4;
+        for level in 0..height.min(boxes - tiles.len()) {
+            let x_left = (column * TILE_WIDTH) as f32;
+            let y_bottom = 200.0 - (level * TILE_HEIGHT) as f32;
+            tiles.push(Tile { tile_type: TileType::Obstacle, x_left, x_right: x_left + TILE_WIDTH as f32, y_bottom, y_top: y_bottom - TILE_HEIGHT as f32 });
+        }
+        column += 1 + rng.next_u32() as usize % 3;
+    }
+
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::level::loader::{extract_obstacles, extract_obstacles_by_scan};
+    use crate::state::collision::Aabb;
+    use crate::state::constants::graphics::{TILE_HEIGHT, TILE_WIDTH};
+    use crate::state::constants::physics::BOX_GROUND;
+    use crate::state::game::testing::{game_state, place_boxes};
+    use crate::state::structs::Map;
+
+    use super::{bounds, generate_boxes, ObstacleGrid};
+
+    /// Checks that the grid of `map` lists every obstacle in the cells a freshly built grid would, and nothing else.
+    fn assert_indexed(map: &Map) {
+        let sorted = |grid: &ObstacleGrid| {
+            let mut cells: Vec<_> = grid.cells.iter().map(|(&cell, ids)| {
+                let mut ids = ids.clone();
+                ids.sort_unstable();
+                (cell, ids)
+            }).collect();
+            cells.sort_unstable();
+            cells
+        };
+        assert_eq!(sorted(&map.obstacle_grid), sorted(&ObstacleGrid::build(&map.obstacles)));
+    }
+
+    #[test]
+    fn queries_reach_across_cell_borders_and_stop_at_the_filled_cells() {
+        let mut game_state = game_state();
+        let (width, height) = (TILE_WIDTH as f32, TILE_HEIGHT as f32);
+        let ids = place_boxes(&mut game_state, &[(2.0 * width, BOX_GROUND), (6.0 * width, BOX_GROUND)]);
+        let map = &game_state.all_maps[game_state.current_map_index];
+        let grid = &map.obstacle_grid;
+        let first = bounds(&map.obstacles[ids[0]]);
+
+        // A point on the right edge of the first box lies in the next cell as well
+        assert_eq!(grid.query(Aabb::new(first.right, first.top, 0.0, 0.0)), vec![ids[0]]);
+        assert_eq!(grid.query(Aabb::new(first.right + width, first.top, 0.0, 0.0)), vec![]);
+        assert_eq!(grid.query(Aabb::new(first.left - 0.5, first.top, 0.0, 0.0)), vec![]);
+        assert_eq!(grid.query(Aabb::new(first.left - width, first.bottom, width, height)), vec![ids[0]]);
+
+        let everywhere = Aabb { left: f32::NEG_INFINITY, top: f32::NEG_INFINITY, right: f32::INFINITY, bottom: f32::INFINITY };
+        assert_eq!(grid.query(everywhere), ids);
+        let right_of_first = Aabb { left: first.right + width, top: first.top, right: f32::INFINITY, bottom: first.top };
+        assert_eq!(grid.query(right_of_first), vec![ids[1]]);
+    }
+
+    #[test]
+    fn moving_and_removing_obstacles_keeps_the_grid_in_step() {
+        let mut game_state = game_state();
+        let width = TILE_WIDTH as f32;
+        let ids = place_boxes(&mut game_state, &[(400.0, BOX_GROUND), (400.0 + width, BOX_GROUND), (400.0, BOX_GROUND - TILE_HEIGHT as f32)]);
+        let map = &mut game_state.all_maps[game_state.current_map_index];
+
+        // Within the same cells, then across cells both ways
+        map.move_obstacle(ids[1], 0.5, 0.0);
+        assert_indexed(map);
+        let from = bounds(&map.obstacles[ids[1]]);
+        map.move_obstacle(ids[1], 3.0 * width, 0.0);
+        assert_indexed(map);
+        assert!(!map.obstacles_near(from).contains(&ids[1]));
+        assert!(map.obstacles_near(bounds(&map.obstacles[ids[1]])).contains(&ids[1]));
+        map.move_obstacle(ids[2], 0.0, -3.5 * TILE_HEIGHT as f32);
+        assert_indexed(map);
+
+        let removed = bounds(&map.obstacles[ids[0]]);
+        map.remove_obstacle(ids[0]);
+        assert_indexed(map);
+        assert!(!map.obstacles_near(removed).contains(&ids[0]));
+        assert!(map.obstacle_grid.cells.values().all(|cell| !cell.is_empty()));
+    }
+
+    #[test]
+    fn linking_through_the_grid_matches_the_scan() {
+        let tiles = generate_boxes(500);
+        let (linked, scanned) = (extract_obstacles(&tiles), extract_obstacles_by_scan(&tiles));
+
+        assert_eq!(linked.len(), scanned.len());
+        for (a, b) in linked.iter().zip(scanned.iter()) {
+            assert_eq!(a.id, b.id);
+            assert_eq!((a.left_obstacle, a.right_obstacle, a.over_obstacle, a.under_obstacle), (b.left_obstacle, b.right_obstacle, b.over_obstacle, b.under_obstacle));
+            assert_eq!((a.is_leftmost_obstacle, a.is_rightmost_obstacle, a.is_top_obstacle, a.is_bottom_obstacle), (b.is_leftmost_obstacle, b.is_rightmost_obstacle, b.is_top_obstacle, b.is_bottom_obstacle));
+        }
+        // Generated columns stand next to each other and up to four boxes high, so there are links to compare
+        assert!(linked.iter().any(|obstacle| obstacle.over_obstacle.is_some()));
+        assert!(linked.iter().any(|obstacle| obstacle.right_obstacle.is_some()));
+    }
+}
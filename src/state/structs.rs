@@ -1,7 +1,22 @@
-use std::time::Instant;
-use minifb::Window;
-use crate::graphics::sprites::SpriteMaps;
+use std::fmt;
+
+use crate::graphics::backend::DisplayBackend;
+use crate::graphics::camera::Camera;
+use crate::graphics::parallax::ParallaxLayer;
+use crate::graphics::render_graphics::Scaler;
+use crate::graphics::display::DisplaySettings;
+use crate::graphics::font::BitmapFont;
+use crate::graphics::animation::Animator;
+use crate::graphics::atlas::SpriteAtlas;
+use crate::state::arena::ObstacleArena;
+use crate::state::enemy::Enemy;
+use crate::state::hazard::Hazard;
 use crate::state::player::Player;
+use crate::state::rng::Rng;
+use crate::input::action::Bindings;
+use crate::input::recording::InputMode;
+use crate::state::scene::Scene;
+use crate::state::spatial::ObstacleGrid;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Direction {
@@ -9,21 +24,34 @@ pub enum Direction {
     Left
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub struct ObstacleId(pub usize);
+/// Handle of an obstacle in an `ObstacleArena`, which keeps reaching the same obstacle while it
+/// is in play and reaches nothing once it is removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObstacleId {
+    pub index: usize, // Slot of the obstacle in the arena
+    pub generation: u32, // Times the slot had been emptied when the obstacle was put in it
+}
+
+impl fmt::Display for ObstacleId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.index, self.generation)
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct EnemyId(pub usize);
 
 #[derive(Clone, Copy)]
 pub struct Obstacle {
-    pub id: ObstacleId,
+    pub id: ObstacleId, // Assigned by the arena the obstacle is inserted into
     pub x_left: f32, // left x coordinate of the box (lower x value)
     pub x_right: f32, // right x coordinate of the box (higher x value)
     pub y_top: f32, // top y coordinate of the box (lower y value)
     pub y_bottom: f32, // bottom y coordinate of the box (higher y value)
     pub velocity_y: f32, // For gravity
+    pub velocity_x: f32, // Speed of a launched box sliding along the ground, negative to the left
     pub falling: bool,   // Whether it's falling
+    pub fall_start_y: f32, // `y_bottom` at which the current fall started, which decides how heavily the box lands
     pub active: bool,    // If false, box is removed
     pub durability: u8,  // Health of the box
     pub is_bottom_obstacle: bool, // Whether it's a bottom obstacle
@@ -36,36 +64,56 @@ pub struct Obstacle {
     pub under_obstacle: Option<ObstacleId> // Id of the obstacle below
 }
 
-pub struct Map<'a> {
+pub struct Map {
     pub id: usize, // Unique identifier for the map
-    pub width: usize, // Width of the map
-    pub height: usize, // Height of the map
-    pub obstacles: &'a mut Vec<Obstacle>, // Obstacles for the map
-    pub transition_x: Option<f32>, // X-coordinate for map transition
+    pub name: String, // Name of the level file the map was loaded from
+    pub width: usize, // Width of the map in pixels
+    pub height: usize, // Height of the map in pixels
+    pub obstacles: ObstacleArena, // Obstacles currently in play, moved and removed through the methods in `spatial` so they stay indexed and linked
+    pub obstacle_grid: ObstacleGrid, // Spatial index of `obstacles`, looked up through `Map::obstacles_near`
+    pub obstacle_layout: ObstacleArena, // Obstacles as placed by the level file, spawned on entering the map
+    pub enemies: Vec<Enemy>, // Enemies currently in play
+    pub enemy_layout: Vec<Enemy>, // Enemies as placed by the level file, spawned on entering the map
+    pub hazards: Vec<Hazard>, // Toxic traps currently in play
+    pub hazard_layout: Vec<Hazard>, // Toxic traps as placed by the level file, spawned on entering the map
+    pub transition_x: f32, // X-coordinate at which the player leaves for the next map
+    pub parallax: Vec<ParallaxLayer>, // Background layers drawn behind the map, back to front
 }
 
 pub struct GameState<'a> {
     pub player: Player, // Player object
-    pub sprites: SpriteMaps, // Sprite maps
+    pub sprites: SpriteAtlas, // Sprite sheets and animation clips
+    pub camera: Camera, // Part of the world shown on screen
     pub window_buffer: &'a mut Vec<u32>, // Window buffer
     pub window_width: usize, // Width of the window
     pub window_height: usize, // Height of the window
-    pub window: &'a mut Window, // Window object
+    pub display: &'a mut dyn DisplayBackend, // Window or headless display, also the source of key input
     pub scaled_buffer: &'a mut Vec<u32>, // Scaled buffer
-    pub game_over_index: usize, // Game over index
-    pub all_maps: Vec<Map<'a>>, // All maps
+    pub scaler: Scaler, // Maps the window buffer onto the scaled buffer
+    pub display_settings: DisplaySettings, // Fullscreen, window size and scaling, saved between runs
+    pub all_maps: Vec<Map>, // All maps
     pub current_map_index: usize, // Current map index
     pub footstep_index: usize, // Footstep index
     pub footstep_active: bool, // Footstep active
-    pub sounds: Vec<Vec<u8>>,  // Sounds
-    pub heart_sprite_frame_index: usize, // Index for the heart sprite animation frame
-    pub lighthouse_sprite_frame_index: usize, // Index for the lighthouse sprite animation frame
-    pub ground_sprite_frame_index: usize, // Index for the ground sprite animation frame
-    pub mountains_sprite_frame_index: usize, // Index for the mountains sprite animation frame
-    pub last_heart_sprite_frame_index_change: Instant, // Timestamp of the last heart sprite frame change
-    pub last_ground_sprite_frame_index_change: Instant, // Timestamp of the last ground sprite frame change
-    pub last_light_house_sprite_frame_index_change: Instant, // Timestamp of the last lighthouse sprite frame change
+    pub heart_animator: Animator, // Beating animation of the hearts still left
+    pub font: BitmapFont, // Font of the HUD and menus
+    pub score: u32, // Points for defeated enemies and destroyed boxes
+    pub play_ticks: u64, // Ticks played so far, shown by the timer and stopped once the game ends
+    pub show_debug: bool, // Indicates if the debug overlay toggled with F3 is shown
     pub obstacle_spawned: bool, // Indicates if an obstacle has been spawned
+    pub enemies_spawned: bool, // Indicates if the enemies of the current map have been spawned
+    pub hazards_spawned: bool, // Indicates if the toxic traps of the current map have been spawned
     pub designated_x: f32, // X-coordinate for the player to converge to
     pub damage_taken: bool, // Indicates if the player has taken damage
+    pub transition_frame: Option<usize>, // Frame of the running map transition effect, if any
+    pub tick: u64, // Number of simulation ticks run so far
+    pub render_alpha: f32, // Progress (0.0 - 1.0) from the previous to the current tick used to interpolate drawing
+    pub rng: Rng, // Source of all randomness in the simulation
+    pub input_mode: InputMode, // Whether keys are read live, recorded or replayed
+    pub bindings: Bindings, // Keys bound to each action
+    pub held_actions: u8, // Actions held during the latest tick
+    pub frame_actions: u8, // Actions read from the keyboard once for the ticks of the current frame
+    pub rebinding: Option<usize>, // Index of the action waiting for a key on the rebinding screen, if open
+    pub scene: Scene, // Title screen, game or menu currently shown
+    pub quit: bool, // Set from a menu to leave the event loop
 }
\ No newline at end of file
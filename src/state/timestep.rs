@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use crate::audio::mixer::Mixer;
+use crate::state::scheduler::Scheduler;
+use crate::state::structs::GameState;
+
+/// Accumulates real time and converts it into a whole number of fixed-length simulation ticks.
+///
+/// Time left over after the last tick is kept for the next frame and exposed through `alpha` so
+/// rendering can interpolate between the previous and the current tick.
+pub struct FixedTimestep {
+    tick_duration: Duration,
+    accumulator: Duration,
+    max_ticks_per_frame: u32, // Ticks run at most per frame before the remaining backlog is dropped
+}
+
+impl FixedTimestep {
+    /// # Parameters
+    /// - `tick_rate`: Simulation ticks per second.
+    /// - `max_ticks_per_frame`: Catch-up limit, which keeps a long stall from freezing the game while it replays every missed tick.
+    pub fn new(tick_rate: u32, max_ticks_per_frame: u32) -> Self {
+        FixedTimestep {
+            tick_duration: Duration::from_secs(1) / tick_rate.max(1),
+            accumulator: Duration::ZERO,
+            max_ticks_per_frame,
+        }
+    }
+
+    /// Adds the real time which passed since the previous frame.
+    ///
+    /// # Returns
+    /// The number of ticks to simulate this frame.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.tick_duration && ticks < self.max_ticks_per_frame {
+            self.accumulator -= self.tick_duration;
+            ticks += 1;
+        }
+
+        // Drop whatever could not be caught up on so the game slows down instead of spiralling
+        if ticks == self.max_ticks_per_frame && self.accumulator >= self.tick_duration {
+            println!("Simulation fell behind, skipping {:?}", self.accumulator);
+            self.accumulator = Duration::ZERO;
+        }
+
+        ticks
+    }
+
+    /// Progress (0.0 - 1.0) from the last simulated tick towards the next one.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick_duration.as_secs_f32()
+    }
+}
+
+/// Runs a single simulation tick.
+pub fn step(game_state: &mut GameState, scheduler: &Scheduler, mixer: &mut Mixer) {
+    // Remember where everything was so the renderer can interpolate towards the new positions
+    game_state.player.previous_x = game_state.player.x;
+    game_state.player.previous_y = game_state.player.y;
+    game_state.camera.previous_x = game_state.camera.x;
+    game_state.camera.previous_y = game_state.camera.y;
+    for enemy in game_state.all_maps[game_state.current_map_index].enemies.iter_mut() {
+        enemy.previous_x = enemy.x;
+        enemy.previous_y = enemy.y;
+    }
+
+    // Handle user input, then process game logic such as obstacle detection, physics, sounds etc.
+    scheduler.run(game_state, mixer);
+
+    // Recordings keep the position of every tick, so a replay can tell exactly when it stops matching
+    let previous = (game_state.player.previous_x, game_state.player.previous_y);
+    let position = (game_state.player.x, game_state.player.y);
+    if let Some(recorded) = game_state.input_mode.track_position(game_state.tick, previous, position) {
+        println!("Replay diverged on tick {}: player at {:?} instead of {:?}", game_state.tick, position, recorded);
+    }
+    game_state.tick += 1;
+}
+
+/// Linearly interpolates between the value of the previous tick and the value of the current tick.
+pub fn interpolate(previous: f32, current: f32, alpha: f32) -> f32 {
+    previous + (current - previous) * alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::FixedTimestep;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn runs_a_tick_for_every_tick_length_passed() {
+        let mut timestep = FixedTimestep::new(100, 5);
+
+        assert_eq!(timestep.advance(TICK * 3), 3);
+        assert_eq!(timestep.advance(Duration::ZERO), 0);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn carries_leftover_time_over_to_the_next_frame() {
+        let mut timestep = FixedTimestep::new(100, 5);
+
+        assert_eq!(timestep.advance(Duration::from_millis(25)), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4, "alpha {}", timestep.alpha());
+        assert_eq!(timestep.advance(Duration::from_millis(7)), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-4, "alpha {}", timestep.alpha());
+    }
+
+    #[test]
+    fn drops_the_backlog_past_the_catch_up_limit() {
+        let mut timestep = FixedTimestep::new(100, 5);
+
+        assert_eq!(timestep.advance(Duration::from_secs(2)), 5);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(TICK), 1);
+
+        // Exactly reaching the limit keeps the time short of another tick
+        assert_eq!(timestep.advance(TICK * 5 + Duration::from_millis(4)), 5);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-4, "alpha {}", timestep.alpha());
+    }
+}
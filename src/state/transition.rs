@@ -0,0 +1,72 @@
+use crate::audio::mixer::Mixer;
+use crate::state::constants::level::{PLAYER_START_X, TRANSITION_FRAMES};
+use crate::state::core_logic::CoreLogic;
+use crate::state::scene::Scene;
+use crate::state::structs::GameState;
+
+pub struct MapTransition;
+
+impl CoreLogic for MapTransition {
+    fn execute(&self, game_state: &mut GameState, _mixer: &mut Mixer) {
+        match game_state.transition_frame {
+            Some(frame) => Self::advance(game_state, frame),
+            None => {
+                // Start leaving the map once the player crosses its transition point
+                if game_state.player.x >= game_state.all_maps[game_state.current_map_index].transition_x {
+                    if game_state.current_map_index + 1 < game_state.all_maps.len() {
+                        game_state.transition_frame = Some(0);
+                    } else {
+                        game_state.scene = Scene::StageClear { ticks: 0, selected: 0 };
+                        game_state.player.vx = 0.0;
+                        println!("Stage clear!");
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl MapTransition {
+    fn advance(game_state: &mut GameState, frame: usize) {
+        // Swap maps while the screen is fully faded out
+        if frame == TRANSITION_FRAMES / 2 {
+            enter_map(game_state, game_state.current_map_index + 1);
+        }
+
+        game_state.transition_frame = if frame + 1 >= TRANSITION_FRAMES { None } else { Some(frame + 1) };
+    }
+}
+
+/// Moves the player to the start of the map at `index`, which is spawned afresh on the next tick.
+/// Also used to restart the current map.
+pub fn enter_map(game_state: &mut GameState, index: usize) {
+    // Clear the obstacles, enemies and traps of the map being left so that it is fresh if visited again
+    game_state.all_maps[game_state.current_map_index].clear_obstacles();
+    game_state.all_maps[game_state.current_map_index].enemies.clear();
+    game_state.all_maps[game_state.current_map_index].hazards.clear();
+    game_state.current_map_index = index;
+
+    // Reset per-map state
+    game_state.obstacle_spawned = false;
+    game_state.enemies_spawned = false;
+    game_state.hazards_spawned = false;
+    game_state.damage_taken = false;
+    game_state.designated_x = 0.0;
+    game_state.player.x = PLAYER_START_X;
+    game_state.player.previous_x = PLAYER_START_X;
+    game_state.player.vx = 0.0;
+    game_state.player.knockback = 0.0;
+    game_state.player.invincible = false;
+
+    let map = &game_state.all_maps[game_state.current_map_index];
+    let player = &game_state.player;
+    game_state.camera.snap(player.x + 11.0, player.y, player.direction, map.width as f32, map.height as f32);
+    println!("Entering map {} '{}'", map.id, map.name);
+}
+
+/// Returns the brightness (0.0 - 1.0) of the screen for the given frame of a map transition,
+/// fading out during the first half and back in during the second half.
+pub fn transition_brightness(frame: usize) -> f32 {
+    let half = (TRANSITION_FRAMES / 2) as f32;
+    ((frame as f32 - half).abs() / half).clamp(0.0, 1.0)
+}
@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use minifb::Key;
+
+use age_of_panda::audio::backend::NullBackend;
+use age_of_panda::audio::engine::load_sounds;
+use age_of_panda::audio::mixer::Mixer;
+use age_of_panda::graphics::atlas::SpriteAtlas;
+use age_of_panda::graphics::backend::{HeadlessBackend, KeyEvent};
+use age_of_panda::graphics::display::DisplaySettings;
+use age_of_panda::graphics::font::BitmapFont;
+use age_of_panda::input::action::Bindings;
+use age_of_panda::input::handler::initialize_input_logic_map;
+use age_of_panda::input::recording::{InputMode, Recording};
+use age_of_panda::level::loader::load_levels;
+use age_of_panda::state::constants::animation::SPRITE_MANIFEST;
+use age_of_panda::state::constants::graphics::{TICK_RATE, WINDOW_BUFFER_HEIGHT, WINDOW_BUFFER_WIDTH};
+use age_of_panda::state::constants::hud::FONT_FILE;
+use age_of_panda::state::constants::level::{LEVELS_DIRECTORY, PLAYER_START_X};
+use age_of_panda::state::core_logic::initialize_scheduler;
+use age_of_panda::state::event_loop::start_event_loop;
+use age_of_panda::state::game::new_game_state;
+use age_of_panda::state::scheduler::Scheduler;
+use age_of_panda::state::structs::GameState;
+use age_of_panda::state::timestep::step;
+
+/// What a headless run ended with.
+struct Run {
+    player_x: f32,
+    ticks: u64,
+    frames: Vec<Vec<u32>>, // Every 10th presented frame
+}
+
+/// Sets up the first map on a headless display which closes after `frames` frames and presses the
+/// keys of `script`, and hands it to `play`.
+///
+/// # Returns
+/// What `play` returned, and every 10th frame presented.
+fn with_session<R>(script: Vec<KeyEvent>, frames: u64, play: impl FnOnce(&mut GameState, &mut Scheduler, &mut Mixer) -> R) -> (R, Vec<Vec<u32>>) {
+    let mut mixer = Mixer::new(Box::new(NullBackend::default()), &load_sounds());
+    let mut scheduler = initialize_scheduler(initialize_input_logic_map()).unwrap();
+    let mut display = HeadlessBackend::new(script, frames).capture_frames(10);
+    let mut window_buffer = vec![0; WINDOW_BUFFER_WIDTH * WINDOW_BUFFER_HEIGHT];
+    let mut scaled_buffer = Vec::new();
+
+    let all_maps = load_levels(Path::new(LEVELS_DIRECTORY)).unwrap();
+    let sprites = SpriteAtlas::load(Path::new(SPRITE_MANIFEST)).unwrap();
+    let font = BitmapFont::load(Path::new(FONT_FILE)).unwrap();
+
+    let mut game_state = new_game_state(&mut display, &mut window_buffer, &mut scaled_buffer, DisplaySettings::default(), all_maps, sprites, font);
+    // Keys are scripted against the default bindings, not whatever `controls.cfg` holds
+    game_state.bindings = Bindings::default();
+    let result = play(&mut game_state, &mut scheduler, &mut mixer);
+
+    (result, display.frames().to_vec())
+}
+
+/// Plays the first map for `frames` frames with the keys of `script`, one tick per frame.
+fn run_headless(script: Vec<KeyEvent>, frames: u64) -> Run {
+    let ((player_x, ticks), frames) = with_session(script, frames, |game_state, scheduler, mixer| {
+        start_event_loop(game_state, scheduler, mixer, TICK_RATE);
+        (game_state.player.x, game_state.tick)
+    });
+
+    Run { player_x, ticks, frames }
+}
+
+#[test]
+fn holding_d_walks_right_and_scrolls_the_frames() {
+    let run = run_headless(vec![KeyEvent { frame: 0, key: Key::D, pressed: true }], 60);
+
+    assert!(run.player_x > PLAYER_START_X, "player stayed at x {}", run.player_x);
+    assert_eq!(run.frames.len(), 6);
+    assert_ne!(run.frames.first(), run.frames.last());
+}
+
+#[test]
+fn releasing_d_stops_the_player() {
+    let script = vec![
+        KeyEvent { frame: 0, key: Key::D, pressed: true },
+        KeyEvent { frame: 30, key: Key::D, pressed: false },
+    ];
+    let stopped = run_headless(script.clone(), 90).player_x;
+    let later = run_headless(script, 120).player_x;
+
+    assert!(stopped > PLAYER_START_X);
+    assert_eq!(stopped, later);
+}
+
+#[test]
+fn runs_without_input_are_identical() {
+    let first = run_headless(Vec::new(), 60);
+    let second = run_headless(Vec::new(), 60);
+
+    assert_eq!(first.player_x, PLAYER_START_X);
+    assert_eq!(first.frames, second.frames);
+}
+
+#[test]
+fn pausing_stops_the_simulation_but_not_the_animations() {
+    let script = vec![
+        KeyEvent { frame: 0, key: Key::D, pressed: true },
+        KeyEvent { frame: 15, key: Key::P, pressed: true },
+    ];
+    let paused = run_headless(script, 60);
+    let unpaused = run_headless(vec![KeyEvent { frame: 0, key: Key::D, pressed: true }], 15);
+
+    assert_eq!(paused.ticks, 15);
+    assert_eq!(paused.player_x, unpaused.player_x);
+    // The hearts and the toxic trap keep moving behind the pause menu
+    assert_ne!(paused.frames[2], paused.frames[4]);
+}
+
+#[test]
+fn replays_follow_the_recording_on_every_tick() {
+    // Walk right, jump onto the first box and kick the next one
+    let script = vec![
+        KeyEvent { frame: 0, key: Key::D, pressed: true },
+        KeyEvent { frame: 40, key: Key::Space, pressed: true },
+        KeyEvent { frame: 50, key: Key::Space, pressed: false },
+        KeyEvent { frame: 100, key: Key::X, pressed: true },
+        KeyEvent { frame: 130, key: Key::D, pressed: false },
+    ];
+    let (recording, _) = with_session(script, 180, |game_state, scheduler, mixer| {
+        game_state.input_mode = InputMode::Record(Recording::new(7, game_state.all_maps[0].id));
+        start_event_loop(game_state, scheduler, mixer, TICK_RATE);
+        match std::mem::replace(&mut game_state.input_mode, InputMode::Live) {
+            InputMode::Record(recording) => recording,
+            _ => unreachable!(),
+        }
+    });
+
+    assert_eq!(recording.positions.len(), 180);
+    assert!(recording.positions.iter().any(|&(_, y)| y != recording.positions[0].1), "the player never left the ground");
+
+    with_session(Vec::new(), u64::MAX, |game_state, scheduler, mixer| {
+        game_state.input_mode = InputMode::Replay(recording.clone());
+        for (tick, &recorded) in recording.positions.iter().enumerate() {
+            step(game_state, scheduler, mixer);
+            assert_eq!((game_state.player.x, game_state.player.y), recorded, "replay diverged on tick {}", tick);
+        }
+    });
+}